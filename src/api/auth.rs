@@ -0,0 +1,81 @@
+use super::error::{ApiError, Result};
+use super::State;
+use crate::{config::KufefeConfig, context::Context, error::Error};
+use hyper::{header::AUTHORIZATION, HeaderMap};
+use k8s_openapi::api::authentication::v1::{TokenReview, TokenReviewSpec};
+use kube::{api::PostParams, Api};
+
+/// Someone who authenticated with the self-service API
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caller {
+    pub username: String,
+    pub groups: Vec<String>,
+}
+
+/// Authenticates the bearer token of a call and checks the caller may use the API.
+/// ID tokens of the OIDC issuer are verified locally, anything else through a TokenReview.
+pub async fn authenticate(state: &State, headers: &HeaderMap) -> Result<Caller> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| ApiError::unauthorized("missing bearer token"))?;
+
+    let caller = match &state.oidc {
+        Some(oidc) if oidc.issued(token) => oidc.authenticate(token).await?,
+        _ => token_review(&state.ctx, token).await?,
+    };
+
+    authorize(&state.ctx.settings(), caller)
+}
+
+/// Asks the API server who a token belongs to
+pub async fn token_review(ctx: &Context, token: &str) -> Result<Caller> {
+    let review = TokenReview {
+        spec: TokenReviewSpec {
+            token: Some(token.to_string()),
+            ..TokenReviewSpec::default()
+        },
+        ..TokenReview::default()
+    };
+
+    let review = Api::<TokenReview>::all(ctx.hub())
+        .create(&PostParams::default(), &review)
+        .await
+        .map_err(Error::api("create", "TokenReview", ""))?;
+
+    match review.status {
+        Some(status) if status.authenticated == Some(true) => {
+            let user = status.user.unwrap_or_default();
+
+            Ok(Caller {
+                username: user.username.unwrap_or_default(),
+                groups: user.groups.unwrap_or_default(),
+            })
+        }
+        Some(status) => Err(ApiError::unauthorized(
+            status.error.unwrap_or_else(|| "invalid token".to_string()),
+        )),
+        None => Err(ApiError::unauthorized("invalid token")),
+    }
+}
+
+/// Checks the caller is a member of one of the allowed groups, denying everyone if
+/// none are configured
+pub fn authorize(settings: &KufefeConfig, caller: Caller) -> Result<Caller> {
+    if caller.username.is_empty() {
+        return Err(ApiError::unauthorized("token has no username"));
+    }
+
+    let allowed = settings.api_allowed_groups().unwrap_or_default();
+
+    if !caller.groups.iter().any(|g| allowed.contains(g)) {
+        return Err(ApiError::Forbidden {
+            reason: format!("{} is not in any of the allowed groups", caller.username),
+        });
+    }
+
+    Ok(caller)
+}
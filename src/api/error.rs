@@ -0,0 +1,114 @@
+use crate::error::Error;
+use hyper::{header::CONTENT_TYPE, Body, Response, StatusCode};
+use serde_json::json;
+
+/// Errors returned to callers of the self-service API
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("Unauthorized: {reason}")]
+    Unauthorized { reason: String },
+
+    #[error("Forbidden: {reason}")]
+    Forbidden { reason: String },
+
+    #[error("Bad request: {reason}")]
+    BadRequest { reason: String },
+
+    #[error("Not found")]
+    NotFound,
+
+    #[error("Request {name} failed: {reason}")]
+    Failed { name: String, reason: String },
+
+    #[error(
+        "Request {name} is not ready yet, fetch it later from /api/v1/requests/{name}"
+    )]
+    Timeout { name: String },
+
+    #[error(
+        "Request {name} is waiting for approval, fetch it later from /api/v1/requests/{name}"
+    )]
+    PendingApproval { name: String },
+
+    #[error("OIDC provider failed: {reason}")]
+    Provider { reason: String },
+
+    #[error(transparent)]
+    Kufefe(#[from] Error),
+}
+
+pub type Result<T, E = ApiError> = std::result::Result<T, E>;
+
+impl ApiError {
+    /// Wraps a failure of the caller to authenticate
+    pub fn unauthorized(reason: impl ToString) -> Self {
+        Self::Unauthorized {
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Wraps a failure to talk to the OIDC provider
+    pub fn provider(reason: impl ToString) -> Self {
+        Self::Provider {
+            reason: reason.to_string(),
+        }
+    }
+
+    /// HTTP status code the error is reported with
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            Self::Forbidden { .. } => StatusCode::FORBIDDEN,
+            Self::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Failed { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            Self::PendingApproval { .. } => StatusCode::ACCEPTED,
+            Self::Provider { .. } => StatusCode::BAD_GATEWAY,
+            Self::Kufefe(e) => match e {
+                Error::RoleNotFound { .. }
+                | Error::RoleNotAllowed { .. }
+                | Error::RoleNotListed { .. }
+                | Error::DurationNotAllowed { .. } => StatusCode::FORBIDDEN,
+                Error::ClusterNotFound { .. } => StatusCode::NOT_FOUND,
+                Error::Unsupported { .. } => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+        }
+    }
+
+    /// Machine readable reason, matching the condition reasons of Requests
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Unauthorized { .. } => "Unauthorized",
+            Self::Forbidden { .. } => "Forbidden",
+            Self::BadRequest { .. } => "BadRequest",
+            Self::NotFound => "NotFound",
+            Self::Failed { .. } => "Failed",
+            Self::Timeout { .. } => "Timeout",
+            Self::PendingApproval { .. } => "ApprovalRequired",
+            Self::Provider { .. } => "ProviderError",
+            Self::Kufefe(e) => e.reason(),
+        }
+    }
+
+    /// Renders the error as a JSON response, hiding the details of internal errors
+    pub fn response(&self) -> Response<Body> {
+        let status = self.status();
+        let message = match status {
+            StatusCode::INTERNAL_SERVER_ERROR => {
+                tracing::error!("Self-service API failed: {}", self);
+                "Internal error".to_string()
+            }
+            _ => self.to_string(),
+        };
+
+        let body = json!({ "reason": self.reason(), "message": message });
+
+        Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+}
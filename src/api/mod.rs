@@ -0,0 +1,183 @@
+use crate::{context::Context, shutdown};
+use error::ApiError;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{header::CONTENT_TYPE, Body, Method, Response, StatusCode};
+use oidc::Oidc;
+use openssl::ssl::{Ssl, SslAcceptor, SslFiletype, SslMethod};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::select;
+use tokio_openssl::SslStream;
+
+pub mod auth;
+pub mod error;
+pub mod oidc;
+pub mod requests;
+pub mod ui;
+
+/// What every call to the self-service API has access to
+pub struct State {
+    pub ctx: Context,
+    pub oidc: Option<Oidc>,
+}
+
+/// Serves the self-service API over HTTPS until shutdown, if it is enabled
+pub async fn serve(ctx: Context) {
+    let settings = ctx.settings();
+    let (Some(port), Some((cert, key))) = (settings.api_port(), settings.api_tls())
+    else {
+        return;
+    };
+
+    let acceptor = match acceptor(&cert, &key) {
+        Ok(acceptor) => Arc::new(acceptor),
+        Err(e) => {
+            tracing::error!("Failed to load the self-service API certificate: {}", e);
+            return;
+        }
+    };
+
+    let oidc = match settings.oidc() {
+        Some((issuer, client_id)) => match Oidc::new(issuer, client_id) {
+            Ok(oidc) => match settings.oidc_redirect_url() {
+                Some(url) => Some(oidc.with_login(url, settings.oidc_client_secret())),
+                None => Some(oidc),
+            },
+            Err(e) => {
+                tracing::error!("Failed to set up OIDC authentication: {}", e);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("Failed to bind self-service API to {}: {}", addr, e);
+            return;
+        }
+    };
+
+    tracing::info!("Serving the self-service API on {}", addr);
+    let state = Arc::new(State { ctx, oidc });
+
+    loop {
+        let stream = select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            },
+            _ = shutdown::triggered() => return,
+        };
+
+        let acceptor = acceptor.clone();
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            let stream =
+                Ssl::new(acceptor.context()).and_then(|ssl| SslStream::new(ssl, stream));
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => return tracing::warn!("Failed to set up TLS: {}", e),
+            };
+
+            if let Err(e) = Pin::new(&mut stream).accept().await {
+                return tracing::debug!("TLS handshake failed: {}", e);
+            }
+
+            let service = service_fn(move |request| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(&state, request).await) }
+            });
+
+            if let Err(e) = Http::new().serve_connection(stream, service).await {
+                tracing::debug!("Self-service API connection failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Routes a call to its handler
+pub async fn handle(state: &State, request: hyper::Request<Body>) -> Response<Body> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let segments = path.split('/').collect::<Vec<_>>();
+
+    // The web UI is only served when users can log in to it
+    if state.oidc.as_ref().is_some_and(|o| o.login_enabled()) {
+        match (&method, segments.as_slice()) {
+            (&Method::GET, ["", ""]) => return ui::home(state, request).await,
+            (&Method::GET, ["", "login"]) => return ui::login(state).await,
+            (&Method::GET, ["", "callback"]) => {
+                return ui::callback(state, request).await
+            }
+            (&Method::GET, ["", "logout"]) => return ui::logout(),
+            (&Method::POST, ["", "requests"]) => return ui::create(state, request).await,
+            (&Method::GET, ["", "requests", name, "kubeconfig"]) => {
+                return ui::download(state, request, name).await
+            }
+            (&Method::POST, ["", "requests", name, "revoke"]) => {
+                return ui::revoke(state, request, name).await
+            }
+            _ => {}
+        }
+    }
+
+    let result = match (method, segments.as_slice()) {
+        (Method::GET, ["", "healthz"]) => Ok(Response::new(Body::from("ok"))),
+        (Method::POST, ["", "api", "v1", "requests"]) => {
+            requests::create(state, request).await
+        }
+        (Method::GET, ["", "api", "v1", "requests"]) => {
+            requests::index(state, request).await
+        }
+        (Method::GET, ["", "api", "v1", "requests", name]) => {
+            requests::get(state, request, name).await
+        }
+        (Method::DELETE, ["", "api", "v1", "requests", name]) => {
+            requests::delete(state, request, name).await
+        }
+        (Method::POST, ["", "api", "v1", "requests", name, "token"]) => {
+            requests::token(state, request, name).await
+        }
+        (Method::POST, ["", "api", "v1", "requests", name, "approve"]) => {
+            requests::approve(state, request, name).await
+        }
+        _ => Err(ApiError::NotFound),
+    };
+
+    result.unwrap_or_else(|e| e.response())
+}
+
+/// Renders a JSON response
+fn json<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(body).unwrap_or_default()))
+        .unwrap()
+}
+
+/// Builds the TLS acceptor from a PEM certificate chain and key
+fn acceptor(
+    cert: &Path,
+    key: &Path,
+) -> std::result::Result<SslAcceptor, openssl::error::ErrorStack> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+    builder.set_private_key_file(key, SslFiletype::PEM)?;
+    builder.set_certificate_chain_file(cert)?;
+    builder.check_private_key()?;
+
+    Ok(builder.build())
+}
@@ -0,0 +1,378 @@
+use super::auth::Caller;
+use super::error::{ApiError, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hyper::client::HttpConnector;
+use hyper::{body, header::CONTENT_TYPE, Body, Client, StatusCode, Uri};
+use hyper_openssl::HttpsConnector;
+use openssl::{bn::BigNum, hash::MessageDigest, pkey::PKey, rsa::Rsa, sign::Verifier};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// How long signing keys are cached before they are fetched again
+const KEYS_TTL: Duration = Duration::from_secs(3600);
+
+/// Signing keys published by the provider
+#[derive(Deserialize, Clone, Default)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// An RSA signing key
+#[derive(Deserialize, Clone)]
+pub struct Jwk {
+    pub kid: Option<String>,
+    pub kty: String,
+    pub n: Option<String>,
+    pub e: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Claims of an ID token that Kufefe looks at
+#[derive(Deserialize)]
+struct Claims {
+    iss: String,
+    aud: Audience,
+    exp: i64,
+    nbf: Option<i64>,
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+/// Endpoints of the provider, as published in its discovery document
+#[derive(Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+    authorization_endpoint: Option<String>,
+    token_endpoint: Option<String>,
+}
+
+/// Response of the token endpoint
+#[derive(Deserialize)]
+struct Tokens {
+    id_token: String,
+}
+
+/// How the web UI logs in through the provider
+struct Login {
+    redirect_url: String,
+    client_secret: Option<String>,
+}
+
+/// Verifies ID tokens issued by an OIDC provider
+pub struct Oidc {
+    issuer: String,
+    client_id: String,
+    client: Client<HttpsConnector<HttpConnector>>,
+    keys: RwLock<Option<(Instant, Jwks)>>,
+    login: Option<Login>,
+}
+
+impl Oidc {
+    /// Sets up verification of ID tokens from the issuer for the client
+    pub fn new(issuer: String, client_id: String) -> Result<Self> {
+        let connector = HttpsConnector::new().map_err(ApiError::provider)?;
+
+        Ok(Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id,
+            client: Client::builder().build(connector),
+            keys: RwLock::new(None),
+            login: None,
+        })
+    }
+
+    /// Lets users log in through the provider, which the web UI needs
+    pub fn with_login(
+        mut self,
+        redirect_url: String,
+        client_secret: Option<String>,
+    ) -> Self {
+        self.login = Some(Login {
+            redirect_url,
+            client_secret,
+        });
+        self
+    }
+
+    /// Checks if users can log in through the provider
+    pub fn login_enabled(&self) -> bool {
+        self.login.is_some()
+    }
+
+    /// Builds the URL users are sent to for logging in, using PKCE
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        challenge: &str,
+    ) -> Result<String> {
+        let login = self.login()?;
+        let endpoint = self
+            .discovery()
+            .await?
+            .authorization_endpoint
+            .ok_or_else(|| ApiError::provider("no authorization endpoint published"))?;
+
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &login.redirect_url)
+            .append_pair("scope", "openid email profile")
+            .append_pair("state", state)
+            .append_pair("code_challenge", challenge)
+            .append_pair("code_challenge_method", "S256")
+            .finish();
+
+        let separator = if endpoint.contains('?') { '&' } else { '?' };
+        Ok(format!("{}{}{}", endpoint, separator, query))
+    }
+
+    /// Exchanges the code the provider redirected back with for an ID token
+    pub async fn exchange(&self, code: &str, verifier: &str) -> Result<String> {
+        let login = self.login()?;
+        let endpoint = self
+            .discovery()
+            .await?
+            .token_endpoint
+            .ok_or_else(|| ApiError::provider("no token endpoint published"))?;
+
+        // The serializer isn't Send, so it must not live across an await
+        let form = {
+            let mut form = form_urlencoded::Serializer::new(String::new());
+            form.append_pair("grant_type", "authorization_code")
+                .append_pair("code", code)
+                .append_pair("redirect_uri", &login.redirect_url)
+                .append_pair("client_id", &self.client_id)
+                .append_pair("code_verifier", verifier);
+            if let Some(secret) = &login.client_secret {
+                form.append_pair("client_secret", secret);
+            }
+            form.finish()
+        };
+
+        let request = hyper::Request::post(&endpoint)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .map_err(ApiError::provider)?;
+
+        let tokens: Tokens = self.send(request).await?;
+
+        Ok(tokens.id_token)
+    }
+
+    /// Checks if a token claims to come from this issuer, without verifying it
+    pub fn issued(&self, token: &str) -> bool {
+        #[derive(Deserialize)]
+        struct Issuer {
+            iss: String,
+        }
+
+        token
+            .split('.')
+            .nth(1)
+            .and_then(|payload| decode::<Issuer>(payload).ok())
+            .is_some_and(|claims| claims.iss.trim_end_matches('/') == self.issuer)
+    }
+
+    /// Verifies an ID token and returns who it was issued to
+    pub async fn authenticate(&self, token: &str) -> Result<Caller> {
+        let now = chrono::Utc::now().timestamp();
+        let mut keys = self.keys(false).await?;
+
+        // Keys the provider rotated in since they were cached are fetched once more
+        let kid = token
+            .split('.')
+            .next()
+            .and_then(|header| decode::<Header>(header).ok())
+            .and_then(|header| header.kid);
+        if kid.is_some() && !keys.keys.iter().any(|k| k.kid == kid) {
+            keys = self.keys(true).await?;
+        }
+
+        verify(token, &keys, &self.issuer, &self.client_id, now)
+    }
+
+    /// Gets the signing keys of the provider, from the cache unless it is stale
+    async fn keys(&self, refresh: bool) -> Result<Jwks> {
+        if let Some((fetched, keys)) = self.keys.read().await.as_ref() {
+            if !refresh && fetched.elapsed() < KEYS_TTL {
+                return Ok(keys.clone());
+            }
+        }
+
+        let keys: Jwks = self.get(&self.discovery().await?.jwks_uri).await?;
+
+        *self.keys.write().await = Some((Instant::now(), keys.clone()));
+
+        Ok(keys)
+    }
+
+    /// Gets the settings for logging in, if enabled
+    fn login(&self) -> Result<&Login> {
+        self.login
+            .as_ref()
+            .ok_or_else(|| ApiError::provider("logging in is not enabled"))
+    }
+
+    /// Fetches the discovery document of the provider
+    async fn discovery(&self) -> Result<Discovery> {
+        self.get(&format!("{}/.well-known/openid-configuration", self.issuer))
+            .await
+    }
+
+    /// Fetches a JSON document from the provider
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let uri: Uri = url.parse().map_err(ApiError::provider)?;
+        let request = hyper::Request::get(uri)
+            .body(Body::empty())
+            .map_err(ApiError::provider)?;
+
+        self.send(request).await
+    }
+
+    /// Sends a request to the provider and parses the JSON response
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: hyper::Request<Body>,
+    ) -> Result<T> {
+        let target = format!("{} {}", request.method(), request.uri());
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(ApiError::provider)?;
+
+        if response.status() != StatusCode::OK {
+            return Err(ApiError::provider(format!(
+                "{} returned {}",
+                target,
+                response.status()
+            )));
+        }
+
+        let bytes = body::to_bytes(response.into_body())
+            .await
+            .map_err(ApiError::provider)?;
+
+        serde_json::from_slice(&bytes).map_err(ApiError::provider)
+    }
+}
+
+/// Verifies the RS256 signature and the claims of an ID token
+pub fn verify(
+    token: &str,
+    keys: &Jwks,
+    issuer: &str,
+    client_id: &str,
+    now: i64,
+) -> Result<Caller> {
+    let parts: Vec<&str> = token.split('.').collect();
+    let [header, payload, signature] = parts[..] else {
+        return Err(ApiError::unauthorized("malformed ID token"));
+    };
+
+    let header: Header = decode(header)?;
+    if header.alg != "RS256" {
+        return Err(ApiError::unauthorized(format!(
+            "unsupported signing algorithm {}",
+            header.alg
+        )));
+    }
+
+    let key = keys
+        .keys
+        .iter()
+        .filter(|k| k.kty == "RSA")
+        .find(|k| header.kid.is_none() || k.kid == header.kid)
+        .ok_or_else(|| {
+            ApiError::unauthorized("ID token was signed with an unknown key")
+        })?;
+
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(ApiError::unauthorized)?;
+    let signed = format!("{}.{}", parts[0], parts[1]);
+    if !verify_signature(key, signed.as_bytes(), &signature)? {
+        return Err(ApiError::unauthorized("invalid ID token signature"));
+    }
+
+    let claims: Claims = decode(payload)?;
+    let audience = match &claims.aud {
+        Audience::One(aud) => aud == client_id,
+        Audience::Many(aud) => aud.iter().any(|a| a == client_id),
+    };
+
+    if claims.iss.trim_end_matches('/') != issuer.trim_end_matches('/') {
+        return Err(ApiError::unauthorized("ID token has another issuer"));
+    }
+
+    if !audience {
+        return Err(ApiError::unauthorized(
+            "ID token was issued for another client",
+        ));
+    }
+
+    if claims.exp <= now || claims.nbf.is_some_and(|nbf| nbf > now) {
+        return Err(ApiError::unauthorized(
+            "ID token is expired or not yet valid",
+        ));
+    }
+
+    // Only a verified email names the caller, anyone may claim an unverified one
+    let username = match claims.email {
+        Some(email) if claims.email_verified => email,
+        _ => format!("oidc:{}#{}", claims.iss, claims.sub),
+    };
+
+    Ok(Caller {
+        username,
+        groups: claims.groups,
+    })
+}
+
+/// Checks a signature against an RSA key
+fn verify_signature(key: &Jwk, data: &[u8], signature: &[u8]) -> Result<bool> {
+    let component = |value: &Option<String>| {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(value.as_deref().unwrap_or_default())
+            .map_err(ApiError::provider)?;
+
+        BigNum::from_slice(&bytes).map_err(ApiError::provider)
+    };
+
+    let rsa = Rsa::from_public_components(component(&key.n)?, component(&key.e)?)
+        .map_err(ApiError::provider)?;
+    let key = PKey::from_rsa(rsa).map_err(ApiError::provider)?;
+
+    let mut verifier =
+        Verifier::new(MessageDigest::sha256(), &key).map_err(ApiError::provider)?;
+    verifier.update(data).map_err(ApiError::provider)?;
+
+    // A malformed signature is a mismatch rather than a failure of the provider
+    Ok(verifier.verify(signature).unwrap_or(false))
+}
+
+/// Decodes a base64url encoded JSON part of a token
+fn decode<T: DeserializeOwned>(part: &str) -> Result<T> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(ApiError::unauthorized)?;
+
+    serde_json::from_slice(&bytes).map_err(ApiError::unauthorized)
+}
@@ -0,0 +1,506 @@
+use super::auth::{self, Caller};
+use super::error::{ApiError, Result};
+use super::{json, State};
+use crate::crd::{
+    CredentialMode, OutputFormat, Request, RequestSpec, NAMESPACE_ANNOTATION,
+};
+use crate::kubeconfig::file;
+use crate::{clusters, context::Context, error::Error, resources::role::Role, watcher};
+use hyper::body::{Bytes, HttpBody};
+use hyper::{Body, Response, StatusCode};
+use k8s_openapi::api::authentication::v1::{TokenRequest, TokenRequestSpec};
+use k8s_openapi::api::core::v1::ServiceAccount;
+use kube::api::{DeleteParams, ListParams, PostParams};
+use kube::runtime::wait::await_condition;
+use kube::{Api, ResourceExt};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Annotation recording who asked for a Request through the API
+pub const REQUESTED_BY_ANNOTATION: &str = "kufefe.io/requested-by";
+
+/// How long a call waits for the Request to become ready
+const READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest request body accepted
+const MAX_BODY: u64 = 64 * 1024;
+
+/// Lifetime of minted tokens, unless the caller asks for another one
+const TOKEN_TTL_SECONDS: i64 = 600;
+
+/// The API server refuses to issue tokens shorter than this
+const MIN_TOKEN_TTL_SECONDS: i64 = 600;
+
+/// What a caller asks for
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Issue {
+    pub role: String,
+    pub duration_minutes: Option<u64>,
+    pub output_format: Option<OutputFormat>,
+    pub credential_mode: Option<CredentialMode>,
+    pub cluster: Option<String>,
+    pub namespace: Option<String>,
+}
+
+/// What a caller gets back once the Request is ready
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Issued {
+    pub name: String,
+    pub expires_at: Option<i64>,
+    pub kubeconfig: String,
+    pub output: Option<String>,
+}
+
+/// A short-lived token for a Request of the exec credential mode
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Token {
+    pub token: String,
+    pub expires_at: i64,
+}
+
+/// A Request of the caller, as listed
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub name: String,
+    pub role: String,
+    pub cluster: Option<String>,
+    pub namespace: Option<String>,
+    pub ready: bool,
+    pub failed: bool,
+    pub message: String,
+    pub expires_at: Option<i64>,
+    pub approved_by: Option<String>,
+}
+
+impl From<&Request> for Summary {
+    fn from(request: &Request) -> Self {
+        let status = request.status.clone().unwrap_or_default();
+
+        Self {
+            name: request.name_any(),
+            role: request.spec.role.clone(),
+            cluster: request.spec.cluster.clone(),
+            namespace: request.annotations().get(NAMESPACE_ANNOTATION).cloned(),
+            ready: status.ready,
+            failed: status.failed,
+            message: status.message,
+            expires_at: status.expires_at,
+            approved_by: status.approved_by,
+        }
+    }
+}
+
+/// Handles POST /api/v1/requests
+pub async fn create(
+    state: &State,
+    request: hyper::Request<Body>,
+) -> Result<Response<Body>> {
+    let caller = auth::authenticate(state, request.headers()).await?;
+
+    let bytes = read(request.into_body()).await?;
+    let params: Issue =
+        serde_json::from_slice(&bytes).map_err(|e| ApiError::BadRequest {
+            reason: e.to_string(),
+        })?;
+
+    let issued = issue(&state.ctx, &caller, params).await?;
+
+    Ok(json(StatusCode::CREATED, &issued))
+}
+
+/// Reads the body of a call, refusing bodies larger than MAX_BODY
+pub async fn read(body: Body) -> Result<Bytes> {
+    if body.size_hint().upper().unwrap_or(u64::MAX) > MAX_BODY {
+        return Err(ApiError::BadRequest {
+            reason: "body is too large".to_string(),
+        });
+    }
+
+    hyper::body::to_bytes(body)
+        .await
+        .map_err(|e| ApiError::BadRequest {
+            reason: e.to_string(),
+        })
+}
+
+/// Handles GET /api/v1/requests, listing the Requests of the caller
+pub async fn index(
+    state: &State,
+    request: hyper::Request<Body>,
+) -> Result<Response<Body>> {
+    let caller = auth::authenticate(state, request.headers()).await?;
+    let summaries: Vec<Summary> = list(&state.ctx, &caller)
+        .await?
+        .iter()
+        .map(Summary::from)
+        .collect();
+
+    Ok(json(StatusCode::OK, &summaries))
+}
+
+/// Handles DELETE /api/v1/requests/{name}, revoking a Request of the caller
+pub async fn delete(
+    state: &State,
+    request: hyper::Request<Body>,
+    name: &str,
+) -> Result<Response<Body>> {
+    let caller = auth::authenticate(state, request.headers()).await?;
+    revoke(&state.ctx, &caller, name).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// Handles GET /api/v1/requests/{name}, for Requests the caller asked for
+pub async fn get(
+    state: &State,
+    request: hyper::Request<Body>,
+    name: &str,
+) -> Result<Response<Body>> {
+    let caller = auth::authenticate(state, request.headers()).await?;
+
+    let resource = find(&state.ctx, &caller, name).await?;
+
+    Ok(json(StatusCode::OK, &issued(resource)?))
+}
+
+/// Handles POST /api/v1/requests/{name}/token, minting a token for a Request of the
+/// caller that uses the exec credential mode
+pub async fn token(
+    state: &State,
+    request: hyper::Request<Body>,
+    name: &str,
+) -> Result<Response<Body>> {
+    let caller = auth::authenticate(state, request.headers()).await?;
+
+    let ttl_seconds =
+        form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes())
+            .find(|(key, _)| key == "ttlSeconds")
+            .map(|(_, value)| {
+                value.parse::<i64>().map_err(|e| ApiError::BadRequest {
+                    reason: format!("ttlSeconds: {}", e),
+                })
+            })
+            .transpose()?
+            .unwrap_or(TOKEN_TTL_SECONDS);
+
+    let token = mint(&state.ctx, &caller, name, ttl_seconds).await?;
+
+    Ok(json(StatusCode::CREATED, &token))
+}
+
+/// Handles POST /api/v1/requests/{name}/approve, for members of the approver groups
+pub async fn approve(
+    state: &State,
+    request: hyper::Request<Body>,
+    name: &str,
+) -> Result<Response<Body>> {
+    let caller = auth::authenticate(state, request.headers()).await?;
+    let approved = grant(&state.ctx, &caller, name).await?;
+
+    Ok(json(StatusCode::OK, &Summary::from(&approved)))
+}
+
+/// Creates a Request for the caller under the same policy checks as the controller,
+/// and waits until it is ready, or until it waits for approval
+pub async fn issue(ctx: &Context, caller: &Caller, params: Issue) -> Result<Issued> {
+    if params.duration_minutes == Some(0) {
+        return Err(ApiError::BadRequest {
+            reason: "durationMinutes must be greater than 0".to_string(),
+        });
+    }
+
+    let mut request = Request::new(
+        "",
+        RequestSpec {
+            role: params.role,
+            output_format: params.output_format,
+            credential_mode: params.credential_mode,
+            cluster: params.cluster,
+            duration_minutes: params.duration_minutes,
+        },
+    );
+    request.metadata.name = None;
+    request.metadata.generate_name = Some(Request::name_prefix(&request.spec.role));
+    request
+        .annotations_mut()
+        .insert(REQUESTED_BY_ANNOTATION.to_string(), caller.username.clone());
+
+    if let Some(namespace) = params.namespace.filter(|ns| !ns.is_empty()) {
+        request
+            .annotations_mut()
+            .insert(NAMESPACE_ANNOTATION.to_string(), namespace);
+    }
+
+    check(ctx, &request).await?;
+
+    let api = Request::api(ctx);
+    let created = api
+        .create(&PostParams::default(), &request)
+        .await
+        .map_err(Error::api("create", "Request", &request.spec.role))?;
+    let name = created.name_any();
+
+    tracing::info!(
+        "Created Request {} for role {} on behalf of {}",
+        name,
+        created.spec.role,
+        caller.username
+    );
+
+    let done = |r: Option<&Request>| {
+        r.is_some_and(|r| r.awaiting_approval())
+            || r.and_then(|r| r.status.as_ref())
+                .is_some_and(|s| s.ready || s.failed)
+    };
+
+    match tokio::time::timeout(READY_TIMEOUT, await_condition(api, &name, done)).await {
+        Ok(Ok(Some(resource))) => issued(resource),
+        Ok(Ok(None)) => Err(ApiError::Failed {
+            name,
+            reason: "the Request was deleted".to_string(),
+        }),
+        Ok(Err(e)) => Err(ApiError::Failed {
+            name,
+            reason: e.to_string(),
+        }),
+        Err(_) => Err(ApiError::Timeout { name }),
+    }
+}
+
+/// Checks the role, duration and credential mode of a Request before it is created,
+/// so callers get an answer right away. The controller checks them again when
+/// provisioning.
+pub async fn check(ctx: &Context, request: &Request) -> Result<()> {
+    let settings = ctx.settings();
+    watcher::check_credential_mode(ctx, request)?;
+
+    if let Some(minutes) = request.spec.duration_minutes {
+        let max = settings.duration_limit();
+
+        if minutes > max.as_secs() / 60 {
+            return Err(Error::DurationNotAllowed {
+                minutes,
+                max: max.as_secs() / 60,
+            }
+            .into());
+        }
+    }
+
+    // Roles are looked up in the cluster the kubeconfig is issued for
+    let target = clusters::context(ctx, request).await?;
+    Role::new(&target).get(&request.spec.role).await?;
+
+    Ok(())
+}
+
+/// Lists the Requests the caller asked for
+pub async fn list(ctx: &Context, caller: &Caller) -> Result<Vec<Request>> {
+    let requests = Request::api(ctx)
+        .list(&ListParams::default())
+        .await
+        .map_err(Error::api("list", "Request", ""))?;
+
+    Ok(requests
+        .into_iter()
+        .filter(|r| owned_by(r, caller))
+        .collect())
+}
+
+/// Gets a Request the caller asked for. Requests of others are reported as
+/// missing, so their names don't leak.
+pub async fn find(ctx: &Context, caller: &Caller, name: &str) -> Result<Request> {
+    Request::api(ctx)
+        .get_opt(name)
+        .await
+        .map_err(Error::api("get", "Request", name))?
+        .filter(|r| owned_by(r, caller))
+        .ok_or(ApiError::NotFound)
+}
+
+/// Deletes a Request the caller asked for, which revokes its kubeconfig
+pub async fn revoke(ctx: &Context, caller: &Caller, name: &str) -> Result<()> {
+    find(ctx, caller, name).await?;
+
+    Request::api(ctx)
+        .delete(name, &DeleteParams::default())
+        .await
+        .map_err(Error::api("delete", "Request", name))?;
+
+    tracing::info!("Revoked Request {} on behalf of {}", name, caller.username);
+
+    Ok(())
+}
+
+/// Approves a Request waiting for approval on behalf of a member of the approver
+/// groups. Nobody approves their own Requests, so the Request must record who asked
+/// for it.
+pub async fn grant(ctx: &Context, caller: &Caller, name: &str) -> Result<Request> {
+    let settings = ctx.settings();
+    let approvers = settings.api_approver_groups().unwrap_or_default();
+
+    if !caller.groups.iter().any(|g| approvers.contains(g)) {
+        return Err(ApiError::Forbidden {
+            reason: format!("{} is not in any of the approver groups", caller.username),
+        });
+    }
+
+    let mut request = Request::api(ctx)
+        .get_opt(name)
+        .await
+        .map_err(Error::api("get", "Request", name))?
+        .ok_or(ApiError::NotFound)?;
+
+    if !request.awaiting_approval() {
+        return Err(ApiError::BadRequest {
+            reason: format!("Request {} isn't waiting for approval", name),
+        });
+    }
+
+    match request.annotations().get(REQUESTED_BY_ANNOTATION) {
+        None => {
+            return Err(ApiError::BadRequest {
+                reason: format!(
+                "Request {} wasn't made through the API, so who asked for it is unknown",
+                name
+            ),
+            })
+        }
+        Some(_) if owned_by(&request, caller) => {
+            return Err(ApiError::Forbidden {
+                reason: "Requests can't be approved by whoever asked for them"
+                    .to_string(),
+            })
+        }
+        Some(_) => {}
+    }
+
+    // The controller picks the Request up again once it sees the approval
+    if let Some(status) = request.status.as_mut() {
+        status.approved_by = Some(caller.username.clone());
+    }
+    request
+        .message(format!("Approved by {}", caller.username))
+        .update_status(ctx)
+        .await?;
+
+    tracing::info!("Approved Request {} on behalf of {}", name, caller.username);
+
+    Ok(request)
+}
+
+/// Mints a token for the ServiceAccount of a Request the caller asked for, as long
+/// as it is ready and hasn't expired. Only the controller may create tokens, so
+/// nobody can mint them for the Requests of others.
+pub async fn mint(
+    ctx: &Context,
+    caller: &Caller,
+    name: &str,
+    ttl_seconds: i64,
+) -> Result<Token> {
+    let request = find(ctx, caller, name).await?;
+
+    if request.spec.credential_mode.unwrap_or_default() != CredentialMode::Exec {
+        return Err(ApiError::BadRequest {
+            reason: format!("Request {} doesn't use the exec credential mode", name),
+        });
+    }
+
+    let status = match request.status {
+        Some(status) if status.ready => status,
+        _ => {
+            return Err(ApiError::Timeout {
+                name: name.to_string(),
+            })
+        }
+    };
+
+    let expires_at = status.expires_at.unwrap_or_default();
+    let remaining = expires_at - chrono::Utc::now().timestamp();
+    if remaining <= 0 {
+        return Err(ApiError::Forbidden {
+            reason: format!("Request {} has expired", name),
+        });
+    }
+
+    // A token outliving the Request stops working once its ServiceAccount is deleted
+    let token_request = TokenRequest {
+        spec: TokenRequestSpec {
+            expiration_seconds: Some(
+                ttl_seconds.min(remaining).max(MIN_TOKEN_TTL_SECONDS),
+            ),
+            ..TokenRequestSpec::default()
+        },
+        ..TokenRequest::default()
+    };
+
+    let account = &status.service_account_name;
+    let token_status = Api::<ServiceAccount>::namespaced(ctx.client(), &ctx.namespace())
+        .create_token_request(account, &PostParams::default(), &token_request)
+        .await
+        .map_err(Error::api("create", "TokenRequest", account))?
+        .status
+        .ok_or_else(|| ApiError::Failed {
+            name: name.to_string(),
+            reason: "the TokenRequest returned no token".to_string(),
+        })?;
+
+    tracing::info!(
+        "Minted token for Request {} on behalf of {}",
+        name,
+        caller.username
+    );
+
+    // Never let kubectl cache the token beyond the expiry of the Request
+    Ok(Token {
+        token: token_status.token,
+        expires_at: token_status
+            .expiration_timestamp
+            .0
+            .timestamp()
+            .min(expires_at),
+    })
+}
+
+/// Checks if the caller asked for a Request
+pub fn owned_by(request: &Request, caller: &Caller) -> bool {
+    request.annotations().get(REQUESTED_BY_ANNOTATION) == Some(&caller.username)
+}
+
+/// Gets what the caller receives for a Request, if it is ready
+pub fn issued(request: Request) -> Result<Issued> {
+    let name = request.name_any();
+    let namespace = request.annotations().get(NAMESPACE_ANNOTATION).cloned();
+
+    if request.awaiting_approval() {
+        return Err(ApiError::PendingApproval { name });
+    }
+
+    match request.status {
+        Some(status) if status.ready => {
+            let kubeconfig = status.kubeconfig.unwrap_or_default();
+            let kubeconfig = match namespace {
+                Some(namespace) => file::with_namespace(&kubeconfig, &namespace)?,
+                None => kubeconfig,
+            };
+
+            Ok(Issued {
+                name,
+                expires_at: status.expires_at,
+                kubeconfig,
+                output: status.output,
+            })
+        }
+        Some(status) if status.failed => Err(ApiError::Failed {
+            name,
+            reason: status.message,
+        }),
+        _ => Err(ApiError::Timeout { name }),
+    }
+}
@@ -0,0 +1,466 @@
+use super::auth::{self, Caller};
+use super::error::{ApiError, Result};
+use super::requests::{self, Issue, Summary};
+use super::State;
+use crate::resources::role::Role;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{TimeZone, Utc};
+use hyper::header::{
+    CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE,
+};
+use hyper::{Body, HeaderMap, Response, StatusCode};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+/// Cookie holding the ID token of a logged in user
+const SESSION_COOKIE: &str = "kufefe_session";
+
+/// Cookie holding the state and PKCE verifier while logging in
+const LOGIN_COOKIE: &str = "kufefe_login";
+
+/// Content security policy of every page, which run no scripts
+const CSP: &str = "default-src 'none'; style-src 'unsafe-inline'; form-action 'self'; frame-ancestors 'none'";
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:60rem;margin:2rem auto;padding:0 1rem}\
+table{border-collapse:collapse;width:100%}td,th{text-align:left;padding:.4rem;border-bottom:1px solid #ddd}\
+.notice{padding:.6rem;background:#eef6ee}.error{padding:.6rem;background:#fbeaea}\
+form.inline{display:inline}label{display:block;margin:.5rem 0}";
+
+/// Someone logged in to the web UI, with the token their forms must carry
+struct Session {
+    caller: Caller,
+    csrf: String,
+}
+
+/// Handles GET /, showing the roles that can be requested and the caller's Requests
+pub async fn home(state: &State, request: hyper::Request<Body>) -> Response<Body> {
+    let session = match session(state, request.headers()).await {
+        Ok(session) => session,
+        Err(e) => return error(e),
+    };
+
+    let query = query(request.uri().query());
+    let notice = query
+        .get("issued")
+        .map(|name| format!("Request {} is ready.", name))
+        .or_else(|| {
+            query.get("pending").map(|name| {
+                format!("Request {} is not ready yet, it will show up below.", name)
+            })
+        })
+        .or_else(|| {
+            query
+                .get("revoked")
+                .map(|name| format!("Request {} was revoked.", name))
+        });
+
+    render(state, &session, notice.map(Ok)).await
+}
+
+/// Handles GET /login, sending the user to the OIDC provider
+pub async fn login(state: &State) -> Response<Body> {
+    let oidc = match state.oidc.as_ref() {
+        Some(oidc) => oidc,
+        None => return error(ApiError::NotFound),
+    };
+
+    let login_state = random();
+    let verifier = random();
+    let challenge = URL_SAFE_NO_PAD.encode(openssl::sha::sha256(verifier.as_bytes()));
+
+    match oidc.authorization_url(&login_state, &challenge).await {
+        Ok(url) => Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header(LOCATION, url)
+            .header(
+                SET_COOKIE,
+                cookie(
+                    LOGIN_COOKIE,
+                    &format!("{}.{}", login_state, verifier),
+                    Some(600),
+                ),
+            )
+            .body(Body::empty())
+            .unwrap(),
+        Err(e) => error(e),
+    }
+}
+
+/// Handles GET /callback, where the OIDC provider sends the user back to
+pub async fn callback(state: &State, request: hyper::Request<Body>) -> Response<Body> {
+    let result = async {
+        let oidc = state.oidc.as_ref().ok_or(ApiError::NotFound)?;
+        let query = query(request.uri().query());
+
+        if let Some(e) = query.get("error") {
+            return Err(ApiError::Forbidden {
+                reason: format!("login failed: {}", e),
+            });
+        }
+
+        // The state must match the one this browser was sent off with
+        let (expected, verifier) = cookies(request.headers())
+            .get(LOGIN_COOKIE)
+            .and_then(|c| c.split_once('.'))
+            .map(|(s, v)| (s.to_string(), v.to_string()))
+            .ok_or_else(|| ApiError::unauthorized("login expired, please try again"))?;
+
+        let code = match (query.get("code"), query.get("state")) {
+            (Some(code), Some(state)) if *state == expected => code,
+            _ => return Err(ApiError::unauthorized("login state does not match")),
+        };
+
+        let token = oidc.exchange(code, &verifier).await?;
+        let caller =
+            auth::authorize(&state.ctx.settings(), oidc.authenticate(&token).await?)?;
+        tracing::info!("{} logged in to the web UI", caller.username);
+
+        Ok(token)
+    }
+    .await;
+
+    match result {
+        Ok(token) => Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header(LOCATION, "/")
+            .header(SET_COOKIE, cookie(SESSION_COOKIE, &token, None))
+            .header(SET_COOKIE, cookie(LOGIN_COOKIE, "", Some(0)))
+            .body(Body::empty())
+            .unwrap(),
+        Err(e) => page(
+            e.status(),
+            &format!(
+                "<p class=\"error\">{}</p>\
+            <p><a href=\"/login\">Log in again</a></p>",
+                escape(&e.to_string())
+            ),
+        ),
+    }
+}
+
+/// Handles GET /logout, forgetting the session
+pub fn logout() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header(LOCATION, "/")
+        .header(SET_COOKIE, cookie(SESSION_COOKIE, "", Some(0)))
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Handles POST /requests, asking for access from the form
+pub async fn create(state: &State, request: hyper::Request<Body>) -> Response<Body> {
+    let (session, form) = match submitted(state, request).await {
+        Ok(submitted) => submitted,
+        Err(e) => return error(e),
+    };
+
+    let issue = Issue {
+        role: form.get("role").cloned().unwrap_or_default(),
+        duration_minutes: form.get("durationMinutes").and_then(|d| d.parse().ok()),
+        namespace: form.get("namespace").map(|ns| ns.trim().to_string()),
+        ..Issue::default()
+    };
+
+    match requests::issue(&state.ctx, &session.caller, issue).await {
+        Ok(issued) => redirect(&format!("/?issued={}", issued.name)),
+        Err(ApiError::Timeout { name }) => redirect(&format!("/?pending={}", name)),
+        Err(e @ ApiError::Unauthorized { .. }) => error(e),
+        Err(e) => render(state, &session, Some(Err(e.to_string()))).await,
+    }
+}
+
+/// Handles GET /requests/{name}/kubeconfig, downloading the kubeconfig of a Request
+pub async fn download(
+    state: &State,
+    request: hyper::Request<Body>,
+    name: &str,
+) -> Response<Body> {
+    let result = async {
+        let session = session(state, request.headers()).await?;
+        let request = requests::find(&state.ctx, &session.caller, name).await?;
+
+        requests::issued(request)
+    }
+    .await;
+
+    match result {
+        Ok(issued) => Response::builder()
+            .header(CONTENT_TYPE, "application/yaml")
+            .header(CACHE_CONTROL, "no-store")
+            .header(
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"kufefe-{}.yaml\"", issued.name),
+            )
+            .body(Body::from(issued.kubeconfig))
+            .unwrap(),
+        Err(e) => error(e),
+    }
+}
+
+/// Handles POST /requests/{name}/revoke, revoking a Request from the list
+pub async fn revoke(
+    state: &State,
+    request: hyper::Request<Body>,
+    name: &str,
+) -> Response<Body> {
+    let result = async {
+        let (session, _) = submitted(state, request).await?;
+        requests::revoke(&state.ctx, &session.caller, name).await
+    }
+    .await;
+
+    match result {
+        Ok(()) => redirect(&format!("/?revoked={}", name)),
+        Err(e) => error(e),
+    }
+}
+
+/// Renders the home page, with the outcome of what the user just did
+async fn render(
+    state: &State,
+    session: &Session,
+    outcome: Option<std::result::Result<String, String>>,
+) -> Response<Body> {
+    let settings = state.ctx.settings();
+    let roles = Role::new(&state.ctx).list().await;
+    let owned = requests::list(&state.ctx, &session.caller).await;
+
+    let (roles, owned) = match (roles, owned) {
+        (Ok(roles), Ok(owned)) => (roles, owned),
+        (Err(e), _) => return error(e.into()),
+        (_, Err(e)) => return error(e),
+    };
+
+    let mut html = format!(
+        "<p>Logged in as <b>{}</b> · <a href=\"/logout\">Log out</a></p>",
+        escape(&session.caller.username)
+    );
+
+    match outcome {
+        Some(Ok(notice)) => write!(html, "<p class=\"notice\">{}</p>", escape(&notice)),
+        Some(Err(e)) => write!(html, "<p class=\"error\">{}</p>", escape(&e)),
+        None => Ok(()),
+    }
+    .ok();
+
+    // Form to request access
+    let default = settings.expire_after().as_secs() / 60;
+    let max = settings
+        .max_expire_after()
+        .map(|max| format!(" max=\"{}\"", max.as_secs() / 60))
+        .unwrap_or_default();
+    let options: String = roles
+        .iter()
+        .map(|r| format!("<option>{}</option>", escape(r)))
+        .collect();
+
+    write!(
+        html,
+        "<h2>Request access</h2>\
+        <form method=\"post\" action=\"/requests\">\
+        <input type=\"hidden\" name=\"csrf\" value=\"{csrf}\">\
+        <label>Role <select name=\"role\" required>{options}</select></label>\
+        <label>Duration in minutes <input type=\"number\" name=\"durationMinutes\" min=\"1\"{max} value=\"{default}\" required></label>\
+        <label>Default namespace <input type=\"text\" name=\"namespace\" placeholder=\"optional\"></label>\
+        <button type=\"submit\">Request</button></form>",
+        csrf = session.csrf,
+    )
+    .ok();
+
+    // The caller's Requests
+    html.push_str("<h2>Your access</h2>");
+    if owned.is_empty() {
+        html.push_str("<p>You have no active access.</p>");
+    } else {
+        html.push_str(
+            "<table><tr><th>Name</th><th>Role</th><th>Namespace</th>\
+            <th>Status</th><th>Expires</th><th></th></tr>",
+        );
+
+        for summary in owned.iter().map(Summary::from) {
+            let name = escape(&summary.name);
+            let status = match (summary.ready, summary.failed) {
+                (true, _) => "Ready".to_string(),
+                (_, true) => format!("Failed: {}", escape(&summary.message)),
+                _ => "Pending".to_string(),
+            };
+            let download = match summary.ready {
+                true => {
+                    format!("<a href=\"/requests/{}/kubeconfig\">Download</a> ", name)
+                }
+                false => String::new(),
+            };
+
+            write!(
+                html,
+                "<tr><td>{name}</td><td>{role}</td><td>{namespace}</td><td>{status}</td>\
+                <td>{expires}</td><td>{download}\
+                <form class=\"inline\" method=\"post\" action=\"/requests/{name}/revoke\">\
+                <input type=\"hidden\" name=\"csrf\" value=\"{csrf}\">\
+                <button type=\"submit\">Revoke</button></form></td></tr>",
+                role = escape(&summary.role),
+                namespace = escape(summary.namespace.as_deref().unwrap_or("-")),
+                expires = summary.expires_at.map(time).unwrap_or_default(),
+                csrf = session.csrf,
+            )
+            .ok();
+        }
+
+        html.push_str("</table>");
+    }
+
+    page(StatusCode::OK, &html)
+}
+
+/// Authenticates the session cookie of a browser
+async fn session(state: &State, headers: &HeaderMap) -> Result<Session> {
+    let oidc = state.oidc.as_ref().ok_or(ApiError::NotFound)?;
+    let cookies = cookies(headers);
+    let token = cookies
+        .get(SESSION_COOKIE)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| ApiError::unauthorized("not logged in"))?;
+
+    let caller = auth::authorize(&state.ctx.settings(), oidc.authenticate(token).await?)?;
+
+    Ok(Session {
+        caller,
+        csrf: csrf(token),
+    })
+}
+
+/// Authenticates a submitted form and checks its CSRF token
+async fn submitted(
+    state: &State,
+    request: hyper::Request<Body>,
+) -> Result<(Session, HashMap<String, String>)> {
+    let session = session(state, request.headers()).await?;
+    let bytes = requests::read(request.into_body()).await?;
+    let form: HashMap<String, String> =
+        form_urlencoded::parse(&bytes).into_owned().collect();
+
+    if form.get("csrf") != Some(&session.csrf) {
+        return Err(ApiError::Forbidden {
+            reason: "the form has expired, please try again".to_string(),
+        });
+    }
+
+    Ok((session, form))
+}
+
+/// Derives the CSRF token of a session, so it doesn't need to be stored
+pub fn csrf(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(openssl::sha::sha256(format!("csrf:{}", token).as_bytes()))
+}
+
+/// Reports an error as a page, sending users that aren't logged in to the login
+fn error(e: ApiError) -> Response<Body> {
+    match e {
+        ApiError::Unauthorized { .. } => redirect("/login"),
+        e => {
+            // Internal errors are logged, but not shown
+            let message = match e.status() {
+                StatusCode::INTERNAL_SERVER_ERROR => {
+                    tracing::error!("Web UI failed: {}", e);
+                    "Internal error".to_string()
+                }
+                _ => e.to_string(),
+            };
+
+            page(
+                e.status(),
+                &format!(
+                    "<p class=\"error\">{}</p><p><a href=\"/\">Back</a></p>",
+                    escape(&message)
+                ),
+            )
+        }
+    }
+}
+
+/// Wraps content in an HTML page
+fn page(status: StatusCode, content: &str) -> Response<Body> {
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Kufefe</title>\
+        <style>{}</style></head><body><h1>Kufefe</h1>{}</body></html>",
+        STYLE, content
+    );
+
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(CACHE_CONTROL, "no-store")
+        .header("Content-Security-Policy", CSP)
+        .body(Body::from(html))
+        .unwrap()
+}
+
+/// Redirects the browser after a form was handled
+fn redirect(location: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header(LOCATION, location)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Builds a cookie only sent back over HTTPS and hidden from scripts
+fn cookie(name: &str, value: &str, max_age: Option<i64>) -> String {
+    let mut cookie =
+        format!("{}={}; Path=/; HttpOnly; Secure; SameSite=Lax", name, value);
+    if let Some(max_age) = max_age {
+        write!(cookie, "; Max-Age={}", max_age).ok();
+    }
+
+    cookie
+}
+
+/// Parses the cookies a browser sent
+fn cookies(headers: &HeaderMap) -> HashMap<String, String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Parses a query string
+fn query(query: Option<&str>) -> HashMap<String, String> {
+    form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect()
+}
+
+/// Generates a random URL safe value
+fn random() -> String {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes).expect("Failed to generate random bytes");
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Formats a unix timestamp for display
+fn time(secs: i64) -> String {
+    Utc.timestamp_opt(secs, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+/// Escapes text for use in HTML
+pub fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&#39;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,10 @@
+use clap::Parser;
+use kufefe::plugin::{self, Cli};
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = plugin::run(Cli::parse()).await {
+        eprintln!("error: {:#}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,60 @@
+use crate::config::ConfigArgs;
+use clap::{Args, FromArgMatches, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(version, about = "Kufefe - Ephemeral Kubeconfig Generator")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Gets the subcommand to run. Without one, the controller runs with settings
+    /// from the environment and the configuration file.
+    pub fn command(self) -> Command {
+        self.command.unwrap_or_else(|| {
+            let matches = ConfigArgs::augment_args(clap::Command::new("kufefe"))
+                .get_matches_from(["kufefe"]);
+            let config =
+                ConfigArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+            Command::Run(Box::new(config))
+        })
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Runs the controller, the default without a subcommand
+    Run(Box<ConfigArgs>),
+    /// Fetches a short-lived token for a Request (kubectl exec credential plugin)
+    Credential(CredentialArgs),
+    /// Prints the CustomResourceDefinition for Requests
+    Crd,
+}
+
+#[derive(Args)]
+pub struct CredentialArgs {
+    /// Name of the Request to fetch a token for
+    #[arg(long)]
+    pub request: String,
+
+    /// URL of the self-service API, which mints the tokens
+    #[arg(long, env = "KUFEFE_API_URL")]
+    pub api_url: String,
+
+    /// PEM bundle the certificate of the self-service API is verified with,
+    /// defaults to the system roots
+    #[arg(long, env = "KUFEFE_API_CA")]
+    pub api_ca: Option<PathBuf>,
+
+    /// Kubeconfig context whose credentials authenticate with the self-service API,
+    /// defaults to the current context
+    #[arg(long, env = "KUFEFE_CONTEXT")]
+    pub context: Option<String>,
+
+    /// Requested lifetime of the token in seconds
+    #[arg(long, default_value_t = 600)]
+    pub ttl_seconds: i64,
+}
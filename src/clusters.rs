@@ -0,0 +1,141 @@
+use crate::context::{Context, Endpoint};
+use crate::error::{Error, Result};
+use crate::{crd::Request, resources::token::Token, transaction::Transaction};
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::{ListParams, Patch, PatchParams};
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::{Api, Client, Config, ResourceExt};
+use serde_json::json;
+
+/// Label registering a kubeconfig Secret as a target cluster, valued with its name
+pub const CLUSTER_LABEL: &str = "kufefe.io/cluster";
+
+/// Finalizer removing the resources of a Request from its target cluster
+pub const FINALIZER: &str = "kufefe.io/cleanup";
+
+/// Gets the Context to provision a Request in, which is its target cluster if it names one
+pub async fn context(ctx: &Context, request: &Request) -> Result<Context> {
+    match &request.spec.cluster {
+        Some(cluster) if ctx.cluster().as_ref() != Some(cluster) => {
+            target(ctx, cluster).await
+        }
+        _ => Ok(ctx.clone()),
+    }
+}
+
+/// Connects to a target cluster through its registered kubeconfig Secret
+async fn target(ctx: &Context, cluster: &str) -> Result<Context> {
+    let api: Api<Secret> = Api::namespaced(ctx.hub(), &ctx.namespace());
+    let selector = format!("{}={}", CLUSTER_LABEL, cluster);
+
+    let secret = api
+        .list(&ListParams::default().labels(&selector))
+        .await
+        .map_err(Error::api("list", "Secret", &selector))?
+        .items
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::ClusterNotFound {
+            cluster: cluster.to_string(),
+        })?;
+
+    let unavailable = |reason: String| Error::ClusterUnavailable {
+        cluster: cluster.to_string(),
+        reason,
+    };
+
+    let kubeconfig = String::from_utf8(Token::data(&secret, "kubeconfig")?.0)
+        .map_err(|_| unavailable("kubeconfig is not valid UTF-8".to_string()))?;
+    let kubeconfig =
+        Kubeconfig::from_yaml(&kubeconfig).map_err(|e| unavailable(e.to_string()))?;
+    let config =
+        Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default())
+            .await
+            .map_err(|e| unavailable(e.to_string()))?;
+
+    // Generated resources go in the same namespace as here, unless the Secret says otherwise
+    let namespace = match Token::data(&secret, "namespace") {
+        Ok(namespace) => String::from_utf8(namespace.0)
+            .map_err(|_| unavailable("namespace is not valid UTF-8".to_string()))?,
+        Err(_) => ctx.namespace(),
+    };
+
+    // The CA is taken from the token Secret in the target cluster
+    let endpoint = Endpoint {
+        url: config
+            .cluster_url
+            .to_string()
+            .trim_end_matches('/')
+            .to_string(),
+        provider: "cluster".to_string(),
+        ca: None,
+    };
+    let client = Client::try_from(config).map_err(|e| unavailable(e.to_string()))?;
+
+    Ok(ctx.target(cluster, client, namespace, endpoint))
+}
+
+/// Adds the finalizer to a Request for a target cluster, as its resources
+/// can't be garbage collected through an owner reference
+pub async fn add_finalizer(ctx: &Context, request: &Request) -> Result<()> {
+    if request.spec.cluster.is_none()
+        || request.finalizers().iter().any(|f| f == FINALIZER)
+    {
+        return Ok(());
+    }
+
+    let mut finalizers = request.finalizers().to_vec();
+    finalizers.push(FINALIZER.to_string());
+
+    patch_finalizers(ctx, request, finalizers).await
+}
+
+/// Deletes the resources of a deleted Request from its target cluster and releases it
+pub async fn finalize(ctx: &Context, request: Request) -> Result<()> {
+    if !request.finalizers().iter().any(|f| f == FINALIZER) {
+        return Ok(());
+    }
+
+    if let Some(status) = &request.status {
+        match context(ctx, &request).await {
+            Ok(target) => Transaction::from_status(status).rollback(&target).await,
+            // Nothing can be cleaned up in a cluster that is no longer registered
+            Err(e @ Error::ClusterNotFound { .. }) => {
+                tracing::warn!("Releasing {} without cleanup: {}", request.name_any(), e)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let finalizers = request
+        .finalizers()
+        .iter()
+        .filter(|f| *f != FINALIZER)
+        .cloned()
+        .collect();
+
+    patch_finalizers(ctx, &request, finalizers).await?;
+    tracing::info!("Cleaned up {} in its target cluster", request.name_any());
+
+    Ok(())
+}
+
+/// Replaces the finalizers of a Request
+async fn patch_finalizers(
+    ctx: &Context,
+    request: &Request,
+    finalizers: Vec<String>,
+) -> Result<()> {
+    let patch = json!({ "metadata": { "finalizers": finalizers } });
+
+    Request::api(ctx)
+        .patch(
+            &request.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+        .map_err(Error::api("patch", "Request", &request.name_any()))?;
+
+    Ok(())
+}
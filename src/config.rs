@@ -1,96 +1,643 @@
-use crate::resources::gke::cluster::Cluster;
-use anyhow::{bail, Result};
-use kube::{api::ListParams, Api, Client};
-use std::env;
+use crate::crd::{OutputFormat, MAX_DURATION_MINUTES};
+use crate::discovery::{self, DEFAULT_ORDER};
+use crate::notify::Webhook;
+use anyhow::{bail, Context as _, Result};
+use clap::{Args, ValueEnum};
+use hyper::Uri;
+use kube::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::PathBuf;
+use std::time::Duration;
 
+/// Settings as given in the configuration file, environment or command line,
+/// where flags take precedence over environment variables, which take
+/// precedence over the file
+#[derive(Args, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ConfigArgs {
+    /// Path to a YAML configuration file
+    #[arg(long, env = "KUFEFE_CONFIG")]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// API address written to issued kubeconfigs, auto-detected if not set
+    #[arg(long, env = "CLUSTER_URL")]
+    pub cluster_url: Option<String>,
+
+    /// Name of the GKE/Anthos, EKS or AKS cluster if there are several
+    #[arg(long, env = "CLUSTER_NAME")]
+    pub cluster_name: Option<String>,
+
+    /// Providers tried in turn to auto-detect the cluster URL
+    #[arg(long, env = "DISCOVERY_ORDER", value_delimiter = ',')]
+    pub discovery_order: Option<Vec<String>>,
+
+    /// How often the cluster URL and CA are re-discovered, 0 disables it
+    #[arg(long, env = "DISCOVERY_INTERVAL_SECONDS")]
+    pub discovery_interval_seconds: Option<u64>,
+
+    /// Rewrite issued kubeconfigs when the cluster URL or CA changes
+    #[arg(long, env = "REGENERATE_KUBECONFIGS")]
+    pub regenerate_kubeconfigs: Option<bool>,
+
+    /// Namespace generated resources are created in
+    #[arg(long, env = "NAMESPACE")]
+    pub namespace: Option<String>,
+
+    /// Verify issued kubeconfigs before handing them out
+    #[arg(long, env = "VALIDATE_KUBECONFIG")]
+    pub validate_kubeconfig: Option<bool>,
+
+    /// How long to wait for the token controller
+    #[arg(long, env = "TOKEN_TIMEOUT_SECONDS")]
+    pub token_timeout_seconds: Option<u64>,
+
+    /// Prefix of generated resource names
+    #[arg(long, env = "NAME_PREFIX")]
+    pub name_prefix: Option<String>,
+
+    /// Maximum length of generated resource names
+    #[arg(long, env = "NAME_MAX_LENGTH")]
+    pub name_max_length: Option<usize>,
+
+    /// Interval between sweeps for orphaned resources
+    #[arg(long, env = "SWEEP_INTERVAL_SECONDS")]
+    pub sweep_interval_seconds: Option<u64>,
+
+    /// Only report orphaned resources instead of deleting them
+    #[arg(long, env = "SWEEP_DRY_RUN")]
+    pub sweep_dry_run: Option<bool>,
+
+    /// What to do when generated resources are tampered with
+    #[arg(long, env = "DRIFT_POLICY")]
+    pub drift_policy: Option<DriftPolicy>,
+
+    /// Interval between full scans for expired Requests
+    #[arg(long, env = "SCAN_INTERVAL_SECONDS")]
+    pub scan_interval_seconds: Option<u64>,
+
+    /// How long in-flight reconciles may take during shutdown
+    #[arg(long, env = "SHUTDOWN_TIMEOUT_SECONDS")]
+    pub shutdown_timeout_seconds: Option<u64>,
+
+    /// How many times provisioning a Request is attempted
+    #[arg(long, env = "RETRY_MAX_ATTEMPTS")]
+    pub retry_max_attempts: Option<u32>,
+
+    /// Delay before the first retry, doubled on every further attempt
+    #[arg(long, env = "RETRY_BACKOFF_SECONDS")]
+    pub retry_backoff_seconds: Option<u64>,
+
+    /// Port metrics are served on
+    #[arg(long, env = "METRICS_PORT")]
+    pub metrics_port: Option<u16>,
+
+    /// How long Requests are valid, unless they ask for another duration
+    #[arg(long, env = "EXPIRE_MINUTES")]
+    pub expire_minutes: Option<u64>,
+
+    /// Longest duration a Request may ask for
+    #[arg(long, env = "MAX_EXPIRE_MINUTES")]
+    pub max_expire_minutes: Option<u64>,
+
+    /// Roles Requests may use, on top of the kufefe.io/role annotation
+    #[arg(long, env = "ALLOWED_ROLES", value_delimiter = ',')]
+    pub allowed_roles: Option<Vec<String>>,
+
+    /// Output format of Requests that don't ask for one
+    #[arg(long, env = "DEFAULT_OUTPUT_FORMAT")]
+    pub default_output_format: Option<OutputFormat>,
+
+    /// ConfigMap in the namespace whose settings are applied while running
+    #[arg(long, env = "CONFIG_MAP")]
+    pub config_map: Option<String>,
+
+    /// Port the self-service API is served on, disabled if not set
+    #[arg(long, env = "API_PORT")]
+    pub api_port: Option<u16>,
+
+    /// PEM certificate chain the self-service API is served with
+    #[arg(long, env = "API_TLS_CERT")]
+    pub api_tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the self-service API certificate
+    #[arg(long, env = "API_TLS_KEY")]
+    pub api_tls_key: Option<PathBuf>,
+
+    /// Groups allowed to use the self-service API, which is required when it is enabled
+    #[arg(long, env = "API_ALLOWED_GROUPS", value_delimiter = ',')]
+    pub api_allowed_groups: Option<Vec<String>>,
+
+    /// Groups whose members may approve Requests for roles requiring approval
+    #[arg(long, env = "API_APPROVER_GROUPS", value_delimiter = ',')]
+    pub api_approver_groups: Option<Vec<String>>,
+
+    /// URL users reach the self-service API at, which kubeconfigs of the exec
+    /// credential mode fetch their tokens from
+    #[arg(long, env = "API_URL")]
+    pub api_url: Option<String>,
+
+    /// Issuer of the OIDC ID tokens the self-service API accepts
+    #[arg(long, env = "OIDC_ISSUER_URL")]
+    pub oidc_issuer_url: Option<String>,
+
+    /// Client ID OIDC ID tokens must be issued for
+    #[arg(long, env = "OIDC_CLIENT_ID")]
+    pub oidc_client_id: Option<String>,
+
+    /// Client secret the web UI logs in with, if the client is confidential
+    #[arg(long, env = "OIDC_CLIENT_SECRET", hide_env_values = true)]
+    pub oidc_client_secret: Option<String>,
+
+    /// URL the OIDC provider redirects to after logging in to the web UI, which
+    /// is served if this is set
+    #[arg(long, env = "OIDC_REDIRECT_URL")]
+    pub oidc_redirect_url: Option<String>,
+
+    /// Webhooks told about issued, extended, revoked and expired Requests, which
+    /// can only be set in the configuration file or ConfigMap
+    #[arg(skip)]
+    pub webhooks: Option<Vec<Webhook>>,
+}
+
+/// Settings that can be changed while running, through the key config.yaml of
+/// the ConfigMap. They take precedence over every other source.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Overrides {
+    pub expire_minutes: Option<u64>,
+    pub max_expire_minutes: Option<u64>,
+    pub allowed_roles: Option<Vec<String>>,
+    pub name_prefix: Option<String>,
+    pub name_max_length: Option<usize>,
+    pub default_output_format: Option<OutputFormat>,
+    pub validate_kubeconfig: Option<bool>,
+    pub webhooks: Option<Vec<Webhook>>,
+}
+
+impl ConfigArgs {
+    /// Fills in everything not set here from the other source
+    fn or(self, other: Self) -> Self {
+        Self {
+            config: self.config.or(other.config),
+            cluster_url: self.cluster_url.or(other.cluster_url),
+            cluster_name: self.cluster_name.or(other.cluster_name),
+            discovery_order: self.discovery_order.or(other.discovery_order),
+            discovery_interval_seconds: self
+                .discovery_interval_seconds
+                .or(other.discovery_interval_seconds),
+            regenerate_kubeconfigs: self
+                .regenerate_kubeconfigs
+                .or(other.regenerate_kubeconfigs),
+            namespace: self.namespace.or(other.namespace),
+            validate_kubeconfig: self.validate_kubeconfig.or(other.validate_kubeconfig),
+            token_timeout_seconds: self
+                .token_timeout_seconds
+                .or(other.token_timeout_seconds),
+            name_prefix: self.name_prefix.or(other.name_prefix),
+            name_max_length: self.name_max_length.or(other.name_max_length),
+            sweep_interval_seconds: self
+                .sweep_interval_seconds
+                .or(other.sweep_interval_seconds),
+            sweep_dry_run: self.sweep_dry_run.or(other.sweep_dry_run),
+            drift_policy: self.drift_policy.or(other.drift_policy),
+            scan_interval_seconds: self
+                .scan_interval_seconds
+                .or(other.scan_interval_seconds),
+            shutdown_timeout_seconds: self
+                .shutdown_timeout_seconds
+                .or(other.shutdown_timeout_seconds),
+            retry_max_attempts: self.retry_max_attempts.or(other.retry_max_attempts),
+            retry_backoff_seconds: self
+                .retry_backoff_seconds
+                .or(other.retry_backoff_seconds),
+            metrics_port: self.metrics_port.or(other.metrics_port),
+            expire_minutes: self.expire_minutes.or(other.expire_minutes),
+            max_expire_minutes: self.max_expire_minutes.or(other.max_expire_minutes),
+            allowed_roles: self.allowed_roles.or(other.allowed_roles),
+            default_output_format: self
+                .default_output_format
+                .or(other.default_output_format),
+            config_map: self.config_map.or(other.config_map),
+            api_port: self.api_port.or(other.api_port),
+            api_tls_cert: self.api_tls_cert.or(other.api_tls_cert),
+            api_tls_key: self.api_tls_key.or(other.api_tls_key),
+            api_allowed_groups: self.api_allowed_groups.or(other.api_allowed_groups),
+            api_approver_groups: self.api_approver_groups.or(other.api_approver_groups),
+            api_url: self.api_url.or(other.api_url),
+            oidc_issuer_url: self.oidc_issuer_url.or(other.oidc_issuer_url),
+            oidc_client_id: self.oidc_client_id.or(other.oidc_client_id),
+            oidc_client_secret: self.oidc_client_secret.or(other.oidc_client_secret),
+            oidc_redirect_url: self.oidc_redirect_url.or(other.oidc_redirect_url),
+            webhooks: self.webhooks.or(other.webhooks),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct KufefeConfig {
     url: String,
+    url_provider: String,
+    ca: Option<String>,
+    cluster_url: Option<String>,
+    cluster_name: Option<String>,
+    discovery_order: Vec<String>,
+    discovery_interval: Duration,
+    regenerate_kubeconfigs: bool,
     namespace: String,
-    client: Client,
+    validate: bool,
+    token_timeout: Duration,
+    name_prefix: String,
+    name_max_length: usize,
+    sweep_interval: Duration,
+    sweep_dry_run: bool,
+    drift_policy: DriftPolicy,
+    scan_interval: Duration,
+    shutdown_timeout: Duration,
+    retry_max_attempts: u32,
+    retry_backoff: Duration,
+    metrics_port: u16,
+    expire_after: Duration,
+    max_expire_after: Option<Duration>,
+    allowed_roles: Option<Vec<String>>,
+    default_output_format: OutputFormat,
+    config_map: Option<String>,
+    api_port: Option<u16>,
+    api_tls_cert: Option<PathBuf>,
+    api_tls_key: Option<PathBuf>,
+    api_allowed_groups: Option<Vec<String>>,
+    api_approver_groups: Option<Vec<String>>,
+    api_url: Option<String>,
+    oidc_issuer_url: Option<String>,
+    oidc_client_id: Option<String>,
+    oidc_client_secret: Option<String>,
+    oidc_redirect_url: Option<String>,
+    webhooks: Vec<Webhook>,
+}
+
+/// How to handle generated resources that no longer match their Request
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DriftPolicy {
+    Repair,
+    Revoke,
+    Ignore,
 }
 
 impl KufefeConfig {
-    /// Attempt to automatically fetch the cluster url from the environment
-    pub async fn new() -> Result<Self> {
-        let client = Client::try_default()
-            .await
-            .expect("Failed to create Kubernetes Client");
+    /// Loads and validates the settings, reading the configuration file if one is given
+    pub fn load(args: ConfigArgs) -> Result<Self> {
+        let file = match &args.config {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path).with_context(|| {
+                    format!("Failed to read configuration file {}", path.display())
+                })?;
 
-        let mut url: String = Self::from_env();
-        let namespace = env::var("NAMESPACE").unwrap_or_else(|_| "default".to_string());
+                serde_yaml::from_str(&contents).with_context(|| {
+                    format!("Invalid configuration file {}", path.display())
+                })?
+            }
+            None => ConfigArgs::default(),
+        };
 
-        // Handle fallback methods if URL isn't explicitly set
-        if url.is_empty() {
-            tracing::info!(
-                "Cluster URL not explicitly set. Attempting to find it automatically.."
-            );
+        let args = args.or(file);
+        let seconds =
+            |v: Option<u64>, default: u64| Duration::from_secs(v.unwrap_or(default));
 
-            url = Self::anthos(client.clone()).await?
-        }
+        let settings = Self {
+            url: String::new(),
+            url_provider: String::new(),
+            ca: None,
+            cluster_url: args.cluster_url.filter(|url| !url.is_empty()),
+            cluster_name: args.cluster_name.filter(|name| !name.is_empty()),
+            discovery_order: args
+                .discovery_order
+                .unwrap_or_else(|| DEFAULT_ORDER.iter().map(|p| p.to_string()).collect())
+                .into_iter()
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+            discovery_interval: seconds(args.discovery_interval_seconds, 300),
+            regenerate_kubeconfigs: args.regenerate_kubeconfigs.unwrap_or(false),
+            namespace: args.namespace.unwrap_or_else(|| "default".to_string()),
+            validate: args.validate_kubeconfig.unwrap_or(true),
+            token_timeout: seconds(args.token_timeout_seconds, 30),
+            name_prefix: args.name_prefix.unwrap_or_else(|| "kufefe".to_string()),
+            name_max_length: args.name_max_length.unwrap_or(63),
+            sweep_interval: seconds(args.sweep_interval_seconds, 600),
+            sweep_dry_run: args.sweep_dry_run.unwrap_or(false),
+            drift_policy: args.drift_policy.unwrap_or(DriftPolicy::Repair),
+            scan_interval: seconds(args.scan_interval_seconds, 900),
+            shutdown_timeout: seconds(args.shutdown_timeout_seconds, 25),
+            retry_max_attempts: args.retry_max_attempts.unwrap_or(5),
+            retry_backoff: seconds(args.retry_backoff_seconds, 30),
+            metrics_port: args.metrics_port.unwrap_or(9090),
+            expire_after: minutes(args.expire_minutes.unwrap_or(60)),
+            max_expire_after: args.max_expire_minutes.map(minutes),
+            allowed_roles: args.allowed_roles,
+            default_output_format: args.default_output_format.unwrap_or_default(),
+            config_map: args.config_map.filter(|name| !name.is_empty()),
+            api_port: args.api_port.filter(|port| *port != 0),
+            api_tls_cert: args.api_tls_cert,
+            api_tls_key: args.api_tls_key,
+            api_allowed_groups: args
+                .api_allowed_groups
+                .map(|groups| groups.into_iter().filter(|g| !g.is_empty()).collect())
+                .filter(|groups: &Vec<String>| !groups.is_empty()),
+            api_approver_groups: args
+                .api_approver_groups
+                .map(|groups| groups.into_iter().filter(|g| !g.is_empty()).collect())
+                .filter(|groups: &Vec<String>| !groups.is_empty()),
+            api_url: args.api_url.filter(|url| !url.is_empty()),
+            oidc_issuer_url: args.oidc_issuer_url.filter(|url| !url.is_empty()),
+            oidc_client_id: args.oidc_client_id.filter(|id| !id.is_empty()),
+            oidc_client_secret: args.oidc_client_secret.filter(|s| !s.is_empty()),
+            oidc_redirect_url: args.oidc_redirect_url.filter(|url| !url.is_empty()),
+            webhooks: args.webhooks.unwrap_or_default(),
+        };
 
-        tracing::info!("Detected URL: {} and namespace: {}", url, namespace);
+        settings.validate_settings()?;
 
-        Ok(Self {
-            url,
-            namespace,
-            client: Client::try_default()
-                .await
-                .expect("Failed to generate Kubernetes Client"),
-        })
+        Ok(settings)
     }
 
-    /// Attempts to fetch the cluster url from GKE / Anthos
-    async fn anthos(client: Client) -> Result<String> {
-        tracing::info!("Attempting to find GKE/Anthos kind: Cluster");
-
-        let api: Api<Cluster> = Api::namespaced(client, "default");
-
-        match api.list(&ListParams::default()).await {
-            Ok(list) => {
-                // If there's multiple questions, defer to the cluster name.
-                if list.items.len() > 1 {
-                    let cluster_name = env::var("CLUSTER_NAME");
-                    if cluster_name.is_err() {
-                        bail!("Found more than one cluster. Please specify the CLUSTER_NAME environment variable");
-                    }
-
-                    // Attempt to find the cluster in the list
-                    if let Some(cluster) = list.items.iter().find(|c| {
-                        let cluster_name = cluster_name.clone().unwrap();
-                        c.metadata.name.clone().unwrap() == cluster_name
-                    }) {
-                        if let Some(status) = &cluster.status {
-                            return Ok(format!(
-                                "https://{}:{}",
-                                status.api_endpoints[0].host.clone(),
-                                status.api_endpoints[0].port.clone()
-                            ));
-                        }
-                    }
-                }
+    /// Checks the settings, reporting every problem at once
+    fn validate_settings(&self) -> Result<()> {
+        let mut problems = Vec::new();
 
-                // Grab the first cluster if there's only one
-                if let Some(cluster) = list.items.first() {
-                    if let Some(status) = &cluster.status {
-                        return Ok(format!(
-                            "https://{}:{}",
-                            status.api_endpoints[0].host.clone(),
-                            status.api_endpoints[0].port.clone()
-                        ));
-                    }
-                }
+        if let Some(url) = &self.cluster_url {
+            match url.parse::<Uri>() {
+                Ok(uri) if uri.scheme_str() == Some("https") && uri.host().is_some() => {}
+                _ => problems.push(format!("clusterUrl {} is not an https URL", url)),
+            }
+        }
 
-                bail!("Failed to find any cluster resources");
+        if self.discovery_order.is_empty() {
+            problems.push("discoveryOrder must name at least one provider".to_string());
+        }
+
+        for provider in &self.discovery_order {
+            if !DEFAULT_ORDER.contains(&provider.as_str()) {
+                problems.push(format!(
+                    "discoveryOrder contains unknown provider {}, expected one of {}",
+                    provider,
+                    DEFAULT_ORDER.join(", ")
+                ));
+            }
+        }
+
+        let label = |s: &str| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                && !s.starts_with('-')
+        };
+
+        if !label(&self.namespace) {
+            problems.push(format!("namespace {} is not a valid name", self.namespace));
+        }
+
+        if !label(&self.name_prefix) {
+            problems.push(format!(
+                "namePrefix {} must consist of lowercase letters, digits and dashes",
+                self.name_prefix
+            ));
+        }
+
+        // Room for the prefix, a UID suffix and at least a few characters of the name
+        let min_length = self.name_prefix.len() + 14;
+        if self.name_max_length < min_length || self.name_max_length > 63 {
+            problems.push(format!(
+                "nameMaxLength must be between {} and 63, got {}",
+                min_length, self.name_max_length
+            ));
+        }
+
+        for (name, value) in [
+            ("tokenTimeoutSeconds", self.token_timeout),
+            ("sweepIntervalSeconds", self.sweep_interval),
+            ("scanIntervalSeconds", self.scan_interval),
+            ("retryBackoffSeconds", self.retry_backoff),
+            ("expireMinutes", self.expire_after),
+        ] {
+            if value.is_zero() {
+                problems.push(format!("{} must be greater than 0", name));
+            }
+        }
+
+        for (name, value) in [
+            ("expireMinutes", Some(self.expire_after)),
+            ("maxExpireMinutes", self.max_expire_after),
+        ] {
+            if value.is_some_and(|value| value > minutes(MAX_DURATION_MINUTES)) {
+                problems
+                    .push(format!("{} must not exceed {}", name, MAX_DURATION_MINUTES));
             }
-            Err(e) => {
-                bail!("Failed to find a cluster: {}", e);
+        }
+
+        if let Some(max) = self.max_expire_after {
+            if max < self.expire_after {
+                problems.push(
+                    "maxExpireMinutes must not be less than expireMinutes".to_string(),
+                );
+            }
+        }
+
+        if self.retry_max_attempts == 0 {
+            problems.push("retryMaxAttempts must be at least 1".to_string());
+        }
+
+        if self.metrics_port == 0 {
+            problems.push("metricsPort must not be 0".to_string());
+        }
+
+        if self.api_port.is_some() {
+            if self.api_port == Some(self.metrics_port) {
+                problems.push("apiPort must differ from metricsPort".to_string());
+            }
+
+            if self.api_tls_cert.is_none() || self.api_tls_key.is_none() {
+                problems.push("apiPort requires apiTlsCert and apiTlsKey".to_string());
+            }
+
+            // Every pod's ServiceAccount token passes a TokenReview, so callers must
+            // always be narrowed down to groups
+            if self.api_allowed_groups.is_none() {
+                problems.push("apiPort requires apiAllowedGroups".to_string());
+            }
+        }
+
+        if self.api_approver_groups.is_some() && self.api_port.is_none() {
+            problems.push("apiApproverGroups requires apiPort".to_string());
+        }
+
+        if let Some(url) = &self.api_url {
+            match url.parse::<Uri>() {
+                Ok(uri) if uri.scheme_str() == Some("https") && uri.host().is_some() => {}
+                _ => problems.push(format!("apiUrl {} is not an https URL", url)),
+            }
+
+            if self.api_port.is_none() {
+                problems.push("apiUrl requires apiPort".to_string());
+            }
+        }
+
+        if let Some(url) = &self.oidc_issuer_url {
+            match url.parse::<Uri>() {
+                Ok(uri) if uri.scheme_str() == Some("https") && uri.host().is_some() => {}
+                _ => problems.push(format!("oidcIssuerUrl {} is not an https URL", url)),
+            }
+
+            if self.oidc_client_id.is_none() {
+                problems.push("oidcIssuerUrl requires oidcClientId".to_string());
+            }
+        }
+
+        if let Some(url) = &self.oidc_redirect_url {
+            match url.parse::<Uri>() {
+                Ok(uri)
+                    if uri.scheme_str() == Some("https")
+                        && uri.host().is_some()
+                        && uri.path() == "/callback" => {}
+                _ => problems.push(format!(
+                    "oidcRedirectUrl {} is not an https URL with the path /callback",
+                    url
+                )),
+            }
+
+            if self.api_port.is_none() || self.oidc_issuer_url.is_none() {
+                problems.push(
+                    "oidcRedirectUrl requires apiPort and oidcIssuerUrl".to_string(),
+                );
+            }
+        }
+
+        // Webhook URLs often embed a token, so they are referred to by position
+        for (i, webhook) in self.webhooks.iter().enumerate() {
+            match webhook.url.parse::<Uri>() {
+                Ok(uri)
+                    if matches!(uri.scheme_str(), Some("https" | "http"))
+                        && uri.host().is_some() => {}
+                _ => problems.push(format!("webhooks[{}].url is not an http(s) URL", i)),
+            }
+
+            if let Some(env) = &webhook.secret_env {
+                if std::env::var_os(env).is_none() {
+                    problems.push(format!(
+                        "webhooks[{}].secretEnv {} is not set in the environment",
+                        i, env
+                    ));
+                }
             }
         }
+
+        if !problems.is_empty() {
+            bail!("Invalid configuration:\n  {}", problems.join("\n  "));
+        }
+
+        Ok(())
+    }
+
+    /// Applies the overrides on top of these settings, validating the result
+    pub fn with_overrides(&self, overrides: &Overrides) -> Result<Self> {
+        let mut settings = self.clone();
+        let overrides = overrides.clone();
+
+        if let Some(after) = overrides.expire_minutes.map(minutes) {
+            settings.expire_after = after;
+        }
+        if let Some(max) = overrides.max_expire_minutes.map(minutes) {
+            settings.max_expire_after = Some(max);
+        }
+        if let Some(roles) = overrides.allowed_roles {
+            settings.allowed_roles = Some(roles);
+        }
+        if let Some(prefix) = overrides.name_prefix {
+            settings.name_prefix = prefix;
+        }
+        if let Some(length) = overrides.name_max_length {
+            settings.name_max_length = length;
+        }
+        if let Some(format) = overrides.default_output_format {
+            settings.default_output_format = format;
+        }
+        if let Some(validate) = overrides.validate_kubeconfig {
+            settings.validate = validate;
+        }
+        if let Some(webhooks) = overrides.webhooks {
+            settings.webhooks = webhooks;
+        }
+
+        settings.validate_settings()?;
+
+        Ok(settings)
     }
 
-    /// Attempts to fetch the cluster url from the environment
-    fn from_env() -> String {
-        env::var("CLUSTER_URL").unwrap_or("".to_string())
+    /// Discovers the cluster url and CA
+    pub async fn discover(&mut self, client: &Client) -> Result<()> {
+        let chain = discovery::chain(&self.discovery_order, self)?;
+        let discovered = discovery::discover(client, &chain).await?;
+
+        // Without the CA ConfigMap, the CA of each token Secret is used instead
+        self.ca = discovery::ca(client, &self.namespace)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to read the cluster CA: {}", e);
+                None
+            });
+
+        tracing::info!(
+            "Detected URL: {} via {} and namespace: {}",
+            discovered.url,
+            discovered.provider,
+            self.namespace
+        );
+
+        self.url = discovered.url;
+        self.url_provider = discovered.provider;
+
+        Ok(())
+    }
+
+    /// The effective settings, safe to log
+    pub fn redacted(&self) -> serde_json::Value {
+        json!({
+            "clusterUrl": self.cluster_url.as_deref().map(redact_url),
+            "clusterName": self.cluster_name,
+            "discoveryOrder": self.discovery_order,
+            "discoveryIntervalSeconds": self.discovery_interval.as_secs(),
+            "regenerateKubeconfigs": self.regenerate_kubeconfigs,
+            "namespace": self.namespace,
+            "validateKubeconfig": self.validate,
+            "tokenTimeoutSeconds": self.token_timeout.as_secs(),
+            "namePrefix": self.name_prefix,
+            "nameMaxLength": self.name_max_length,
+            "sweepIntervalSeconds": self.sweep_interval.as_secs(),
+            "sweepDryRun": self.sweep_dry_run,
+            "driftPolicy": format!("{:?}", self.drift_policy).to_lowercase(),
+            "scanIntervalSeconds": self.scan_interval.as_secs(),
+            "shutdownTimeoutSeconds": self.shutdown_timeout.as_secs(),
+            "retryMaxAttempts": self.retry_max_attempts,
+            "retryBackoffSeconds": self.retry_backoff.as_secs(),
+            "metricsPort": self.metrics_port,
+            "expireMinutes": self.expire_after.as_secs() / 60,
+            "maxExpireMinutes": self.max_expire_after.map(|max| max.as_secs() / 60),
+            "allowedRoles": self.allowed_roles,
+            "defaultOutputFormat": self.default_output_format,
+            "configMap": self.config_map,
+            "apiPort": self.api_port,
+            "apiTlsCert": self.api_tls_cert,
+            "apiTlsKey": self.api_tls_key,
+            "apiAllowedGroups": self.api_allowed_groups,
+            "apiApproverGroups": self.api_approver_groups,
+            "apiUrl": self.api_url,
+            "oidcIssuerUrl": self.oidc_issuer_url,
+            "oidcClientId": self.oidc_client_id,
+            "oidcClientSecret": self.oidc_client_secret.as_ref().map(|_| "REDACTED"),
+            "oidcRedirectUrl": self.oidc_redirect_url,
+            "webhooks": self.webhooks.iter().map(Webhook::redacted).collect::<Vec<_>>(),
+        })
     }
 
     /// Getter for URL
@@ -98,13 +645,217 @@ impl KufefeConfig {
         self.url.clone()
     }
 
+    /// Getter for the provider the URL was discovered by
+    pub fn url_provider(&self) -> String {
+        self.url_provider.clone()
+    }
+
+    /// Getter for the cluster CA, if it was found
+    pub fn ca(&self) -> Option<String> {
+        self.ca.clone()
+    }
+
+    /// Getter for the explicitly configured cluster URL
+    pub fn cluster_url(&self) -> Option<String> {
+        self.cluster_url.clone()
+    }
+
+    /// Getter for the name of the GKE/Anthos, EKS or AKS cluster
+    pub fn cluster_name(&self) -> Option<String> {
+        self.cluster_name.clone()
+    }
+
+    /// Getter for the order cluster URL providers are tried in
+    pub fn discovery_order(&self) -> &[String] {
+        &self.discovery_order
+    }
+
+    /// Getter for the interval between re-discoveries of the cluster URL and CA
+    pub fn discovery_interval(&self) -> Duration {
+        self.discovery_interval
+    }
+
+    /// Getter for whether issued kubeconfigs are rewritten when the endpoint changes
+    pub fn regenerate_kubeconfigs(&self) -> bool {
+        self.regenerate_kubeconfigs
+    }
+
     /// Getter for namespace
     pub fn namespace(&self) -> String {
         self.namespace.clone()
     }
 
-    /// Getter for client
-    pub fn client(&self) -> Client {
-        self.client.clone()
+    /// Getter for whether issued kubeconfigs are validated
+    pub fn validate(&self) -> bool {
+        self.validate
+    }
+
+    /// Getter for how long to wait for the token controller
+    pub fn token_timeout(&self) -> Duration {
+        self.token_timeout
+    }
+
+    /// Getter for the prefix of generated resource names
+    pub fn name_prefix(&self) -> String {
+        self.name_prefix.clone()
+    }
+
+    /// Getter for the maximum length of generated resource names
+    pub fn name_max_length(&self) -> usize {
+        self.name_max_length
+    }
+
+    /// Getter for the interval between sweeps for orphaned resources
+    pub fn sweep_interval(&self) -> Duration {
+        self.sweep_interval
+    }
+
+    /// Getter for whether orphaned resources are only reported
+    pub fn sweep_dry_run(&self) -> bool {
+        self.sweep_dry_run
+    }
+
+    /// Getter for how drift of generated resources is handled
+    pub fn drift_policy(&self) -> DriftPolicy {
+        self.drift_policy
+    }
+
+    /// Getter for the interval between full scans for expired Requests
+    pub fn scan_interval(&self) -> Duration {
+        self.scan_interval
+    }
+
+    /// Getter for how long in-flight reconciles may take during shutdown
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+
+    /// Getter for how many times provisioning a Request is attempted
+    pub fn retry_max_attempts(&self) -> u32 {
+        self.retry_max_attempts
+    }
+
+    /// Getter for the initial delay between attempts, doubled on each attempt
+    pub fn retry_backoff(&self) -> Duration {
+        self.retry_backoff
+    }
+
+    /// Getter for the port metrics are served on
+    pub fn metrics_port(&self) -> u16 {
+        self.metrics_port
+    }
+
+    /// Getter for how long Requests are valid
+    pub fn expire_after(&self) -> Duration {
+        self.expire_after
+    }
+
+    /// Getter for the longest duration a Request may ask for
+    pub fn max_expire_after(&self) -> Option<Duration> {
+        self.max_expire_after
+    }
+
+    /// Getter for the longest duration a Request may ask for, which is bounded
+    /// even without maxExpireMinutes
+    pub fn duration_limit(&self) -> Duration {
+        self.max_expire_after
+            .unwrap_or_else(|| minutes(MAX_DURATION_MINUTES))
+    }
+
+    /// Getter for the roles Requests may use, if restricted
+    pub fn allowed_roles(&self) -> Option<&[String]> {
+        self.allowed_roles.as_deref()
+    }
+
+    /// Getter for the output format of Requests that don't ask for one
+    pub fn default_output_format(&self) -> OutputFormat {
+        self.default_output_format
+    }
+
+    /// Getter for the name of the ConfigMap settings are reloaded from
+    pub fn config_map(&self) -> Option<String> {
+        self.config_map.clone()
+    }
+
+    /// Getter for the port the self-service API is served on, if enabled
+    pub fn api_port(&self) -> Option<u16> {
+        self.api_port
+    }
+
+    /// Getter for the certificate chain and key of the self-service API
+    pub fn api_tls(&self) -> Option<(PathBuf, PathBuf)> {
+        Some((self.api_tls_cert.clone()?, self.api_tls_key.clone()?))
+    }
+
+    /// Getter for the groups allowed to use the self-service API, if restricted
+    pub fn api_allowed_groups(&self) -> Option<&[String]> {
+        self.api_allowed_groups.as_deref()
+    }
+
+    /// Getter for the groups whose members may approve Requests, if any
+    pub fn api_approver_groups(&self) -> Option<&[String]> {
+        self.api_approver_groups.as_deref()
+    }
+
+    /// Getter for the URL users reach the self-service API at, if it is known
+    pub fn api_url(&self) -> Option<String> {
+        self.api_url.clone()
+    }
+
+    /// Getter for the issuer and client ID of accepted OIDC ID tokens
+    pub fn oidc(&self) -> Option<(String, String)> {
+        Some((self.oidc_issuer_url.clone()?, self.oidc_client_id.clone()?))
+    }
+
+    /// Getter for the redirect URL of the web UI, if it is served
+    pub fn oidc_redirect_url(&self) -> Option<String> {
+        self.oidc_redirect_url.clone()
+    }
+
+    /// Getter for the client secret the web UI logs in with
+    pub fn oidc_client_secret(&self) -> Option<String> {
+        self.oidc_client_secret.clone()
+    }
+
+    /// Getter for the webhooks told about Requests
+    pub fn webhooks(&self) -> &[Webhook] {
+        &self.webhooks
+    }
+}
+
+/// Strips credentials from a URL
+fn redact_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let end = rest.find('/').unwrap_or(rest.len());
+
+    match rest[..end].rsplit_once('@') {
+        Some((_, host)) => format!("{}://REDACTED@{}{}", scheme, host, &rest[end..]),
+        None => url.to_string(),
+    }
+}
+
+/// Converts minutes to a duration, saturating as oversized values are rejected anyway
+fn minutes(minutes: u64) -> Duration {
+    Duration::from_secs(minutes.saturating_mul(60))
+}
+
+#[cfg(test)]
+impl KufefeConfig {
+    /// Default settings for tests, without validation as the fake API can't authenticate
+    pub fn for_tests(namespace: &str) -> Self {
+        let mut settings = Self::load(ConfigArgs {
+            namespace: Some(namespace.to_string()),
+            validate_kubeconfig: Some(false),
+            token_timeout_seconds: Some(5),
+            retry_max_attempts: Some(1),
+            ..ConfigArgs::default()
+        })
+        .unwrap();
+
+        settings.url = "https://kubernetes.test:6443".to_string();
+        settings.url_provider = "config".to_string();
+        settings
     }
 }
@@ -0,0 +1,174 @@
+use crate::{config::KufefeConfig, metrics::Metrics};
+use k8s_openapi::api::core::v1::ObjectReference;
+use kube::runtime::events::{Recorder, Reporter};
+use kube::Client;
+use std::sync::{Arc, RwLock};
+
+/// Where issued kubeconfigs point at, which may change while running
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub url: String,
+    pub provider: String,
+    pub ca: Option<String>,
+}
+
+/// The settings in use, numbered so reloads can be told apart
+struct Settings {
+    generation: u64,
+    config: Arc<KufefeConfig>,
+}
+
+/// Everything a reconcile needs, passed explicitly instead of read from a global
+#[derive(Clone)]
+pub struct Context {
+    client: Client,
+    hub: Client,
+    cluster: Option<String>,
+    namespace: String,
+    endpoint: Arc<RwLock<Endpoint>>,
+    settings: Arc<RwLock<Settings>>,
+    reporter: Reporter,
+    metrics: Arc<Metrics>,
+}
+
+impl Context {
+    /// Creates a Context for the cluster the client talks to
+    pub fn new(client: Client, settings: KufefeConfig) -> Self {
+        let metrics = Metrics::default();
+        metrics.config_generation(1);
+
+        Self {
+            hub: client.clone(),
+            client,
+            cluster: None,
+            namespace: settings.namespace(),
+            endpoint: Arc::new(RwLock::new(Endpoint {
+                url: settings.url(),
+                provider: settings.url_provider(),
+                ca: settings.ca(),
+            })),
+            settings: Arc::new(RwLock::new(Settings {
+                generation: 1,
+                config: Arc::new(settings),
+            })),
+            reporter: Reporter {
+                controller: "kufefe".to_string(),
+                instance: std::env::var("HOSTNAME").ok(),
+            },
+            metrics: Arc::new(metrics),
+        }
+    }
+
+    /// Creates a Context provisioning in a target cluster, while Requests are
+    /// still tracked in the cluster Kufefe runs in
+    pub fn target(
+        &self,
+        cluster: &str,
+        client: Client,
+        namespace: String,
+        endpoint: Endpoint,
+    ) -> Self {
+        Self {
+            client,
+            cluster: Some(cluster.to_string()),
+            namespace,
+            endpoint: Arc::new(RwLock::new(endpoint)),
+            ..self.clone()
+        }
+    }
+
+    /// Getter for the client of the cluster resources are provisioned in
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// Getter for the client of the cluster Requests live in
+    pub fn hub(&self) -> Client {
+        self.hub.clone()
+    }
+
+    /// Getter for the target cluster, if resources aren't provisioned locally
+    pub fn cluster(&self) -> Option<String> {
+        self.cluster.clone()
+    }
+
+    /// Getter for the name labelling what this hub provisions in target clusters,
+    /// which is the cluster name if it makes a valid label value
+    pub fn hub_name(&self) -> String {
+        self.settings()
+            .cluster_name()
+            .filter(|name| {
+                !name.is_empty()
+                    && name.len() <= 63
+                    && name.trim_matches(|c: char| !c.is_ascii_alphanumeric()) == name
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+            })
+            .unwrap_or_else(|| "kufefe".to_string())
+    }
+
+    /// Getter for the namespace generated resources live in
+    pub fn namespace(&self) -> String {
+        self.namespace.clone()
+    }
+
+    /// Getter for the endpoint written to issued kubeconfigs
+    pub fn endpoint(&self) -> Endpoint {
+        self.endpoint.read().unwrap().clone()
+    }
+
+    /// Getter for the cluster URL written to issued kubeconfigs
+    pub fn url(&self) -> String {
+        self.endpoint.read().unwrap().url.clone()
+    }
+
+    /// Getter for the provider the cluster URL was discovered by
+    pub fn url_provider(&self) -> String {
+        self.endpoint.read().unwrap().provider.clone()
+    }
+
+    /// Getter for the cluster CA, if it was found
+    pub fn ca(&self) -> Option<String> {
+        self.endpoint.read().unwrap().ca.clone()
+    }
+
+    /// Replaces the endpoint, returning whether the URL or CA changed
+    pub fn set_endpoint(&self, endpoint: Endpoint) -> bool {
+        let mut current = self.endpoint.write().unwrap();
+        let changed = current.url != endpoint.url || current.ca != endpoint.ca;
+        *current = endpoint;
+
+        changed
+    }
+
+    /// Getter for the settings currently in use
+    pub fn settings(&self) -> Arc<KufefeConfig> {
+        self.settings.read().unwrap().config.clone()
+    }
+
+    /// Getter for the generation of the settings currently in use
+    pub fn generation(&self) -> u64 {
+        self.settings.read().unwrap().generation
+    }
+
+    /// Replaces the settings, returning the new generation
+    pub fn set_settings(&self, config: KufefeConfig) -> u64 {
+        let mut settings = self.settings.write().unwrap();
+        settings.generation += 1;
+        settings.config = Arc::new(config);
+        self.metrics.config_generation(settings.generation);
+
+        settings.generation
+    }
+
+    /// Creates an event recorder for the referenced object
+    pub fn recorder(&self, reference: ObjectReference) -> Recorder {
+        Recorder::new(self.hub(), self.reporter.clone(), reference)
+    }
+
+    /// Getter for metrics
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+}
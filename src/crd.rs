@@ -1,51 +1,231 @@
-use crate::traits::{api::ApiResource, delete::DeleteOpt, expire::Expire};
-use crate::{status_update, CONFIG};
-use anyhow::{bail, Result};
+use crate::notify::{notify, Notification};
+use crate::traits::{delete::DeleteOpt, expire::Expire};
+use crate::{context::Context, error::Error, error::Result, status_update};
+use clap::ValueEnum;
 use kube::api::{DeleteParams, ListParams};
 use kube::{
     api::{Api, PostParams},
-    ResourceExt,
+    CustomResourceExt, ResourceExt,
 };
 use kube_derive::CustomResource;
-use schemars::JsonSchema;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 #[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema, Default)]
 #[kube(
     group = "kufefe.io",
     version = "v1",
     kind = "Request",
-    status = "RequestStatus"
+    status = "RequestStatus",
+    shortname = "rq",
+    shortname = "req",
+    printcolumn = r#"{"name": "READY", "type": "boolean", "jsonPath": ".status.ready"}"#,
+    printcolumn = r#"{"name": "FAILED", "type": "boolean", "jsonPath": ".status.failed"}"#,
+    printcolumn = r#"{"name": "CLUSTER", "type": "string", "jsonPath": ".spec.cluster"}"#,
+    printcolumn = r#"{"name": "AGE", "type": "date", "jsonPath": ".metadata.creationTimestamp"}"#
 )]
 #[serde(rename_all = "camelCase")]
 pub struct RequestSpec {
+    /// The role to be assigned to the user
+    #[schemars(schema_with = "role")]
     pub role: String,
+    /// Additional format to render the kubeconfig in
+    #[serde(default)]
+    #[schemars(schema_with = "immutable::<Option<OutputFormat>>")]
+    pub output_format: Option<OutputFormat>,
+    /// Whether the kubeconfig embeds a token or fetches tokens through the exec plugin
+    #[serde(default)]
+    #[schemars(schema_with = "immutable::<Option<CredentialMode>>")]
+    pub credential_mode: Option<CredentialMode>,
+    /// Registered target cluster to issue the kubeconfig for, defaults to this cluster
+    #[serde(default)]
+    #[schemars(schema_with = "immutable::<Option<String>>")]
+    pub cluster: Option<String>,
+    /// How long the request is valid in minutes, defaults to the configured expiry
+    #[serde(default)]
+    #[schemars(schema_with = "duration")]
+    pub duration_minutes: Option<u64>,
+}
+
+/// Annotation holding the namespace the issued kubeconfig should default to
+pub const NAMESPACE_ANNOTATION: &str = "kufefe.io/namespace";
+
+/// Reason of the Ready condition while a Request waits for someone to approve it
+pub const APPROVAL_REQUIRED: &str = "ApprovalRequired";
+
+/// Adds CEL validation rules to a schema
+fn validated(schema: Schema, rules: &[(&str, &str)]) -> Schema {
+    let mut schema = schema.into_object();
+    let rules = rules
+        .iter()
+        .map(|(rule, message)| json!({ "rule": rule, "message": message }))
+        .collect();
+
+    schema
+        .extensions
+        .insert("x-kubernetes-validations".to_string(), rules);
+
+    schema.into()
+}
+
+/// Schema of a field that can't be changed once set
+fn immutable<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    validated(
+        gen.subschema_for::<T>(),
+        &[("self == oldSelf", "Value is immutable")],
+    )
+}
+
+/// Schema of a generated name, which can't be changed once assigned
+fn assigned(gen: &mut SchemaGenerator) -> Schema {
+    validated(
+        gen.subschema_for::<String>(),
+        &[(
+            "oldSelf == '' || self == oldSelf",
+            "Value is immutable once assigned",
+        )],
+    )
+}
+
+/// Schema of the expiry, which can only be postponed once set
+fn postponable(gen: &mut SchemaGenerator) -> Schema {
+    validated(
+        gen.subschema_for::<Option<i64>>(),
+        &[("self >= oldSelf", "Expiry can only be postponed")],
+    )
+}
+
+/// Schema of the role, which must be set and can't be changed
+fn role(gen: &mut SchemaGenerator) -> Schema {
+    validated(
+        gen.subschema_for::<String>(),
+        &[
+            ("self == oldSelf", "Value is immutable"),
+            ("size(self) > 0", "Role must not be empty"),
+        ],
+    )
+}
+
+/// Longest duration in minutes a Request can ask for, ten years, which keeps
+/// expiry timestamps far from overflowing
+pub const MAX_DURATION_MINUTES: u64 = 10 * 365 * 24 * 60;
+
+/// Schema of the duration, which must be positive, bounded and can't be changed
+fn duration(gen: &mut SchemaGenerator) -> Schema {
+    let mut schema = gen.subschema_for::<Option<u64>>().into_object();
+    schema.number().maximum = Some(MAX_DURATION_MINUTES as f64);
+
+    validated(
+        schema.into(),
+        &[
+            ("self == oldSelf", "Value is immutable"),
+            ("self > 0", "Duration must be positive"),
+        ],
+    )
+}
+
+#[derive(
+    Serialize, Deserialize, Debug, PartialEq, Clone, Copy, JsonSchema, Default, ValueEnum,
+)]
+#[serde(rename_all = "camelCase")]
+#[value(rename_all = "camelCase")]
+pub enum OutputFormat {
+    #[default]
+    Yaml,
+    Json,
+    Env,
+    ExecCredential,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum CredentialMode {
+    /// Embed the ServiceAccount token in the kubeconfig
+    #[default]
+    Token,
+    /// Fetch short-lived tokens on demand through `kufefe credential`
+    Exec,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct RequestStatus {
+    /// The generated name for the ServiceAccount
+    #[schemars(schema_with = "assigned")]
     pub service_account_name: String,
+    /// The generated name for the ServiceAccount Token
+    #[schemars(schema_with = "assigned")]
     pub token_name: String,
+    /// The generated name for the ClusterRoleBinding
+    #[schemars(schema_with = "assigned")]
     pub rolebinding_name: String,
+    /// The kubeconfig for the user
     pub kubeconfig: Option<String>,
+    /// The kubeconfig rendered in the requested output format
+    pub output: Option<String>,
+    /// True if the request has been fulfilled
     pub ready: bool,
+    /// True if the request has failed
     pub failed: bool,
+    /// Latest known status for the request
     pub message: String,
+    /// Timestamp when the request expires
+    #[serde(default)]
+    #[schemars(schema_with = "postponable")]
     pub expires_at: Option<i64>,
+    /// Summary of the rules the issued kubeconfig was verified to have
+    pub effective_rules: Option<Vec<String>>,
+    /// Latest observations of the state of the request
+    pub conditions: Option<Vec<RequestCondition>>,
+    /// Number of attempts made to provision the request
+    pub attempts: Option<u32>,
+    /// Timestamp of the next attempt, if one is scheduled
+    pub retry_at: Option<i64>,
+    /// The error of the latest failed attempt
+    pub last_error: Option<String>,
+    /// Value of the kufefe.io/retry annotation that was last acted upon
+    pub retry_annotation: Option<String>,
+    /// The cluster URL written to the kubeconfig
+    pub cluster_url: Option<String>,
+    /// The provider the cluster URL was discovered by
+    pub cluster_url_provider: Option<String>,
+    /// Who approved the request, for roles that require approval
+    #[serde(default)]
+    #[schemars(schema_with = "immutable::<Option<String>>")]
+    pub approved_by: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestCondition {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub status: String,
+    pub reason: String,
+    pub message: String,
+    pub last_transition_time: Option<String>,
+}
+
+/// Renders the CustomResourceDefinition for Requests as YAML
+pub fn manifest() -> String {
+    serde_yaml::to_string(&Request::crd()).expect("Failed to serialize CRD")
 }
 
 impl RequestStatus {
     /// Creates a new ResourceStatus object but tries to find an existing one
-    pub async fn new(resource: &Request) -> Self {
-        let api = resource.get_api();
+    pub async fn new(ctx: &Context, resource: &Request) -> Self {
+        let api = Request::api(ctx);
 
         match api.get_status(&resource.name_any()).await {
-            Ok(res) => {
+            Ok(Request {
+                status: Some(status),
+                ..
+            }) => {
                 tracing::debug!("Status found for {}", resource.name_any());
-                res.status.unwrap()
+                status
             }
-            Err(_) => {
+            _ => {
                 tracing::debug!(
                     "Failed to find status for {}. Falling back to defaults.",
                     resource.name_any()
@@ -60,10 +240,18 @@ impl RequestStatus {
     }
 
     /// Update status of the CRD
-    pub async fn update(&self, resource: &Request) -> Result<()> {
-        let api = resource.get_api();
+    pub async fn update(&self, ctx: &Context, resource: &Request) -> Result<()> {
+        let api = Request::api(ctx);
 
-        let mut status = api.get_status(&resource.name_any()).await?;
+        let to_error = |source| Error::Status {
+            request: resource.name_any(),
+            source: Box::new(source),
+        };
+
+        let mut status = api
+            .get_status(&resource.name_any())
+            .await
+            .map_err(to_error)?;
         status.status = Some(RequestStatus {
             ..resource.status.clone().unwrap()
         });
@@ -81,18 +269,36 @@ impl RequestStatus {
                 Ok(())
             }
             Err(e) => {
-                tracing::error!(
-                    "Failed to update status for {}: {}",
-                    resource.name_any(),
-                    e
-                );
-                bail!(e)
+                let e = to_error(e);
+                tracing::error!("{}", e);
+
+                Err(e)
             }
         }
     }
 }
 
 impl Request {
+    /// Gets the API for Requests in the cluster of the Context
+    pub fn api(ctx: &Context) -> Api<Request> {
+        Api::all(ctx.hub())
+    }
+
+    /// Prefix for the generated names of Requests for a role
+    pub fn name_prefix(role: &str) -> String {
+        let name: String = role
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .take(50)
+            .collect();
+
+        match name.trim_matches('-') {
+            "" => "request-".to_string(),
+            name => format!("{}-", name),
+        }
+    }
+
     /// Creates a mock object
     pub fn mock() -> Self {
         Self {
@@ -103,24 +309,16 @@ impl Request {
     }
 
     /// Scan for expired Requests
-    pub async fn scan(&self) {
+    pub async fn scan(&self, ctx: &Context) {
         tracing::info!("Scanning for expired requests");
 
-        let api = &self.get_api().clone();
+        let api = Request::api(ctx);
 
         match api.list(&ListParams::default()).await {
             Ok(requests) => {
                 for request in &requests.items {
                     if self.is_expired(request) {
-                        tracing::info!("Deleting expired request {}", request.name_any());
-
-                        if let Err(err) = api
-                            .delete_opt(&request.name_any(), &DeleteParams::default())
-                            .await
-                        {
-                            tracing::error!("Failed to delete request: {}", err);
-                            continue;
-                        }
+                        self.delete_expired(ctx, request).await;
                     }
                 }
             }
@@ -130,11 +328,25 @@ impl Request {
         }
     }
 
+    /// Deletes an expired Request
+    pub async fn delete_expired(&self, ctx: &Context, request: &Request) {
+        tracing::info!("Deleting expired request {}", request.name_any());
+
+        match Request::api(ctx)
+            .delete_opt(&request.name_any(), &DeleteParams::default())
+            .await
+        {
+            Ok(Some(_)) => notify(ctx, Notification::Expired, request),
+            Ok(None) => {}
+            Err(err) => tracing::error!("Failed to delete request: {}", err),
+        }
+    }
+
     /// Checks if the object is expired
     pub fn is_expired(&self, request: &Request) -> bool {
         if let Some(status) = &request.status {
             if let Some(expires_at) = status.expires_at {
-                if expires_at < chrono::Utc::now().timestamp() {
+                if expires_at <= chrono::Utc::now().timestamp() {
                     return true;
                 }
             }
@@ -144,16 +356,17 @@ impl Request {
     }
 
     /// Updates the status of the CRD
-    pub async fn update_status(&mut self) -> Result<&mut Self> {
-        if let Some(status) = &self.status {
-            if let Err(err) = status.update(self).await {
-                bail!("Failed to update status: {}", err);
+    pub async fn update_status(&mut self, ctx: &Context) -> Result<&mut Self> {
+        match &self.status {
+            Some(status) => status.update(ctx, self).await?,
+            None => {
+                return Err(Error::MissingStatus {
+                    request: self.name_any(),
+                })
             }
-
-            return Ok(self);
         }
 
-        bail!("")
+        Ok(self)
     }
 
     status_update!(ready, ready: bool);
@@ -162,6 +375,18 @@ impl Request {
 
     status_update!(message, message: String);
 
+    status_update!(attempts, attempts: Option<u32>, retry_at: Option<i64>);
+
+    status_update!(last_error, last_error: Option<String>);
+
+    status_update!(retry_annotation, retry_annotation: Option<String>);
+
+    status_update!(
+        cluster,
+        cluster_url: Option<String>,
+        cluster_url_provider: Option<String>
+    );
+
     status_update!(
         account_names,
         service_account_name: String,
@@ -192,16 +417,91 @@ impl Request {
 
         self
     }
-}
 
-impl ApiResource for Request {
-    type ApiType = Request;
+    /// Sets the summary of the effective rules of the issued kubeconfig
+    pub fn effective_rules(&mut self, effective_rules: Vec<String>) -> &mut Self {
+        if let Some(status) = self.status.take() {
+            self.status = Some(RequestStatus {
+                effective_rules: Some(effective_rules),
+                ..status
+            });
+        }
+
+        self
+    }
+
+    /// Sets a condition, keeping its transition time if the status is unchanged
+    pub fn condition(
+        &mut self,
+        type_: &str,
+        status: bool,
+        reason: &str,
+        message: String,
+    ) -> &mut Self {
+        if let Some(mut request_status) = self.status.take() {
+            let mut conditions = request_status.conditions.take().unwrap_or_default();
+            let status = if status { "True" } else { "False" }.to_string();
 
-    fn get_api(&self) -> Api<Self::ApiType> {
-        let client = CONFIG.get().unwrap().client();
-        let api: Api<Request> = Api::all(client);
+            let last_transition_time = match conditions.iter().find(|c| c.type_ == type_)
+            {
+                Some(c) if c.status == status => c.last_transition_time.clone(),
+                _ => Some(chrono::Utc::now().to_rfc3339()),
+            };
 
-        api
+            conditions.retain(|c| c.type_ != type_);
+            conditions.push(RequestCondition {
+                type_: type_.to_string(),
+                status,
+                reason: reason.to_string(),
+                message,
+                last_transition_time,
+            });
+
+            self.status = Some(RequestStatus {
+                conditions: Some(conditions),
+                ..request_status
+            });
+        }
+
+        self
+    }
+
+    /// Checks if the Request waits for someone to approve it
+    pub fn awaiting_approval(&self) -> bool {
+        self.held_for_approval() && !self.approved()
+    }
+
+    /// Checks if the Request was approved, but isn't provisioned yet
+    pub fn approval_granted(&self) -> bool {
+        self.held_for_approval() && self.approved()
+    }
+
+    /// Checks if someone approved the Request
+    pub fn approved(&self) -> bool {
+        self.status
+            .as_ref()
+            .is_some_and(|s| s.approved_by.is_some())
+    }
+
+    /// Checks if the Request was held back until someone approves it
+    fn held_for_approval(&self) -> bool {
+        self.status.as_ref().is_some_and(|s| {
+            !s.ready
+                && !s.failed
+                && s.conditions
+                    .iter()
+                    .flatten()
+                    .any(|c| c.type_ == "Ready" && c.reason == APPROVAL_REQUIRED)
+        })
+    }
+
+    /// Sets the kubeconfig rendered in the requested output format
+    pub fn output(&mut self, output: Option<String>) -> &mut Self {
+        if let Some(status) = self.status.take() {
+            self.status = Some(RequestStatus { output, ..status });
+        }
+
+        self
     }
 }
 
@@ -0,0 +1,113 @@
+use crate::{api::requests::Token, cli::CredentialArgs, kubeconfig::ExecCredential};
+use anyhow::{bail, Result};
+use hyper::{Body, Method};
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::{Client, Config};
+use openssl::x509::X509;
+use std::{env, path::Path};
+
+/// Environment variable the plugin sets on itself to detect recursive invocations
+const PLUGIN_ENV: &str = "KUFEFE_CREDENTIAL_PLUGIN";
+
+/// Fetches a fresh token for the Request from the self-service API and prints it
+/// as an ExecCredential
+pub async fn run(args: CredentialArgs) -> Result<()> {
+    // The plugin itself must authenticate with a different user, otherwise
+    // building the client would invoke this plugin again.
+    if env::var_os(PLUGIN_ENV).is_some_and(|v| v == "active") {
+        bail!("Recursive invocation detected. Set KUFEFE_CONTEXT to a context that does not use the Kufefe exec plugin");
+    }
+    env::set_var(PLUGIN_ENV, "active");
+
+    let kubeconfig = Kubeconfig::read()?;
+    let context = args
+        .context
+        .clone()
+        .or_else(|| kubeconfig.current_context.clone());
+
+    if let Some(context) = context.as_deref().filter(|c| uses_plugin(&kubeconfig, c)) {
+        bail!("Context {} authenticates through Kufefe itself. Set KUFEFE_CONTEXT to the context the Request was made with", context);
+    }
+
+    let options = KubeConfigOptions {
+        context,
+        ..KubeConfigOptions::default()
+    };
+    let mut config = Config::from_custom_kubeconfig(kubeconfig, &options).await?;
+
+    // Talk to the self-service API instead, as the user of the context
+    config.cluster_url = args.api_url.parse()?;
+    config.root_cert = match &args.api_ca {
+        Some(path) => Some(certificates(path)?),
+        None => None,
+    };
+    config.tls_server_name = None;
+    config.accept_invalid_certs = false;
+    let client = Client::try_from(config)?;
+
+    // The API checks the Request is ours and hasn't expired before minting a token
+    let request = hyper::Request::builder()
+        .method(Method::POST)
+        .uri(format!(
+            "/api/v1/requests/{}/token?ttlSeconds={}",
+            args.request, args.ttl_seconds
+        ))
+        .body(Body::empty())?;
+    let response = client.send(request).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+
+    if !status.is_success() {
+        let message = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|error| error["message"].as_str().map(String::from))
+            .unwrap_or_else(|| status.to_string());
+        bail!(
+            "Failed to fetch a token for Request {}: {}",
+            args.request,
+            message
+        );
+    }
+
+    let token: Token = serde_json::from_slice(&body)?;
+
+    println!(
+        "{}",
+        ExecCredential::new(token.token, Some(token.expires_at)).to_json()?
+    );
+
+    Ok(())
+}
+
+/// Checks if a context authenticates through this plugin, which would recurse
+fn uses_plugin(kubeconfig: &Kubeconfig, context: &str) -> bool {
+    let user = kubeconfig
+        .contexts
+        .iter()
+        .find(|c| c.name == context)
+        .and_then(|c| c.context.as_ref())
+        .map(|c| c.user.as_str());
+
+    kubeconfig
+        .auth_infos
+        .iter()
+        .filter(|u| Some(u.name.as_str()) == user)
+        .filter_map(|u| u.auth_info.as_ref()?.exec.as_ref())
+        .any(|exec| {
+            exec.command
+                .as_deref()
+                .is_some_and(|c| c.ends_with("kufefe"))
+                && exec
+                    .args
+                    .as_ref()
+                    .is_some_and(|a| a.first().is_some_and(|a| a == "credential"))
+        })
+}
+
+/// Reads a PEM bundle into the DER certificates the client trusts
+fn certificates(path: &Path) -> Result<Vec<Vec<u8>>> {
+    X509::stack_from_pem(&std::fs::read(path)?)?
+        .iter()
+        .map(|cert| Ok(cert.to_der()?))
+        .collect()
+}
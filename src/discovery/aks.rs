@@ -0,0 +1,41 @@
+use super::{clusters, pick, Provider};
+use anyhow::Result;
+use async_trait::async_trait;
+use kube::Client;
+
+/// The FQDN of the AKS ManagedCluster managed by the Azure Service Operator
+pub struct Aks {
+    pub cluster_name: Option<String>,
+}
+
+#[async_trait]
+impl Provider for Aks {
+    fn name(&self) -> &'static str {
+        "aks"
+    }
+
+    async fn discover(&self, client: &Client) -> Result<Option<String>> {
+        let clusters =
+            match clusters(client, "containerservice.azure.com", "ManagedCluster").await?
+            {
+                Some(clusters) => clusters,
+                None => return Ok(None),
+            };
+
+        Ok(
+            pick(&clusters, self.cluster_name.as_deref())?.and_then(|cluster| {
+                let status = &cluster.data["status"];
+
+                // Private clusters are only reachable through their private FQDN
+                let fqdn = match status["apiServerAccessProfile"]["enablePrivateCluster"]
+                    .as_bool()
+                {
+                    Some(true) => status["privateFQDN"].as_str(),
+                    _ => status["fqdn"].as_str(),
+                };
+
+                fqdn.map(|fqdn| format!("https://{}:443", fqdn))
+            }),
+        )
+    }
+}
@@ -0,0 +1,48 @@
+use super::Provider;
+use crate::resources::gke::cluster::Cluster;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use kube::{api::ListParams, error::ErrorResponse, Api, Client};
+
+/// The GKE/Anthos kind: Cluster, disambiguated by clusterName
+pub struct Anthos {
+    pub cluster_name: Option<String>,
+}
+
+#[async_trait]
+impl Provider for Anthos {
+    fn name(&self) -> &'static str {
+        "anthos"
+    }
+
+    async fn discover(&self, client: &Client) -> Result<Option<String>> {
+        tracing::info!("Attempting to find GKE/Anthos kind: Cluster");
+
+        let api: Api<Cluster> = Api::namespaced(client.clone(), "default");
+
+        let list = match api.list(&ListParams::default()).await {
+            Ok(list) => list,
+            Err(kube::Error::Api(ErrorResponse { code: 404, .. })) => return Ok(None),
+            Err(e) => bail!("Failed to find a cluster: {}", e),
+        };
+
+        // If there's multiple clusters, defer to the cluster name.
+        let cluster = if list.items.len() > 1 {
+            let cluster_name = match &self.cluster_name {
+                Some(name) => name,
+                None => bail!("Found more than one cluster. Please specify clusterName"),
+            };
+
+            list.items
+                .iter()
+                .find(|c| c.metadata.name.as_ref() == Some(cluster_name))
+        } else {
+            list.items.first()
+        };
+
+        Ok(cluster
+            .and_then(|c| c.status.as_ref())
+            .and_then(|s| s.api_endpoints.first())
+            .map(|e| format!("https://{}:{}", e.host, e.port)))
+    }
+}
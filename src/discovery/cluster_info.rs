@@ -0,0 +1,25 @@
+use super::{server, Provider};
+use anyhow::Result;
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client};
+
+/// The kubeconfig published in kube-public/cluster-info by kubeadm and others
+pub struct ClusterInfo;
+
+#[async_trait]
+impl Provider for ClusterInfo {
+    fn name(&self) -> &'static str {
+        "cluster-info"
+    }
+
+    async fn discover(&self, client: &Client) -> Result<Option<String>> {
+        let api: Api<ConfigMap> = Api::namespaced(client.clone(), "kube-public");
+
+        Ok(api
+            .get_opt("cluster-info")
+            .await?
+            .and_then(|c| c.data)
+            .and_then(|d| d.get("kubeconfig").and_then(|k| server(k))))
+    }
+}
@@ -0,0 +1,20 @@
+use super::Provider;
+use anyhow::Result;
+use async_trait::async_trait;
+use kube::Client;
+
+/// The URL set explicitly through clusterUrl
+pub struct Configured {
+    pub url: Option<String>,
+}
+
+#[async_trait]
+impl Provider for Configured {
+    fn name(&self) -> &'static str {
+        "config"
+    }
+
+    async fn discover(&self, _client: &Client) -> Result<Option<String>> {
+        Ok(self.url.clone())
+    }
+}
@@ -0,0 +1,46 @@
+use super::{clusters, kube_proxy, pick, Provider};
+use anyhow::Result;
+use async_trait::async_trait;
+use hyper::Uri;
+use kube::Client;
+
+/// The endpoint of the EKS Cluster managed by the AWS Controllers for Kubernetes,
+/// falling back to the EKS endpoint kube-proxy talks to
+pub struct Eks {
+    pub cluster_name: Option<String>,
+}
+
+#[async_trait]
+impl Provider for Eks {
+    fn name(&self) -> &'static str {
+        "eks"
+    }
+
+    async fn discover(&self, client: &Client) -> Result<Option<String>> {
+        if let Some(clusters) =
+            clusters(client, "eks.services.k8s.aws", "Cluster").await?
+        {
+            let endpoint = pick(&clusters, self.cluster_name.as_deref())?
+                .and_then(|c| c.data["status"]["endpoint"].as_str());
+
+            if let Some(endpoint) = endpoint {
+                return Ok(Some(endpoint.to_string()));
+            }
+        }
+
+        // Only trust kube-proxy if it talks to an EKS endpoint rather than a proxy
+        Ok(kube_proxy::server(client)
+            .await?
+            .filter(|url| endpoint(url)))
+    }
+}
+
+/// Checks if a URL points at an EKS API server endpoint
+fn endpoint(url: &str) -> bool {
+    url.parse::<Uri>().ok().is_some_and(|uri| {
+        uri.host().is_some_and(|host| {
+            host.ends_with(".eks.amazonaws.com")
+                || host.ends_with(".eks.amazonaws.com.cn")
+        })
+    })
+}
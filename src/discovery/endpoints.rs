@@ -0,0 +1,39 @@
+use super::Provider;
+use anyhow::Result;
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::Endpoints as KubeEndpoints;
+use kube::{Api, Client};
+
+/// The address of the API server behind the kubernetes.default Service
+pub struct Endpoints;
+
+#[async_trait]
+impl Provider for Endpoints {
+    fn name(&self) -> &'static str {
+        "endpoints"
+    }
+
+    async fn discover(&self, client: &Client) -> Result<Option<String>> {
+        let api: Api<KubeEndpoints> = Api::namespaced(client.clone(), "default");
+
+        let subset = api
+            .get_opt("kubernetes")
+            .await?
+            .and_then(|e| e.subsets)
+            .and_then(|s| s.into_iter().next());
+
+        let ip = subset
+            .as_ref()
+            .and_then(|s| s.addresses.as_ref())
+            .and_then(|a| a.first())
+            .map(|a| a.ip.clone());
+        let port = subset
+            .as_ref()
+            .and_then(|s| s.ports.as_ref())
+            .and_then(|p| p.iter().find(|p| p.name.as_deref() == Some("https")))
+            .map(|p| p.port)
+            .unwrap_or(443);
+
+        Ok(ip.map(|ip| format!("https://{}:{}", ip, port)))
+    }
+}
@@ -0,0 +1,20 @@
+use super::Provider;
+use anyhow::Result;
+use async_trait::async_trait;
+use kube::{Client, Config};
+
+/// The in-cluster address, only reachable from within the cluster
+pub struct InCluster;
+
+#[async_trait]
+impl Provider for InCluster {
+    fn name(&self) -> &'static str {
+        "in-cluster"
+    }
+
+    async fn discover(&self, _client: &Client) -> Result<Option<String>> {
+        Ok(Config::incluster_dns()
+            .ok()
+            .map(|c| c.cluster_url.to_string().trim_end_matches('/').to_string()))
+    }
+}
@@ -0,0 +1,31 @@
+use super::Provider;
+use anyhow::Result;
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client};
+
+/// The kubeconfig kube-proxy uses, as found on kubeadm and EKS clusters
+pub struct KubeProxy;
+
+#[async_trait]
+impl Provider for KubeProxy {
+    fn name(&self) -> &'static str {
+        "kube-proxy"
+    }
+
+    async fn discover(&self, client: &Client) -> Result<Option<String>> {
+        server(client).await
+    }
+}
+
+/// Gets the server from the kubeconfig in the kube-proxy ConfigMap
+pub async fn server(client: &Client) -> Result<Option<String>> {
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), "kube-system");
+
+    // The key differs between distributions, e.g. kubeconfig or kubeconfig.conf
+    Ok(api
+        .get_opt("kube-proxy")
+        .await?
+        .and_then(|c| c.data)
+        .and_then(|d| d.values().find_map(|v| super::server(v))))
+}
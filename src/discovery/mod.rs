@@ -0,0 +1,165 @@
+use crate::config::KufefeConfig;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::core::DynamicObject;
+use kube::{api::ListParams, Api, Client};
+
+mod aks;
+mod anthos;
+mod cluster_info;
+mod config;
+mod eks;
+mod endpoints;
+mod in_cluster;
+mod kube_proxy;
+mod openshift;
+
+/// Providers tried when no order is configured, most specific first
+pub const DEFAULT_ORDER: &[&str] = &[
+    "config",
+    "anthos",
+    "eks",
+    "aks",
+    "openshift",
+    "cluster-info",
+    "kube-proxy",
+    "endpoints",
+    "in-cluster",
+];
+
+/// A source the cluster URL can be discovered from
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Name used to configure the order and to report the winning provider
+    fn name(&self) -> &'static str;
+
+    /// Looks up the cluster URL, or None if this provider doesn't apply
+    async fn discover(&self, client: &Client) -> Result<Option<String>>;
+}
+
+/// A cluster URL and the provider that found it
+#[derive(Debug, Clone)]
+pub struct Discovered {
+    pub url: String,
+    pub provider: String,
+}
+
+/// Looks up a provider by name
+fn provider(name: &str, settings: &KufefeConfig) -> Result<Box<dyn Provider>> {
+    Ok(match name {
+        "config" => Box::new(config::Configured {
+            url: settings.cluster_url(),
+        }),
+        "anthos" => Box::new(anthos::Anthos {
+            cluster_name: settings.cluster_name(),
+        }),
+        "eks" => Box::new(eks::Eks {
+            cluster_name: settings.cluster_name(),
+        }),
+        "aks" => Box::new(aks::Aks {
+            cluster_name: settings.cluster_name(),
+        }),
+        "openshift" => Box::new(openshift::OpenShift),
+        "cluster-info" => Box::new(cluster_info::ClusterInfo),
+        "kube-proxy" => Box::new(kube_proxy::KubeProxy),
+        "endpoints" => Box::new(endpoints::Endpoints),
+        "in-cluster" => Box::new(in_cluster::InCluster),
+        other => bail!("Unknown cluster URL provider {}", other),
+    })
+}
+
+/// Builds the chain of providers in the given order
+pub fn chain(
+    order: &[String],
+    settings: &KufefeConfig,
+) -> Result<Vec<Box<dyn Provider>>> {
+    order.iter().map(|name| provider(name, settings)).collect()
+}
+
+/// Tries each provider in turn until one finds the cluster URL
+pub async fn discover(
+    client: &Client,
+    chain: &[Box<dyn Provider>],
+) -> Result<Discovered> {
+    for provider in chain {
+        match provider.discover(client).await {
+            Ok(Some(url)) => {
+                tracing::info!("Discovered cluster URL {} via {}", url, provider.name());
+
+                return Ok(Discovered {
+                    url,
+                    provider: provider.name().to_string(),
+                });
+            }
+            Ok(None) => tracing::debug!("{} found no cluster URL", provider.name()),
+            Err(e) => tracing::info!(
+                "{} failed to discover the cluster URL: {}",
+                provider.name(),
+                e
+            ),
+        }
+    }
+
+    bail!("No provider could discover the cluster URL, please set clusterUrl")
+}
+
+/// Gets the cluster CA from the kube-root-ca.crt ConfigMap published in every namespace,
+/// which unlike token Secrets follows CA rotations
+pub async fn ca(client: &Client, namespace: &str) -> Result<Option<String>> {
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+
+    Ok(api
+        .get_opt("kube-root-ca.crt")
+        .await?
+        .and_then(|c| c.data)
+        .and_then(|mut d| d.remove("ca.crt")))
+}
+
+/// Gets the server of the first cluster in a kubeconfig
+fn server(kubeconfig: &str) -> Option<String> {
+    kube::config::Kubeconfig::from_yaml(kubeconfig)
+        .ok()?
+        .clusters
+        .into_iter()
+        .find_map(|c| c.cluster.and_then(|c| c.server))
+}
+
+/// Lists the clusters an operator manages in whichever version it serves,
+/// or None if the operator isn't installed
+async fn clusters(
+    client: &Client,
+    group: &str,
+    kind: &str,
+) -> Result<Option<Vec<DynamicObject>>> {
+    let group = match kube::discovery::group(client, group).await {
+        Ok(group) => group,
+        Err(kube::Error::Discovery(_)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let resource = match group.recommended_kind(kind) {
+        Some((resource, _)) => resource,
+        None => return Ok(None),
+    };
+
+    let api: Api<DynamicObject> = Api::all_with(client.clone(), &resource);
+    Ok(Some(api.list(&ListParams::default()).await?.items))
+}
+
+/// Picks the cluster Kufefe runs in, by clusterName if there are several
+fn pick<'a>(
+    clusters: &'a [DynamicObject],
+    cluster_name: Option<&str>,
+) -> Result<Option<&'a DynamicObject>> {
+    if clusters.len() <= 1 {
+        return Ok(clusters.first());
+    }
+
+    match cluster_name {
+        Some(name) => Ok(clusters
+            .iter()
+            .find(|c| c.metadata.name.as_deref() == Some(name))),
+        None => bail!("Found more than one cluster. Please specify clusterName"),
+    }
+}
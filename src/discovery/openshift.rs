@@ -0,0 +1,27 @@
+use super::Provider;
+use anyhow::Result;
+use async_trait::async_trait;
+use kube::core::{DynamicObject, GroupVersionKind};
+use kube::discovery::ApiResource;
+use kube::{Api, Client};
+
+/// The apiServerURL of the OpenShift Infrastructure named cluster
+pub struct OpenShift;
+
+#[async_trait]
+impl Provider for OpenShift {
+    fn name(&self) -> &'static str {
+        "openshift"
+    }
+
+    async fn discover(&self, client: &Client) -> Result<Option<String>> {
+        let gvk = GroupVersionKind::gvk("config.openshift.io", "v1", "Infrastructure");
+        let api: Api<DynamicObject> =
+            Api::all_with(client.clone(), &ApiResource::from_gvk(&gvk));
+
+        Ok(api
+            .get_opt("cluster")
+            .await?
+            .and_then(|i| i.data["status"]["apiServerURL"].as_str().map(String::from)))
+    }
+}
@@ -0,0 +1,314 @@
+use crate::config::DriftPolicy;
+use crate::resources::rolebinding::RoleBinding;
+use crate::traits::meta::{MANAGED_BY_SELECTOR, REQUEST_UID_LABEL};
+use crate::traits::{api::ApiResource, delete::DeleteOpt};
+use crate::transaction::retry;
+use crate::{context::Context, crd::Request};
+use crate::{error::Error, error::Result};
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::{Secret, ServiceAccount};
+use k8s_openapi::api::rbac::v1::{ClusterRoleBinding, RoleRef, Subject};
+use kube::api::{DeleteParams, Patch, PatchParams};
+use kube::runtime::reflector::Store;
+use kube::runtime::watcher::{self, watcher, Event};
+use kube::runtime::{reflector, WatchStreamExt};
+use kube::{Api, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::fmt::Debug;
+use tokio::sync::oneshot;
+
+/// Annotation binding a token Secret to its ServiceAccount
+const SA_NAME_ANNOTATION: &str = "kubernetes.io/service-account.name";
+
+/// A resource Kufefe generates, which must match what its Request dictates
+#[async_trait]
+trait Owned:
+    Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug + Send + Sync
+{
+    /// Describes how the resource deviates from the Request, if it does
+    fn deviation(&self, ctx: &Context, request: &Request) -> Option<String>;
+
+    /// Restores the resource to what the Request dictates
+    async fn restore(&self, ctx: &Context, request: &Request) -> Result<()>;
+}
+
+/// Watches the resources Kufefe owns and handles any drift
+pub async fn run(ctx: Context) {
+    let client = ctx.client();
+    let namespace = ctx.namespace();
+
+    if ctx.settings().drift_policy() == DriftPolicy::Ignore {
+        return;
+    }
+
+    tracing::info!(
+        "Starting drift detection for generated resources (policy: {:?})",
+        ctx.settings().drift_policy()
+    );
+
+    // Keep a cache of Requests, and wait for it to be filled before comparing
+    let (store, writer) = reflector::store();
+    let (synced, ready) = oneshot::channel();
+    let mut synced = Some(synced);
+
+    let requests = reflector(
+        writer,
+        watcher(
+            Api::<Request>::all(client.clone()),
+            watcher::Config::default(),
+        ),
+    );
+
+    tokio::spawn(async move {
+        requests
+            .backoff(watcher::default_backoff())
+            .for_each(|event| {
+                if let Ok(Event::Restarted(_)) = event {
+                    if let Some(synced) = synced.take() {
+                        synced.send(()).ok();
+                    }
+                }
+
+                futures::future::ready(())
+            })
+            .await;
+    });
+
+    ready.await.ok();
+
+    tokio::join!(
+        watch::<ServiceAccount>(
+            &ctx,
+            Api::namespaced(client.clone(), &namespace),
+            &store
+        ),
+        watch::<Secret>(&ctx, Api::namespaced(client.clone(), &namespace), &store),
+        watch::<ClusterRoleBinding>(&ctx, Api::all(client), &store),
+    );
+}
+
+/// Watches a single kind of owned resource
+async fn watch<K: Owned + 'static>(ctx: &Context, api: Api<K>, store: &Store<Request>) {
+    let config = watcher::Config::default().labels(MANAGED_BY_SELECTOR);
+
+    let stream = watcher(api, config)
+        .backoff(watcher::default_backoff())
+        .applied_objects();
+    futures::pin_mut!(stream);
+
+    loop {
+        match stream.try_next().await {
+            Ok(Some(object)) => check(ctx, &object, store).await,
+            Ok(None) => break,
+            Err(e) => tracing::error!("Error during drift watch: {}", e),
+        }
+    }
+}
+
+/// Compares a resource with its Request and repairs or revokes on deviation
+async fn check<K: Owned>(ctx: &Context, object: &K, store: &Store<Request>) {
+    if object.meta().deletion_timestamp.is_some() {
+        return;
+    }
+
+    let uid = match object.labels().get(REQUEST_UID_LABEL) {
+        Some(uid) => uid.clone(),
+        None => return,
+    };
+
+    // Only Requests that are fully provisioned have a state to compare against
+    let request = match store.find(|r| r.uid().as_ref() == Some(&uid)) {
+        Some(request) if request.status.as_ref().is_some_and(|s| s.ready) => request,
+        _ => return,
+    };
+
+    let deviation = match object.deviation(ctx, &request) {
+        Some(deviation) => deviation,
+        None => return,
+    };
+
+    let kind = K::kind(&());
+    let policy = ctx.settings().drift_policy();
+    let mut request = (*request).clone();
+
+    tracing::warn!(
+        audit = true,
+        request = request.name_any(),
+        kind = kind.as_ref(),
+        name = object.name_any(),
+        policy = format!("{:?}", policy),
+        "Detected tampering with {} {}: {}",
+        kind,
+        object.name_any(),
+        deviation
+    );
+
+    request.condition(
+        "Tampered",
+        true,
+        &format!("{}Modified", kind),
+        format!("{} {}: {}", kind, object.name_any(), deviation),
+    );
+
+    if let Err(e) = request.update_status(ctx).await {
+        tracing::error!("{}", e);
+    }
+
+    // Transient failures are retried like any other step Kufefe takes
+    let result = match policy {
+        DriftPolicy::Repair => retry(|| object.restore(ctx, &request)).await,
+        DriftPolicy::Revoke => retry(|| revoke(ctx, &request)).await,
+        DriftPolicy::Ignore => Ok(()),
+    };
+
+    if let Err(e) = result {
+        ctx.metrics().error(&e);
+        tracing::error!(
+            "Failed to handle drift of {} {}: {}",
+            kind,
+            object.name_any(),
+            e
+        );
+    }
+}
+
+/// Revokes access by deleting the Request, which cascades to its resources
+async fn revoke(ctx: &Context, request: &Request) -> Result<()> {
+    tracing::warn!(
+        audit = true,
+        request = request.name_any(),
+        "Revoking request {}",
+        request.name_any()
+    );
+
+    Request::api(ctx)
+        .delete_opt(&request.name_any(), &DeleteParams::default())
+        .await
+        .map_err(Error::api("delete", "Request", &request.name_any()))?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl Owned for ServiceAccount {
+    fn deviation(&self, _ctx: &Context, _request: &Request) -> Option<String> {
+        if self.automount_service_account_token != Some(true) {
+            return Some("automountServiceAccountToken was changed".to_string());
+        }
+
+        None
+    }
+
+    async fn restore(&self, ctx: &Context, _request: &Request) -> Result<()> {
+        let api: Api<ServiceAccount> =
+            Api::namespaced(ctx.client(), &self.namespace().unwrap());
+        let patch = json!({ "automountServiceAccountToken": true });
+
+        api.patch(
+            &self.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+        .map_err(Error::api("patch", "ServiceAccount", &self.name_any()))?;
+
+        tracing::info!("Restored ServiceAccount {}", self.name_any());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Owned for Secret {
+    fn deviation(&self, _ctx: &Context, request: &Request) -> Option<String> {
+        let status = request.status.as_ref()?;
+        let sa_name = self.annotations().get(SA_NAME_ANNOTATION);
+
+        if sa_name != Some(&status.service_account_name) {
+            return Some(format!(
+                "{} was changed to {:?}",
+                SA_NAME_ANNOTATION, sa_name
+            ));
+        }
+
+        None
+    }
+
+    async fn restore(&self, ctx: &Context, request: &Request) -> Result<()> {
+        let status = request.status.clone().unwrap_or_default();
+        let api: Api<Secret> = Api::namespaced(ctx.client(), &self.namespace().unwrap());
+        let patch = json!({
+            "metadata": {
+                "annotations": { SA_NAME_ANNOTATION: status.service_account_name }
+            }
+        });
+
+        api.patch(
+            &self.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+        .map_err(Error::api("patch", "Secret", &self.name_any()))?;
+
+        tracing::info!("Restored Secret {}", self.name_any());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Owned for ClusterRoleBinding {
+    fn deviation(&self, ctx: &Context, request: &Request) -> Option<String> {
+        let status = request.status.as_ref()?;
+
+        let role_ref = RoleRef {
+            api_group: "rbac.authorization.k8s.io".to_string(),
+            kind: "ClusterRole".to_string(),
+            name: request.spec.role.clone(),
+        };
+
+        if self.role_ref != role_ref {
+            return Some(format!("roleRef was changed to {}", self.role_ref.name));
+        }
+
+        let subjects = vec![Subject {
+            kind: "ServiceAccount".to_string(),
+            name: status.service_account_name.clone(),
+            namespace: Some(ctx.namespace()),
+            ..Subject::default()
+        }];
+
+        if self.subjects.as_ref() != Some(&subjects) {
+            return Some("subjects were changed".to_string());
+        }
+
+        None
+    }
+
+    async fn restore(&self, ctx: &Context, request: &Request) -> Result<()> {
+        let status = request.status.clone().unwrap_or_default();
+        let rb = RoleBinding::new(ctx);
+
+        // The roleRef is immutable, so the binding has to be recreated
+        rb.get_api()
+            .delete_opt(&self.name_any(), &DeleteParams::default())
+            .await
+            .map_err(Error::api("delete", "ClusterRoleBinding", &self.name_any()))?;
+
+        let sa = Api::<ServiceAccount>::namespaced(ctx.client(), &ctx.namespace())
+            .get(&status.service_account_name)
+            .await
+            .map_err(Error::api(
+                "get",
+                "ServiceAccount",
+                &status.service_account_name,
+            ))?;
+
+        rb.create(self.name_any(), request.spec.role.clone(), &sa, request)
+            .await?;
+
+        tracing::info!("Restored ClusterRoleBinding {}", self.name_any());
+        Ok(())
+    }
+}
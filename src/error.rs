@@ -0,0 +1,164 @@
+use kube::error::ErrorResponse;
+
+/// Errors that can occur while provisioning a Request
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Role {role} not found")]
+    RoleNotFound { role: String },
+
+    #[error("Role {role} lacks the annotation kufefe.io/role")]
+    RoleNotAllowed { role: String },
+
+    #[error("Role {role} is not one of the allowed roles")]
+    RoleNotListed { role: String },
+
+    #[error(
+        "Requested duration of {minutes} minutes exceeds the maximum of {max} minutes"
+    )]
+    DurationNotAllowed { minutes: u64, max: u64 },
+
+    #[error("Cluster {cluster} is not registered")]
+    ClusterNotFound { cluster: String },
+
+    #[error("Failed to connect to cluster {cluster}: {reason}")]
+    ClusterUnavailable { cluster: String, reason: String },
+
+    #[error("Unsupported request: {reason}")]
+    Unsupported { reason: String },
+
+    #[error("{kind} has no name")]
+    MissingName { kind: &'static str },
+
+    #[error("Request {request} has no status")]
+    MissingStatus { request: String },
+
+    #[error("Secret {secret} has not been populated with a token yet")]
+    TokenNotReady { secret: String },
+
+    #[error("Secret {secret} was deleted")]
+    SecretDeleted { secret: String },
+
+    #[error("Secret {secret} has no valid property {key}")]
+    InvalidSecret { secret: String, key: String },
+
+    #[error("Failed to {verb} {kind} {name}: {source}")]
+    Api {
+        verb: &'static str,
+        kind: &'static str,
+        name: String,
+        #[source]
+        source: Box<kube::Error>,
+    },
+
+    #[error("{kind} {name} already exists, but {reason}")]
+    Occupied {
+        kind: &'static str,
+        name: String,
+        reason: String,
+    },
+
+    #[error("Failed to update status of {request}: {source}")]
+    Status {
+        request: String,
+        #[source]
+        source: Box<kube::Error>,
+    },
+
+    #[error("Issued kubeconfig failed to authenticate: {source}")]
+    Unauthenticated {
+        #[source]
+        source: Box<kube::Error>,
+    },
+
+    #[error(
+        "Issued kubeconfig is not allowed to {verb} {resource} in API group \"{group}\""
+    )]
+    PermissionMissing {
+        verb: String,
+        resource: String,
+        group: String,
+    },
+
+    #[error("Failed to validate issued kubeconfig: {reason}")]
+    Validation { reason: String },
+
+    #[error("Failed to render kubeconfig: {reason}")]
+    Kubeconfig { reason: String },
+
+    #[error("Webhook {host} failed: {reason}")]
+    Webhook { host: String, reason: String },
+
+    #[error("Interrupted by shutdown")]
+    Interrupted,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Builds a mapper wrapping a Kubernetes API error with the failed operation
+    pub fn api(
+        verb: &'static str,
+        kind: &'static str,
+        name: &str,
+    ) -> impl FnOnce(kube::Error) -> Self {
+        let name = name.to_string();
+
+        move |source| Self::Api {
+            verb,
+            kind,
+            name,
+            source: Box::new(source),
+        }
+    }
+
+    /// Wraps a failure to build or serialize a kubeconfig
+    pub fn kubeconfig(reason: impl ToString) -> Self {
+        Self::Kubeconfig {
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Checks if the error is transient, as opposed to one that needs user action
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::TokenNotReady { .. } => true,
+            Self::Api { source, .. } | Self::Status { source, .. } => {
+                match source.as_ref() {
+                    kube::Error::Api(ErrorResponse { code, .. }) => {
+                        matches!(code, 408 | 409 | 429 | 500..=599)
+                    }
+                    kube::Error::HyperError(_) | kube::Error::Service(_) => true,
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Machine readable reason, used for conditions and as metric label
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::RoleNotFound { .. } => "RoleNotFound",
+            Self::RoleNotAllowed { .. } => "RoleNotAllowed",
+            Self::RoleNotListed { .. } => "RoleNotListed",
+            Self::DurationNotAllowed { .. } => "DurationNotAllowed",
+            Self::ClusterNotFound { .. } => "ClusterNotFound",
+            Self::ClusterUnavailable { .. } => "ClusterUnavailable",
+            Self::Unsupported { .. } => "Unsupported",
+            Self::MissingName { .. } => "MissingName",
+            Self::MissingStatus { .. } => "MissingStatus",
+            Self::TokenNotReady { .. } => "TokenNotReady",
+            Self::SecretDeleted { .. } => "SecretDeleted",
+            Self::InvalidSecret { .. } => "InvalidSecret",
+            Self::Api { .. } => "ApiError",
+            Self::Occupied { .. } => "ResourceConflict",
+            Self::Status { .. } => "StatusUpdateFailed",
+            Self::Unauthenticated { .. } => "Unauthenticated",
+            Self::PermissionMissing { .. } => "PermissionMissing",
+            Self::Validation { .. } => "ValidationFailed",
+            Self::Kubeconfig { .. } => "KubeconfigError",
+            Self::Webhook { .. } => "WebhookFailed",
+            Self::Interrupted => "Interrupted",
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the context extension marking entries merged by Kufefe
+pub const EXTENSION: &str = "kufefe.io/request";
+
+/// The Request an entry of a kubeconfig file was issued for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Issued {
+    /// Name of the Request
+    pub request: String,
+    /// API server of the cluster the Request was created in
+    pub server: String,
+}
+
+/// One of the kubeconfig files, kept as plain YAML so that whatever Kufefe
+/// doesn't know about is written back as it was
+struct Document {
+    path: PathBuf,
+    yaml: Value,
+    exists: bool,
+    changed: bool,
+}
+
+/// The kubeconfig files kubectl reads, which issued kubeconfigs are merged into.
+/// Like kubectl, the first file defining an entry wins, and entries are changed in
+/// the file that defines them.
+pub struct KubeconfigFile {
+    documents: Vec<Document>,
+}
+
+impl KubeconfigFile {
+    /// Gets the kubeconfig files kubectl uses by default, in order
+    pub fn default_paths() -> Result<Vec<PathBuf>> {
+        if let Some(paths) = std::env::var_os("KUBECONFIG") {
+            let mut unique: Vec<PathBuf> = Vec::new();
+            for path in
+                std::env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty())
+            {
+                if !unique.contains(&path) {
+                    unique.push(path);
+                }
+            }
+
+            if !unique.is_empty() {
+                return Ok(unique);
+            }
+        }
+
+        match std::env::var_os("HOME") {
+            Some(home) => Ok(vec![PathBuf::from(home).join(".kube").join("config")]),
+            None => Err(Error::kubeconfig(
+                "can't locate the kubeconfig file, set KUBECONFIG",
+            )),
+        }
+    }
+
+    /// Loads the kubeconfig files kubectl uses by default
+    pub fn load_default() -> Result<Self> {
+        Self::load_all(Self::default_paths()?)
+    }
+
+    /// Loads a kubeconfig file, starting out empty if it doesn't exist yet
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        Self::load_all(vec![path.into()])
+    }
+
+    /// Loads kubeconfig files in order, those that don't exist yet starting out empty
+    pub fn load_all(paths: Vec<PathBuf>) -> Result<Self> {
+        let documents = paths
+            .into_iter()
+            .map(|path| {
+                let failed = |e: &dyn std::fmt::Display| {
+                    Error::kubeconfig(format!("failed to read {}: {}", path.display(), e))
+                };
+
+                let (yaml, exists) = match path.exists() {
+                    true => {
+                        let contents =
+                            fs::read_to_string(&path).map_err(|e| failed(&e))?;
+                        let yaml = match serde_yaml::from_str(&contents) {
+                            Ok(Value::Null) => empty(),
+                            Ok(yaml @ Value::Mapping(_)) => yaml,
+                            Ok(_) => return Err(failed(&"not a kubeconfig")),
+                            Err(e) => return Err(failed(&e)),
+                        };
+
+                        (yaml, true)
+                    }
+                    false => (empty(), false),
+                };
+
+                Ok(Document {
+                    path,
+                    yaml,
+                    exists,
+                    changed: false,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if documents.is_empty() {
+            return Err(Error::kubeconfig("no kubeconfig file given"));
+        }
+
+        Ok(Self { documents })
+    }
+
+    /// Path of the file new entries are written to
+    pub fn path(&self) -> &Path {
+        &self.documents[self.target()].path
+    }
+
+    /// The context kubectl uses by default
+    pub fn current_context(&self) -> Option<&str> {
+        self.documents.iter().find_map(|d| current(&d.yaml))
+    }
+
+    /// Lists the contexts merged by Kufefe along with the Request they were issued for
+    pub fn issued(&self) -> Vec<(String, Issued)> {
+        let mut seen = Vec::new();
+        let mut found = Vec::new();
+
+        for context in self
+            .documents
+            .iter()
+            .flat_map(|d| list(&d.yaml, "contexts"))
+        {
+            let Some(name) = name(context) else { continue };
+
+            // Later files can't override a context, so they don't count
+            if seen.contains(&name) {
+                continue;
+            }
+            seen.push(name);
+
+            if let Some(issued) = issued(context) {
+                found.push((name.to_string(), issued));
+            }
+        }
+
+        found
+    }
+
+    /// Merges an issued kubeconfig under names no other entry uses, replacing what
+    /// was merged for the same Request before, and returns the name of its context
+    pub fn merge(
+        &mut self,
+        yaml: &str,
+        origin: Issued,
+        namespace: Option<&str>,
+        switch: bool,
+    ) -> Result<String> {
+        let issued: Value = serde_yaml::from_str(yaml).map_err(Error::kubeconfig)?;
+
+        // Issued kubeconfigs hold a single cluster, user and context
+        let (mut cluster, mut user, mut context) = match (
+            list(&issued, "clusters").next(),
+            list(&issued, "users").next(),
+            list(&issued, "contexts")
+                .next()
+                .and_then(|c| c.get("context")),
+        ) {
+            (Some(cluster), Some(user), Some(Value::Mapping(context))) => {
+                (cluster.clone(), user.clone(), context.clone())
+            }
+            _ => return Err(Error::kubeconfig("issued kubeconfig is incomplete")),
+        };
+
+        let previous: Vec<String> = self
+            .issued()
+            .into_iter()
+            .filter(|(_, i)| *i == origin)
+            .map(|(name, _)| name)
+            .collect();
+        let was_current = previous
+            .iter()
+            .any(|n| Some(n.as_str()) == self.current_context());
+        for name in previous {
+            self.remove(&name);
+        }
+
+        let name = self.unused_name(&format!("kufefe-{}", origin.request));
+        let extension = serde_yaml::to_value(&origin).map_err(Error::kubeconfig)?;
+
+        cluster["name"] = Value::from(name.as_str());
+        user["name"] = Value::from(name.as_str());
+        context.insert("cluster".into(), Value::from(name.as_str()));
+        context.insert("user".into(), Value::from(name.as_str()));
+        if let Some(namespace) = namespace {
+            context.insert("namespace".into(), Value::from(namespace));
+        }
+        context.insert(
+            "extensions".into(),
+            Value::Sequence(vec![Value::Mapping(Mapping::from_iter([
+                ("name".into(), Value::from(EXTENSION)),
+                ("extension".into(), extension),
+            ]))]),
+        );
+        let context = Value::Mapping(Mapping::from_iter([
+            ("name".into(), Value::from(name.as_str())),
+            ("context".into(), Value::Mapping(context)),
+        ]));
+
+        let target = self.target();
+        let document = &mut self.documents[target];
+        list_mut(&mut document.yaml, "clusters").push(cluster);
+        list_mut(&mut document.yaml, "users").push(user);
+        list_mut(&mut document.yaml, "contexts").push(context);
+        document.changed = true;
+
+        if switch || was_current || self.current_context().is_none() {
+            self.set_current_context(&name);
+        }
+
+        Ok(name)
+    }
+
+    /// Removes the contexts merged by Kufefe whose Request is expired, along with
+    /// their cluster and user, and returns their names
+    pub fn prune(&mut self, expired: impl Fn(&Issued) -> bool) -> Vec<String> {
+        let names: Vec<String> = self
+            .issued()
+            .into_iter()
+            .filter(|(_, i)| expired(i))
+            .map(|(name, _)| name)
+            .collect();
+
+        for name in names.iter() {
+            self.remove(name);
+        }
+
+        names
+    }
+
+    /// Writes back the kubeconfig files that were changed
+    pub fn save(&mut self) -> Result<()> {
+        for document in self.documents.iter_mut().filter(|d| d.changed) {
+            let yaml =
+                serde_yaml::to_string(&document.yaml).map_err(Error::kubeconfig)?;
+            write(&document.path, &yaml)?;
+
+            document.exists = true;
+            document.changed = false;
+        }
+
+        Ok(())
+    }
+
+    /// Index of the file new entries go to, which like for kubectl is the first
+    /// file that exists, or the last one if none does
+    fn target(&self) -> usize {
+        self.documents
+            .iter()
+            .position(|d| d.exists)
+            .unwrap_or(self.documents.len() - 1)
+    }
+
+    /// Sets the current context in the file it is set in, or the file new entries
+    /// go to
+    fn set_current_context(&mut self, name: &str) {
+        let index = self
+            .documents
+            .iter()
+            .position(|d| current(&d.yaml).is_some())
+            .unwrap_or_else(|| self.target());
+        let document = &mut self.documents[index];
+
+        document.yaml["current-context"] = Value::from(name);
+        document.changed = true;
+    }
+
+    /// Removes a context, and its cluster and user unless another context uses them
+    fn remove(&mut self, name: &str) {
+        let Some(document) = self
+            .documents
+            .iter_mut()
+            .find(|d| list(&d.yaml, "contexts").any(|c| self::name(c) == Some(name)))
+        else {
+            return;
+        };
+
+        let contexts = list_mut(&mut document.yaml, "contexts");
+        let index = contexts
+            .iter()
+            .position(|c| self::name(c) == Some(name))
+            .unwrap_or_default();
+        let removed = contexts.remove(index);
+        document.changed = true;
+
+        // A current context that no longer exists would break kubectl
+        for document in self.documents.iter_mut() {
+            if current(&document.yaml) == Some(name) {
+                if let Some(yaml) = document.yaml.as_mapping_mut() {
+                    yaml.remove("current-context");
+                }
+                document.changed = true;
+            }
+        }
+
+        for (field, key) in [("cluster", "clusters"), ("user", "users")] {
+            let Some(entry) = removed["context"][field].as_str() else {
+                continue;
+            };
+
+            let used = self
+                .documents
+                .iter()
+                .flat_map(|d| list(&d.yaml, "contexts"))
+                .any(|c| c["context"][field].as_str() == Some(entry));
+            if used {
+                continue;
+            }
+
+            for document in self.documents.iter_mut() {
+                let entries = list_mut(&mut document.yaml, key);
+                let before = entries.len();
+                entries.retain(|e| self::name(e) != Some(entry));
+                document.changed |= entries.len() != before;
+            }
+        }
+    }
+
+    /// Finds a name that no cluster, user or context uses yet
+    fn unused_name(&self, base: &str) -> String {
+        let taken = |candidate: &str| {
+            self.documents.iter().any(|d| {
+                ["clusters", "users", "contexts"]
+                    .iter()
+                    .flat_map(|l| list(&d.yaml, l))
+                    .any(|e| name(e) == Some(candidate))
+            })
+        };
+
+        std::iter::once(base.to_string())
+            .chain((2..).map(|n| format!("{}-{}", base, n)))
+            .find(|name| !taken(name))
+            .unwrap_or_default()
+    }
+}
+
+/// Sets the default namespace of every context of a kubeconfig
+pub fn with_namespace(yaml: &str, namespace: &str) -> Result<String> {
+    let mut kubeconfig: Value = serde_yaml::from_str(yaml).map_err(Error::kubeconfig)?;
+
+    for context in list_mut(&mut kubeconfig, "contexts") {
+        if let Some(context) = context.get_mut("context").and_then(Value::as_mapping_mut)
+        {
+            context.insert("namespace".into(), Value::from(namespace));
+        }
+    }
+
+    serde_yaml::to_string(&kubeconfig).map_err(Error::kubeconfig)
+}
+
+/// Makes the exec plugin of a kubeconfig authenticate with the self-service API
+/// through the given context, unless it already names one
+pub fn with_credential_context(yaml: &str, context: &str) -> Result<String> {
+    let mut kubeconfig: Value = serde_yaml::from_str(yaml).map_err(Error::kubeconfig)?;
+
+    for user in list_mut(&mut kubeconfig, "users") {
+        let Some(args) = user
+            .get_mut("user")
+            .and_then(|u| u.get_mut("exec"))
+            .and_then(|e| e.get_mut("args"))
+            .and_then(Value::as_sequence_mut)
+        else {
+            continue;
+        };
+
+        if args.first().is_some_and(|a| a == "credential")
+            && !args.iter().any(|a| a == "--context")
+        {
+            args.extend([Value::from("--context"), Value::from(context)]);
+        }
+    }
+
+    serde_yaml::to_string(&kubeconfig).map_err(Error::kubeconfig)
+}
+
+/// A kubeconfig without any entries
+fn empty() -> Value {
+    Value::Mapping(Mapping::from_iter([
+        ("apiVersion".into(), Value::from("v1")),
+        ("kind".into(), Value::from("Config")),
+    ]))
+}
+
+/// Name of a cluster, user or context entry
+fn name(entry: &Value) -> Option<&str> {
+    entry.get("name")?.as_str()
+}
+
+/// The current context set in a kubeconfig, if any
+fn current(yaml: &Value) -> Option<&str> {
+    yaml.get("current-context")?
+        .as_str()
+        .filter(|c| !c.is_empty())
+}
+
+/// Entries of a list of a kubeconfig, such as its clusters
+fn list<'a>(yaml: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    yaml.get(key)
+        .and_then(Value::as_sequence)
+        .into_iter()
+        .flatten()
+}
+
+/// Gets a list of a kubeconfig to change, creating it if needed
+fn list_mut<'a>(yaml: &'a mut Value, key: &str) -> &'a mut Vec<Value> {
+    if !yaml.is_mapping() {
+        *yaml = empty();
+    }
+
+    let entries = yaml
+        .as_mapping_mut()
+        .expect("kubeconfig is a mapping")
+        .entry(key.into())
+        .or_insert(Value::Null);
+    if !entries.is_sequence() {
+        *entries = Value::Sequence(Vec::new());
+    }
+
+    entries.as_sequence_mut().expect("list is a sequence")
+}
+
+/// Gets the Request a context was issued for, if it was merged by Kufefe
+fn issued(context: &Value) -> Option<Issued> {
+    list(context.get("context")?, "extensions")
+        .find(|e| name(e) == Some(EXTENSION))
+        .and_then(|e| serde_yaml::from_value(e.get("extension")?.clone()).ok())
+}
+
+/// Writes a file only the current user can read, as it holds credentials
+pub fn write(path: &Path, contents: &str) -> Result<()> {
+    let failed = |e: std::io::Error| {
+        Error::kubeconfig(format!("failed to write {}: {}", path.display(), e))
+    };
+
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(failed)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(failed)
+}
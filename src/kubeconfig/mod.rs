@@ -0,0 +1,379 @@
+use crate::error::{Error, Result};
+use crate::traits::api::ApiResource;
+use crate::{context::Context, crd::OutputFormat, resources::token::Token};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{TimeZone, Utc};
+use k8s_openapi::api::core::v1::{Secret, ServiceAccount};
+use kube::runtime::wait::await_condition;
+use kube::{config::KubeConfigOptions, Client, Config, ResourceExt};
+use serde::{Deserialize, Serialize};
+
+pub mod file;
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Kubeconfig {
+    api_version: String,
+    clusters: Vec<Cluster>,
+    contexts: Vec<NamedContext>,
+    #[serde(rename = "current-context")]
+    current_context: String,
+    kind: String,
+    preferences: Preferences,
+    users: Vec<User>,
+    #[serde(skip)]
+    token: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Cluster {
+    cluster: ClusterDetails,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct ClusterDetails {
+    #[serde(rename = "certificate-authority-data")]
+    certificate_authority_data: String,
+    server: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct NamedContext {
+    context: ContextDetails,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ContextDetails {
+    cluster: String,
+    user: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Preferences {}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecCredential {
+    api_version: String,
+    kind: String,
+    status: ExecCredentialStatus,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ExecCredentialStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expiration_timestamp: Option<String>,
+    token: String,
+}
+
+impl ExecCredential {
+    /// Generates a new ExecCredential for a token
+    pub fn new(token: String, expires_at: Option<i64>) -> Self {
+        let expiration_timestamp = expires_at
+            .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+            .map(|ts| ts.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+
+        Self {
+            api_version: "client.authentication.k8s.io/v1".to_string(),
+            kind: "ExecCredential".to_string(),
+            status: ExecCredentialStatus {
+                expiration_timestamp,
+                token,
+            },
+        }
+    }
+
+    /// Converts the ExecCredential to JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(&self).map_err(Error::kubeconfig)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct User {
+    name: String,
+    user: UserDetails,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct UserDetails {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exec: Option<ExecConfig>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ExecConfig {
+    api_version: String,
+    command: String,
+    args: Vec<String>,
+    interactive_mode: String,
+    provide_cluster_info: bool,
+}
+
+impl Kubeconfig {
+    /// Generetes a new Kubeconfig Struct
+    pub async fn new(ctx: &Context, sa: ServiceAccount, secret: Secret) -> Result<Self> {
+        // Get name of resources
+        let sa_name = sa.metadata.name.as_ref().ok_or(Error::MissingName {
+            kind: "ServiceAccount",
+        })?;
+        let secret_name = secret
+            .metadata
+            .name
+            .as_ref()
+            .ok_or(Error::MissingName { kind: "Secret" })?;
+
+        // Wait for the token controller to populate the Secret
+        let api = Token::new(ctx).get_api();
+        let timeout = ctx.settings().token_timeout();
+
+        tracing::debug!(
+            "Waiting for token of SA {}, secret {}",
+            sa_name,
+            secret_name
+        );
+
+        let populated = |s: Option<&Secret>| {
+            s.and_then(|s| s.data.as_ref())
+                .is_some_and(|d| d.contains_key("ca.crt") && d.contains_key("token"))
+        };
+
+        let secret = match tokio::time::timeout(
+            timeout,
+            await_condition(api, secret_name, populated),
+        )
+        .await
+        {
+            Ok(Ok(Some(secret))) => secret,
+            Ok(Ok(None)) => {
+                return Err(Error::SecretDeleted {
+                    secret: secret_name.clone(),
+                })
+            }
+            Ok(Err(e)) => {
+                return Err(Error::kubeconfig(format!(
+                    "failed to watch secret {}: {}",
+                    secret_name, e
+                )))
+            }
+            Err(_) => {
+                return Err(Error::TokenNotReady {
+                    secret: secret_name.clone(),
+                })
+            }
+        };
+
+        let ca = match ctx.ca() {
+            Some(ca) => ca,
+            None => Self::get_ca(&secret)?,
+        };
+        let token = Self::get_token(&secret)?;
+
+        Ok(Self {
+            api_version: "v1".to_string(),
+            clusters: vec![Cluster {
+                cluster: ClusterDetails {
+                    certificate_authority_data: general_purpose::STANDARD.encode(ca),
+                    server: ctx.url(),
+                },
+                name: "kubernetes".to_string(),
+            }],
+            contexts: vec![NamedContext {
+                context: ContextDetails {
+                    cluster: "kubernetes".to_string(),
+                    user: sa_name.clone(),
+                },
+                name: "kubernetes".to_string(),
+            }],
+            current_context: "kubernetes".to_string(),
+            kind: "Config".to_string(),
+            preferences: Preferences {},
+            users: vec![User {
+                name: sa_name.clone(),
+                user: UserDetails {
+                    token: Some(token.clone()),
+                    exec: None,
+                },
+            }],
+            token,
+        })
+    }
+
+    /// Parses a Kubeconfig previously issued by Kufefe
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let mut kubeconfig: Self =
+            serde_yaml::from_str(yaml).map_err(Error::kubeconfig)?;
+        kubeconfig.token = kubeconfig
+            .users
+            .iter()
+            .find_map(|u| u.user.token.clone())
+            .unwrap_or_default();
+
+        Ok(kubeconfig)
+    }
+
+    /// Points the Kubeconfig at another server and CA, returning whether it changed
+    pub fn rebase(&mut self, server: &str, ca: Option<&str>) -> bool {
+        let ca = ca.map(|ca| general_purpose::STANDARD.encode(ca));
+        let mut changed = false;
+
+        for cluster in self.clusters.iter_mut() {
+            let details = &mut cluster.cluster;
+
+            if details.server != server {
+                details.server = server.to_string();
+                changed = true;
+            }
+
+            if let Some(ca) = ca
+                .as_ref()
+                .filter(|ca| **ca != details.certificate_authority_data)
+            {
+                details.certificate_authority_data = ca.clone();
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Converts the Kubeconfig Struct to YAML
+    pub fn to_yaml(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(Error::kubeconfig)
+    }
+
+    /// Converts the Kubeconfig Struct to JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(&self).map_err(Error::kubeconfig)
+    }
+
+    /// Converts the Kubeconfig Struct to a shell snippet exporting the server,
+    /// a path to the CA file and the token
+    pub fn to_env(&self) -> Result<String> {
+        let cluster = match self.clusters.first() {
+            Some(cluster) => &cluster.cluster,
+            None => return Err(Error::kubeconfig("kubeconfig has no cluster")),
+        };
+
+        Ok(format!(
+            "export KUFEFE_SERVER='{}'\n\
+             export KUFEFE_CA_FILE=\"$(mktemp)\"\n\
+             echo '{}' | base64 -d > \"$KUFEFE_CA_FILE\"\n\
+             export KUFEFE_TOKEN='{}'\n",
+            cluster.server,
+            cluster.certificate_authority_data,
+            self.static_token()?
+        ))
+    }
+
+    /// Converts the Kubeconfig Struct to a client.authentication.k8s.io ExecCredential
+    pub fn to_exec_credential(&self, expires_at: Option<i64>) -> Result<String> {
+        self.static_token()?;
+
+        ExecCredential::new(self.token.clone(), expires_at).to_json()
+    }
+
+    /// Renders the Kubeconfig in the requested output format
+    pub fn render(
+        &self,
+        format: OutputFormat,
+        expires_at: Option<i64>,
+    ) -> Result<String> {
+        match format {
+            OutputFormat::Yaml => self.to_yaml(),
+            OutputFormat::Json => self.to_json(),
+            OutputFormat::Env => self.to_env(),
+            OutputFormat::ExecCredential => self.to_exec_credential(expires_at),
+        }
+    }
+
+    /// Replaces the static token with an exec plugin fetching tokens on demand
+    /// from the self-service API
+    pub fn exec(&mut self, request: &str, api_url: &str) -> &mut Self {
+        for user in self.users.iter_mut() {
+            user.user = UserDetails {
+                token: None,
+                exec: Some(ExecConfig {
+                    api_version: "client.authentication.k8s.io/v1".to_string(),
+                    command: "kufefe".to_string(),
+                    args: vec![
+                        "credential".to_string(),
+                        "--request".to_string(),
+                        request.to_string(),
+                        "--api-url".to_string(),
+                        api_url.to_string(),
+                    ],
+                    interactive_mode: "Never".to_string(),
+                    provide_cluster_info: false,
+                }),
+            };
+        }
+
+        self
+    }
+
+    /// Builds a Kubernetes Client authenticating with the issued token
+    pub async fn client(&self) -> Result<Client> {
+        let mut kubeconfig = self.clone();
+
+        for user in kubeconfig.users.iter_mut() {
+            user.user = UserDetails {
+                token: Some(self.token.clone()),
+                exec: None,
+            };
+        }
+
+        let config = Config::from_custom_kubeconfig(
+            kube::config::Kubeconfig::from_yaml(&kubeconfig.to_yaml()?)
+                .map_err(Error::kubeconfig)?,
+            &KubeConfigOptions::default(),
+        )
+        .await
+        .map_err(Error::kubeconfig)?;
+
+        Client::try_from(config).map_err(Error::kubeconfig)
+    }
+
+    /// Gets the token, unless the kubeconfig uses the exec plugin
+    fn static_token(&self) -> Result<&str> {
+        if self.users.iter().any(|u| u.user.token.is_none()) {
+            return Err(Error::kubeconfig(
+                "output format requires a static token, but the exec plugin is used",
+            ));
+        }
+
+        Ok(&self.token)
+    }
+
+    /// Gets the CA from the Secret
+    fn get_ca(secret: &Secret) -> Result<String> {
+        let ca = Token::data(secret, "ca.crt")?;
+
+        String::from_utf8(ca.0).map_err(|_| Error::InvalidSecret {
+            secret: secret.name_any(),
+            key: "ca.crt".to_string(),
+        })
+    }
+
+    /// Gets the Token from the Secret
+    fn get_token(secret: &Secret) -> Result<String> {
+        let token = Token::data(secret, "token")?;
+
+        String::from_utf8(token.0).map_err(|_| Error::InvalidSecret {
+            secret: secret.name_any(),
+            key: "token".to_string(),
+        })
+    }
+}
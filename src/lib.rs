@@ -0,0 +1,27 @@
+pub mod api;
+pub mod cli;
+pub mod clusters;
+pub mod config;
+pub mod context;
+pub mod crd;
+pub mod credential;
+pub mod discovery;
+pub mod drift;
+pub mod error;
+pub mod kubeconfig;
+pub mod macros;
+pub mod metrics;
+pub mod notify;
+pub mod plugin;
+pub mod refresh;
+pub mod reload;
+pub mod resources;
+pub mod scheduler;
+pub mod shutdown;
+pub mod sweeper;
+#[cfg(test)]
+mod tests;
+pub mod traits;
+pub mod transaction;
+pub mod validation;
+pub mod watcher;
@@ -1,20 +1,32 @@
-use crate::{config::KufefeConfig, crd::Request};
+use clap::Parser;
+use kube::Client;
+use kufefe::{api, cli::Cli, cli::Command, config::KufefeConfig, context::Context};
+use kufefe::{crd, credential, drift, metrics, refresh, reload, scheduler, shutdown};
+use kufefe::{sweeper, watcher};
 use tokio::select;
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::sync::OnceCell;
-
-mod config;
-mod crd;
-mod kubeconfig;
-mod macros;
-mod resources;
-mod traits;
-mod watcher;
-
-static CONFIG: OnceCell<KufefeConfig> = OnceCell::const_new();
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
+    // Subcommands run outside of the cluster and must keep stdout clean
+    let config = match cli.command() {
+        Command::Credential(args) => {
+            if let Err(e) = credential::run(args).await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+
+            return;
+        }
+        Command::Crd => {
+            print!("{}", crd::manifest());
+            return;
+        }
+        Command::Run(config) => *config,
+    };
+
     // Setup Tracing
     if std::env::var_os("RUST_LOG").is_none() {
         std::env::set_var("RUST_LOG", "kufefe=info");
@@ -25,43 +37,73 @@ async fn main() {
         .json()
         .init();
 
-    // Set up statics
-    CONFIG
-        .set(
-            KufefeConfig::new()
-                .await
-                .expect("Failed to generate Kufefe Configuration"),
-        )
-        .ok();
+    // Set up the context shared by the controllers
+    let client = Client::try_default()
+        .await
+        .expect("Failed to create Kubernetes Client");
+    let mut settings = match KufefeConfig::load(config) {
+        Ok(settings) => settings,
+        Err(e) => {
+            tracing::error!("{:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    tracing::info!(config = %settings.redacted(), "Loaded configuration");
+
+    settings
+        .discover(&client)
+        .await
+        .expect("Failed to generate Kufefe Configuration");
+    let ctx = Context::new(client, settings);
 
     // Thread for handling signals
     tokio::spawn(async move {
         let mut sigterm = signal(SignalKind::terminate()).unwrap();
         let mut sigint = signal(SignalKind::interrupt()).unwrap();
 
-        loop {
-            select! {
-                _ = sigterm.recv() => {
-                    tracing::info!("SIGTERM received, exiting");
-                    std::process::exit(0);
-                }
-                _ = sigint.recv() => {
-                    tracing::info!("SIGINT received, exiting");
-                    std::process::exit(0);
-                }
-            }
+        select! {
+            _ = sigterm.recv() => tracing::info!("SIGTERM received, shutting down"),
+            _ = sigint.recv() => tracing::info!("SIGINT received, shutting down"),
         }
+
+        shutdown::trigger();
+
+        // A second signal skips draining
+        select! {
+            _ = sigterm.recv() => {}
+            _ = sigint.recv() => {}
+        }
+
+        tracing::warn!("Second signal received, exiting immediately");
+        std::process::exit(1);
     });
 
+    // Expose reconcile metrics
+    tokio::spawn(metrics::serve(ctx.clone()));
+
+    // Issue kubeconfigs to authenticated callers, if enabled
+    tokio::spawn(api::serve(ctx.clone()));
+
+    // Apply changes of the settings ConfigMap to new Requests
+    tokio::spawn(reload::run(ctx.clone()));
+
     // Bootstrap Controller for CRD's
-    watcher::watch().await;
+    watcher::watch(ctx.clone()).await;
 
-    // Scan for expired resources
-    tracing::info!("Starting watcher for expired resources");
-    let crd = Request::mock();
+    // Repair or revoke on tampering with generated resources
+    tokio::spawn(drift::run(ctx.clone()));
 
-    loop {
-        crd.scan().await;
-        tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
-    }
+    // Garbage collect resources whose Request is gone
+    tokio::spawn(sweeper::run(ctx.clone()));
+
+    // Follow changes of the cluster URL and CA
+    tokio::spawn(refresh::run(ctx.clone()));
+
+    // Delete Requests as they expire, until shutdown is triggered
+    scheduler::run(&ctx).await;
+
+    // Let in-flight reconciles finish before exiting
+    shutdown::drain(&ctx, ctx.settings().shutdown_timeout()).await;
+    tracing::info!("Shutdown complete");
 }
@@ -0,0 +1,154 @@
+use crate::{context::Context, error::Error, shutdown};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header::CONTENT_TYPE, Body, Request, Response, Server};
+use prometheus::{
+    Encoder, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+/// Counters describing how reconciles went
+pub struct Metrics {
+    registry: Registry,
+    reconciliations: IntCounterVec,
+    errors: IntCounterVec,
+    config_generation: IntGauge,
+    config_reload_errors: IntCounter,
+    notifications: IntCounterVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry::new();
+
+        let reconciliations = IntCounterVec::new(
+            Opts::new(
+                "kufefe_reconciliations_total",
+                "Reconciled Requests by result",
+            ),
+            &["result"],
+        )
+        .unwrap();
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "kufefe_reconcile_errors_total",
+                "Reconcile errors by reason",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        let config_generation = IntGauge::new(
+            "kufefe_config_generation",
+            "Generation of the configuration in use",
+        )
+        .unwrap();
+        let config_reload_errors = IntCounter::new(
+            "kufefe_config_reload_errors_total",
+            "Configuration changes that were rejected",
+        )
+        .unwrap();
+        let notifications = IntCounterVec::new(
+            Opts::new(
+                "kufefe_notifications_total",
+                "Webhook notifications by result",
+            ),
+            &["result"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(reconciliations.clone()))
+            .unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry
+            .register(Box::new(config_generation.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(config_reload_errors.clone()))
+            .unwrap();
+        registry.register(Box::new(notifications.clone())).unwrap();
+
+        Self {
+            registry,
+            reconciliations,
+            errors,
+            config_generation,
+            config_reload_errors,
+            notifications,
+        }
+    }
+}
+
+impl Metrics {
+    /// Counts a finished reconciliation, e.g. "ready", "retry" or "failed"
+    pub fn reconciled(&self, result: &str) {
+        self.reconciliations.with_label_values(&[result]).inc();
+    }
+
+    /// Counts a reconcile error by its reason
+    pub fn error(&self, e: &Error) {
+        self.errors.with_label_values(&[e.reason()]).inc();
+    }
+
+    /// Records the generation of the configuration in use
+    pub fn config_generation(&self, generation: u64) {
+        self.config_generation.set(generation as i64);
+    }
+
+    /// Counts a rejected configuration change
+    pub fn config_reload_error(&self) {
+        self.config_reload_errors.inc();
+    }
+
+    /// Counts a webhook notification, "delivered" or "failed"
+    pub fn notified(&self, result: &str) {
+        self.notifications.with_label_values(&[result]).inc();
+    }
+
+    /// Renders the metrics in the Prometheus text format
+    fn render(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .ok();
+
+        buffer
+    }
+}
+
+/// Serves the metrics in the Prometheus text format until shutdown
+pub async fn serve(ctx: Context) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], ctx.settings().metrics_port()));
+    let service = make_service_fn(move |_| {
+        let ctx = ctx.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_: Request<Body>| {
+                let body = ctx.metrics().render();
+
+                async move {
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .header(CONTENT_TYPE, TextEncoder::new().format_type())
+                            .body(Body::from(body))
+                            .unwrap(),
+                    )
+                }
+            }))
+        }
+    });
+
+    tracing::info!("Serving metrics on {}", addr);
+
+    let server = match Server::try_bind(&addr) {
+        Ok(builder) => builder.serve(service),
+        Err(e) => {
+            tracing::error!("Failed to bind metrics server to {}: {}", addr, e);
+            return;
+        }
+    };
+
+    if let Err(e) = server.with_graceful_shutdown(shutdown::triggered()).await {
+        tracing::error!("Metrics server failed: {}", e);
+    }
+}
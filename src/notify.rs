@@ -0,0 +1,303 @@
+use crate::api::requests::REQUESTED_BY_ANNOTATION;
+use crate::{context::Context, crd::Request, error::Error, error::Result, shutdown};
+use chrono::{TimeZone, Utc};
+use futures::future::join_all;
+use hyper::client::HttpConnector;
+use hyper::{header::CONTENT_TYPE, Body, Client, Uri};
+use hyper_openssl::HttpsConnector;
+use kube::ResourceExt;
+use openssl::{error::ErrorStack, hash::MessageDigest, pkey::PKey, sign::Signer};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Header carrying the HMAC-SHA256 signature of the body, as sha256=<hex>
+pub const SIGNATURE_HEADER: &str = "X-Kufefe-Signature";
+
+/// Header naming the event a webhook is called for
+pub const EVENT_HEADER: &str = "X-Kufefe-Event";
+
+/// How many times a webhook is called before giving up
+pub const ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry, doubled on every further attempt
+const BACKOFF: Duration = Duration::from_secs(2);
+
+/// How long a single call may take
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What happened to a Request
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Notification {
+    ApprovalNeeded,
+    Issued,
+    Extended,
+    Revoked,
+    Expired,
+}
+
+impl Notification {
+    /// Name of the event, as in the payload
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ApprovalNeeded => "approvalNeeded",
+            Self::Issued => "issued",
+            Self::Extended => "extended",
+            Self::Revoked => "revoked",
+            Self::Expired => "expired",
+        }
+    }
+}
+
+/// Payload template a webhook is called with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Slack,
+    Teams,
+}
+
+/// An endpoint told about Requests
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub format: Format,
+    /// Only these events are sent, all of them if not set
+    pub events: Option<Vec<Notification>>,
+    /// Only Requests for these roles are reported, all of them if not set
+    pub roles: Option<Vec<String>>,
+    /// Environment variable holding the secret bodies are signed with
+    pub secret_env: Option<String>,
+}
+
+impl Webhook {
+    /// Checks if the webhook wants to hear about an event for a role
+    pub fn wants(&self, event: Notification, role: &str) -> bool {
+        self.events.as_ref().is_none_or(|e| e.contains(&event))
+            && self
+                .roles
+                .as_ref()
+                .is_none_or(|r| r.iter().any(|r| r == role))
+    }
+
+    /// Host of the webhook, which can be logged unlike the URL
+    pub fn host(&self) -> String {
+        self.url
+            .parse::<Uri>()
+            .ok()
+            .and_then(|uri| uri.host().map(String::from))
+            .unwrap_or_default()
+    }
+
+    /// The webhook, safe to log as its URL often embeds a token
+    pub fn redacted(&self) -> Value {
+        json!({
+            "host": self.host(),
+            "format": self.format,
+            "events": self.events,
+            "roles": self.roles,
+            "secretEnv": self.secret_env,
+        })
+    }
+
+    /// Gets the secret bodies are signed with, if any
+    fn secret(&self) -> Option<String> {
+        std::env::var(self.secret_env.as_ref()?).ok()
+    }
+}
+
+/// What webhooks are told about a Request
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+    pub event: Notification,
+    pub request: String,
+    pub role: String,
+    pub cluster: Option<String>,
+    pub requested_by: Option<String>,
+    pub expires_at: Option<i64>,
+    pub timestamp: i64,
+}
+
+impl Payload {
+    /// Describes an event of a Request
+    pub fn new(event: Notification, request: &Request) -> Self {
+        Self {
+            event,
+            request: request.name_any(),
+            role: request.spec.role.clone(),
+            cluster: request.spec.cluster.clone(),
+            requested_by: request.annotations().get(REQUESTED_BY_ANNOTATION).cloned(),
+            expires_at: request.status.as_ref().and_then(|s| s.expires_at),
+            timestamp: Utc::now().timestamp(),
+        }
+    }
+
+    /// Summarizes the event for people reading it in chat
+    pub fn text(&self) -> String {
+        let who = match &self.requested_by {
+            Some(who) => format!(" by {}", who),
+            None => String::new(),
+        };
+        let cluster = match &self.cluster {
+            Some(cluster) => format!(" on cluster {}", cluster),
+            None => String::new(),
+        };
+        let until = self
+            .expires_at
+            .and_then(|t| Utc.timestamp_opt(t, 0).single())
+            .map(|t| format!(", valid until {}", t.format("%Y-%m-%d %H:%M UTC")))
+            .unwrap_or_default();
+
+        let (verb, until) = match self.event {
+            Notification::ApprovalNeeded => ("needs approval", String::new()),
+            Notification::Issued => ("was issued", until),
+            Notification::Extended => ("was extended", until),
+            Notification::Revoked => ("was revoked", String::new()),
+            Notification::Expired => ("expired", String::new()),
+        };
+
+        format!(
+            "Request {}{} for role {}{} {}{}",
+            self.request, who, self.role, cluster, verb, until
+        )
+    }
+
+    /// Renders the body a webhook is called with
+    pub fn render(&self, format: Format) -> Value {
+        match format {
+            Format::Json => json!(self),
+            Format::Slack => json!({ "text": self.text() }),
+            Format::Teams => json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": self.text(),
+                "title": "Kufefe",
+                "text": self.text(),
+            }),
+        }
+    }
+}
+
+/// Tells the configured webhooks about an event of a Request, in the background
+pub fn notify(ctx: &Context, event: Notification, request: &Request) {
+    let webhooks: Vec<Webhook> = ctx
+        .settings()
+        .webhooks()
+        .iter()
+        .filter(|w| w.wants(event, &request.spec.role))
+        .cloned()
+        .collect();
+
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let ctx = ctx.clone();
+    let payload = Payload::new(event, request);
+
+    // Deliveries are waited for on shutdown, so events aren't silently lost
+    shutdown::spawn(async move {
+        let client = match HttpsConnector::new() {
+            Ok(connector) => Client::builder().build(connector),
+            Err(e) => return tracing::error!("Failed to set up webhook client: {}", e),
+        };
+
+        join_all(webhooks.iter().map(|webhook| async {
+            match deliver(&client, webhook, &payload, BACKOFF).await {
+                Ok(()) => ctx.metrics().notified("delivered"),
+                Err(e) => {
+                    ctx.metrics().notified("failed");
+                    tracing::error!(
+                        "Failed to notify of {} ({}): {}",
+                        payload.request,
+                        event.name(),
+                        e
+                    );
+                }
+            }
+        }))
+        .await;
+    });
+}
+
+/// Calls a webhook, retrying with backoff on network errors, 429 and 5xx
+pub async fn deliver(
+    client: &Client<HttpsConnector<HttpConnector>>,
+    webhook: &Webhook,
+    payload: &Payload,
+    backoff: Duration,
+) -> Result<()> {
+    let body = payload.render(webhook.format).to_string();
+    let failed = |reason: String| Error::Webhook {
+        host: webhook.host(),
+        reason,
+    };
+
+    // An unsigned payload would be rejected by the receiver, so it isn't sent
+    let signature = webhook
+        .secret()
+        .map(|secret| sign(&secret, &body))
+        .transpose()
+        .map_err(|e| failed(format!("failed to sign the payload: {}", e)))?;
+
+    let mut attempt = 1;
+    loop {
+        let mut request = hyper::Request::post(&webhook.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, payload.event.name());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        let request = request
+            .body(Body::from(body.clone()))
+            .map_err(|e| failed(e.to_string()))?;
+
+        let reason = match tokio::time::timeout(TIMEOUT, client.request(request)).await {
+            Ok(Ok(response)) if response.status().is_success() => return Ok(()),
+            Ok(Ok(response)) => {
+                let status = response.status();
+                if !(status.is_server_error() || status.as_u16() == 429) {
+                    return Err(failed(format!("responded with {}", status)));
+                }
+
+                format!("responded with {}", status)
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => format!("no response within {}s", TIMEOUT.as_secs()),
+        };
+
+        if attempt >= ATTEMPTS {
+            return Err(failed(format!("{} after {} attempts", reason, attempt)));
+        }
+
+        let delay = backoff * 2u32.pow(attempt - 1);
+        tracing::warn!(
+            "Webhook {} {}, retrying in {}ms",
+            webhook.host(),
+            reason,
+            delay.as_millis()
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Signs a body with HMAC-SHA256, as receivers verify it
+pub fn sign(secret: &str, body: &str) -> Result<String, ErrorStack> {
+    let key = PKey::hmac(secret.as_bytes())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(body.as_bytes())?;
+
+    let hex: String = signer
+        .sign_to_vec()?
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(format!("sha256={}", hex))
+}
@@ -0,0 +1,39 @@
+/// Parses a duration such as `1h`, `30m`, `1h30m` or `90` into minutes
+pub fn parse(value: &str) -> Result<u64, String> {
+    let invalid = || {
+        format!(
+            "invalid duration '{}', expected e.g. 1h, 30m or 1h30m",
+            value
+        )
+    };
+
+    // A plain number is taken as minutes
+    if let Ok(minutes) = value.parse::<u64>() {
+        return (minutes > 0).then_some(minutes).ok_or_else(invalid);
+    }
+
+    let mut minutes = 0u64;
+    let mut number = String::new();
+
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' if !number.is_empty() => {
+                let n: u64 = number.parse().map_err(|_| invalid())?;
+                let factor = if c == 'h' { 60 } else { 1 };
+                minutes = n
+                    .checked_mul(factor)
+                    .and_then(|n| minutes.checked_add(n))
+                    .ok_or_else(invalid)?;
+                number.clear();
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    if !number.is_empty() || minutes == 0 {
+        return Err(invalid());
+    }
+
+    Ok(minutes)
+}
@@ -0,0 +1,115 @@
+use super::GetArgs;
+use crate::crd::{CredentialMode, Request, NAMESPACE_ANNOTATION};
+use crate::kubeconfig::file::{self, Issued, KubeconfigFile};
+use anyhow::{bail, Result};
+use kube::{api::ListParams, Api, Client, ResourceExt};
+use std::collections::HashMap;
+
+/// Prints the kubeconfig of a Request, writes it to a file or merges it
+pub async fn run(
+    client: Client,
+    server: &str,
+    context: Option<&str>,
+    args: GetArgs,
+) -> Result<()> {
+    let request = Api::<Request>::all(client).get(&args.name).await?;
+    let namespace = request.annotations().get(NAMESPACE_ANNOTATION);
+
+    // The exec plugin authenticates with the context the Request was made with
+    let yaml = match context {
+        Some(context)
+            if request.spec.credential_mode.unwrap_or_default()
+                == CredentialMode::Exec =>
+        {
+            file::with_credential_context(kubeconfig(&request)?, context)?
+        }
+        _ => kubeconfig(&request)?.to_string(),
+    };
+    let yaml = yaml.as_str();
+
+    if args.merge {
+        let mut kubeconfig = KubeconfigFile::load_default()?;
+        let origin = Issued {
+            request: args.name,
+            server: server.to_string(),
+        };
+        let context =
+            kubeconfig.merge(yaml, origin, namespace.map(String::as_str), args.switch)?;
+        kubeconfig.save()?;
+
+        println!(
+            "Merged context {} into {}",
+            context,
+            kubeconfig.path().display()
+        );
+        if kubeconfig.current_context() == Some(context.as_str()) {
+            println!("Switched to context {}", context);
+        }
+
+        return Ok(());
+    }
+
+    // Standalone kubeconfigs default to the requested namespace as well
+    let yaml = match namespace {
+        Some(namespace) => file::with_namespace(yaml, namespace)?,
+        None => yaml.to_string(),
+    };
+
+    match args.file {
+        Some(path) => {
+            file::write(&path, &yaml)?;
+            println!("Kubeconfig written to {}", path.display());
+        }
+        None => print!("{}", yaml),
+    }
+
+    Ok(())
+}
+
+/// Removes merged contexts of this cluster whose Request has expired or is gone
+pub async fn prune(client: Client, server: &str) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    let live: HashMap<String, bool> = Api::<Request>::all(client)
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .map(|r| {
+            let expired = r
+                .status
+                .as_ref()
+                .and_then(|s| s.expires_at)
+                .is_some_and(|at| at <= now);
+            (r.name_any(), expired)
+        })
+        .collect();
+
+    let mut kubeconfig = KubeconfigFile::load_default()?;
+    let pruned = kubeconfig.prune(|issued| {
+        issued.server == server && live.get(&issued.request).copied().unwrap_or(true)
+    });
+
+    if pruned.is_empty() {
+        println!("Nothing to prune in {}", kubeconfig.path().display());
+        return Ok(());
+    }
+
+    kubeconfig.save()?;
+    for context in pruned {
+        println!("Removed context {}", context);
+    }
+
+    Ok(())
+}
+
+/// Gets the kubeconfig issued for a ready Request
+fn kubeconfig(request: &Request) -> Result<&str> {
+    match request
+        .status
+        .as_ref()
+        .filter(|s| s.ready)
+        .and_then(|s| s.kubeconfig.as_deref())
+    {
+        Some(yaml) => Ok(yaml),
+        None => bail!("Request {} is not ready", request.name_any()),
+    }
+}
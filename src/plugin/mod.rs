@@ -0,0 +1,152 @@
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::{Client, Config};
+use std::path::PathBuf;
+
+pub mod duration;
+pub mod get;
+pub mod request;
+
+#[derive(Parser)]
+#[command(
+    name = "kubectl-kufefe",
+    version,
+    about = "Request and manage ephemeral kubeconfigs issued by Kufefe"
+)]
+pub struct Cli {
+    /// Kubeconfig context used to talk to the cluster, defaults to the current context
+    #[arg(long, global = true, env = "KUFEFE_CONTEXT")]
+    pub context: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Creates a Request for a role
+    Request(RequestArgs),
+    /// Waits until a Request is ready
+    Wait(WaitArgs),
+    /// Prints the kubeconfig of a Request, writes it to a file or merges it
+    Get(GetArgs),
+    /// Postpones the expiry of a Request
+    Extend(ExtendArgs),
+    /// Deletes a Request, revoking its kubeconfig
+    Revoke(NameArgs),
+    /// Removes merged contexts whose Request has expired from the kubeconfig file
+    Prune,
+    /// Lists Requests
+    List,
+}
+
+#[derive(Args)]
+pub struct RequestArgs {
+    /// Role to request
+    #[arg(long)]
+    pub role: String,
+
+    /// How long the kubeconfig is valid, e.g. 1h, 30m or 1h30m
+    #[arg(long, value_parser = duration::parse)]
+    pub duration: Option<u64>,
+
+    /// Name of the Request, generated from the role by default
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Registered target cluster to issue the kubeconfig for
+    #[arg(long)]
+    pub cluster: Option<String>,
+
+    /// Namespace the issued kubeconfig defaults to
+    #[arg(long)]
+    pub namespace: Option<String>,
+
+    /// Wait until the Request is ready
+    #[arg(long)]
+    pub wait: bool,
+
+    /// How long to wait in seconds
+    #[arg(long, default_value_t = 120)]
+    pub timeout: u64,
+}
+
+#[derive(Args)]
+pub struct WaitArgs {
+    /// Name of the Request
+    pub name: String,
+
+    /// How long to wait in seconds
+    #[arg(long, default_value_t = 120)]
+    pub timeout: u64,
+}
+
+#[derive(Args)]
+pub struct GetArgs {
+    /// Name of the Request
+    pub name: String,
+
+    /// Write the kubeconfig to this file instead of printing it
+    #[arg(long, conflicts_with = "merge")]
+    pub file: Option<PathBuf>,
+
+    /// Merge the kubeconfig into $KUBECONFIG or ~/.kube/config as context kufefe-NAME
+    #[arg(long)]
+    pub merge: bool,
+
+    /// Make the merged context the current context
+    #[arg(long, requires = "merge")]
+    pub switch: bool,
+}
+
+#[derive(Args)]
+pub struct ExtendArgs {
+    /// Name of the Request
+    pub name: String,
+
+    /// How much longer the kubeconfig stays valid, e.g. 1h, 30m or 1h30m
+    #[arg(long, value_parser = duration::parse)]
+    pub duration: u64,
+
+    /// How long to wait for the controller in seconds
+    #[arg(long, default_value_t = 30)]
+    pub timeout: u64,
+}
+
+#[derive(Args)]
+pub struct NameArgs {
+    /// Name of the Request
+    pub name: String,
+}
+
+/// Runs a subcommand of the kubectl plugin
+pub async fn run(cli: Cli) -> Result<()> {
+    let kubeconfig = Kubeconfig::read()?;
+    let context = cli.context.or_else(|| kubeconfig.current_context.clone());
+    let config = config(kubeconfig, context.clone()).await?;
+    let server = config.cluster_url.to_string();
+    let client = Client::try_from(config)?;
+
+    match cli.command {
+        Command::Request(args) => request::create(client, args).await,
+        Command::Wait(args) => request::wait(client, &args.name, args.timeout)
+            .await
+            .map(|_| println!("request.kufefe.io/{} is ready", args.name)),
+        Command::Get(args) => get::run(client, &server, context.as_deref(), args).await,
+        Command::Extend(args) => request::extend(client, args).await,
+        Command::Revoke(args) => request::revoke(client, &args.name).await,
+        Command::Prune => get::prune(client, &server).await,
+        Command::List => request::list(client).await,
+    }
+}
+
+/// Loads the client configuration for the selected kubeconfig context
+async fn config(kubeconfig: Kubeconfig, context: Option<String>) -> Result<Config> {
+    let options = KubeConfigOptions {
+        context,
+        ..KubeConfigOptions::default()
+    };
+
+    Ok(Config::from_custom_kubeconfig(kubeconfig, &options).await?)
+}
@@ -0,0 +1,177 @@
+use super::{ExtendArgs, RequestArgs};
+use crate::crd::{Request, RequestSpec, NAMESPACE_ANNOTATION};
+use crate::watcher::EXPIRES_AT_ANNOTATION;
+use anyhow::{bail, Context as _, Result};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use kube::api::{DeleteParams, ListParams, Patch, PatchParams, PostParams};
+use kube::runtime::wait::await_condition;
+use kube::{Api, Client, ResourceExt};
+use serde_json::json;
+use std::time::Duration;
+
+/// Creates a Request, optionally waiting until it is ready
+pub async fn create(client: Client, args: RequestArgs) -> Result<()> {
+    let mut request = Request::new(
+        args.name.as_deref().unwrap_or_default(),
+        RequestSpec {
+            role: args.role.clone(),
+            cluster: args.cluster,
+            duration_minutes: args.duration,
+            ..RequestSpec::default()
+        },
+    );
+
+    if args.name.is_none() {
+        request.metadata.name = None;
+        request.metadata.generate_name = Some(Request::name_prefix(&args.role));
+    }
+
+    if let Some(namespace) = args.namespace {
+        request
+            .annotations_mut()
+            .insert(NAMESPACE_ANNOTATION.to_string(), namespace);
+    }
+
+    let request = Api::<Request>::all(client.clone())
+        .create(&PostParams::default(), &request)
+        .await?;
+    let name = request.name_any();
+    println!("request.kufefe.io/{} created", name);
+
+    if args.wait {
+        wait(client, &name, args.timeout).await?;
+        println!("request.kufefe.io/{} is ready", name);
+    }
+
+    Ok(())
+}
+
+/// Waits until a Request is ready, failing early if provisioning failed
+pub async fn wait(client: Client, name: &str, timeout: u64) -> Result<Request> {
+    let api = Api::<Request>::all(client);
+    let done = |r: Option<&Request>| {
+        r.and_then(|r| r.status.as_ref())
+            .is_some_and(|s| s.ready || s.failed)
+    };
+
+    let request = tokio::time::timeout(
+        Duration::from_secs(timeout),
+        await_condition(api, name, done),
+    )
+    .await
+    .with_context(|| format!("Timed out waiting for Request {}", name))??;
+
+    match request {
+        Some(request) if request.status.as_ref().is_some_and(|s| s.ready) => Ok(request),
+        Some(Request {
+            status: Some(status),
+            ..
+        }) => bail!("Request {} failed: {}", name, status.message),
+        _ => bail!("Request {} was deleted", name),
+    }
+}
+
+/// Asks the controller to postpone the expiry of a ready Request
+pub async fn extend(client: Client, args: ExtendArgs) -> Result<()> {
+    let api = Api::<Request>::all(client);
+    let request = api.get(&args.name).await?;
+
+    let expires_at = match request.status.as_ref().filter(|s| s.ready) {
+        Some(status) => status.expires_at.unwrap_or_default(),
+        None => bail!("Request {} is not ready", args.name),
+    };
+    let requested = expires_at + args.duration as i64 * 60;
+
+    let patch = json!({ "metadata": { "annotations": {
+        EXPIRES_AT_ANNOTATION: requested.to_string()
+    } } });
+    api.patch(&args.name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await?;
+
+    // The controller clears the annotation once it has handled the extension
+    let handled = |r: Option<&Request>| {
+        r.is_none_or(|r| !r.annotations().contains_key(EXPIRES_AT_ANNOTATION))
+    };
+    let request = tokio::time::timeout(
+        Duration::from_secs(args.timeout),
+        await_condition(api, &args.name, handled),
+    )
+    .await
+    .with_context(|| format!("Timed out waiting for Request {}", args.name))??;
+
+    match request.and_then(|r| r.status).and_then(|s| s.expires_at) {
+        Some(expires_at) if expires_at >= requested => {
+            println!(
+                "request.kufefe.io/{} expires at {}",
+                args.name,
+                timestamp(expires_at)
+            );
+            Ok(())
+        }
+        _ => bail!(
+            "Extension of Request {} was rejected, see its events for details",
+            args.name
+        ),
+    }
+}
+
+/// Deletes a Request, which revokes its kubeconfig
+pub async fn revoke(client: Client, name: &str) -> Result<()> {
+    Api::<Request>::all(client)
+        .delete(name, &DeleteParams::default())
+        .await?;
+    println!("request.kufefe.io/{} revoked", name);
+
+    Ok(())
+}
+
+/// Prints a table of all Requests
+pub async fn list(client: Client) -> Result<()> {
+    let requests = Api::<Request>::all(client)
+        .list(&ListParams::default())
+        .await?;
+
+    let rows: Vec<[String; 5]> = requests
+        .into_iter()
+        .map(|r| {
+            let status = r.status.clone().unwrap_or_default();
+            [
+                r.name_any(),
+                r.spec.role.clone(),
+                r.spec.cluster.clone().unwrap_or_else(|| "-".to_string()),
+                status.ready.to_string(),
+                status.expires_at.map_or_else(|| "-".to_string(), timestamp),
+            ]
+        })
+        .collect();
+
+    let header = ["NAME", "ROLE", "CLUSTER", "READY", "EXPIRES"].map(String::from);
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .chain([&header])
+                .map(|r| r[i].len())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    for row in [&header].into_iter().chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("   ").trim_end());
+    }
+
+    Ok(())
+}
+
+/// Formats a unix timestamp for display
+fn timestamp(secs: i64) -> String {
+    Utc.timestamp_opt(secs, 0).single().map_or_else(
+        || secs.to_string(),
+        |t| t.to_rfc3339_opts(SecondsFormat::Secs, true),
+    )
+}
@@ -0,0 +1,130 @@
+use crate::context::{Context, Endpoint};
+use crate::crd::{OutputFormat, Request};
+use crate::error::Result;
+use crate::{discovery, kubeconfig::Kubeconfig};
+use kube::api::ListParams;
+use kube::ResourceExt;
+
+/// Periodically re-discovers the cluster URL and CA, so rotations are picked up
+pub async fn run(ctx: Context) {
+    let interval = ctx.settings().discovery_interval();
+
+    // Catch up on changes made while Kufefe wasn't running
+    if ctx.settings().regenerate_kubeconfigs() {
+        regenerate(&ctx).await;
+    }
+
+    if interval.is_zero() {
+        return;
+    }
+
+    tracing::info!(
+        "Starting re-discovery of the cluster endpoint (regenerate kubeconfigs: {})",
+        ctx.settings().regenerate_kubeconfigs()
+    );
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        match refresh(&ctx).await {
+            Ok(true) if ctx.settings().regenerate_kubeconfigs() => regenerate(&ctx).await,
+            Ok(_) => {}
+            Err(e) => {
+                tracing::error!("Failed to re-discover the cluster endpoint: {}", e)
+            }
+        }
+    }
+}
+
+/// Discovers the endpoint again, returning whether the URL or CA changed
+async fn refresh(ctx: &Context) -> anyhow::Result<bool> {
+    let client = ctx.client();
+    let settings = ctx.settings();
+    let chain = discovery::chain(settings.discovery_order(), &settings)?;
+    let discovered = discovery::discover(&client, &chain).await?;
+
+    // Keep the known CA if it can't be read this time around
+    let ca = match discovery::ca(&client, &ctx.namespace()).await {
+        Ok(Some(ca)) => Some(ca),
+        Ok(None) => ctx.ca(),
+        Err(e) => {
+            tracing::warn!("Failed to read the cluster CA: {}", e);
+            ctx.ca()
+        }
+    };
+
+    let changed = ctx.set_endpoint(Endpoint {
+        url: discovered.url,
+        provider: discovered.provider,
+        ca,
+    });
+
+    if changed {
+        tracing::info!("Cluster endpoint changed, now using {}", ctx.url());
+    }
+
+    Ok(changed)
+}
+
+/// Rewrites the kubeconfig of every ready Request that points at a stale endpoint
+pub async fn regenerate(ctx: &Context) {
+    let requests = match Request::api(ctx).list(&ListParams::default()).await {
+        Ok(list) => list.items,
+        Err(e) => {
+            tracing::error!("Failed to list requests, skipping regeneration: {}", e);
+            return;
+        }
+    };
+
+    for mut request in requests {
+        if let Err(e) = rewrite(ctx, &mut request).await {
+            tracing::error!(
+                "Failed to regenerate kubeconfig for {}: {}",
+                request.name_any(),
+                e
+            );
+        }
+    }
+}
+
+/// Points the kubeconfig of a single Request at the current endpoint
+async fn rewrite(ctx: &Context, request: &mut Request) -> Result<()> {
+    // Kubeconfigs for target clusters don't point at this cluster
+    if request.spec.cluster.is_some() {
+        return Ok(());
+    }
+
+    let (mut kubeconfig, expires_at) = match &request.status {
+        Some(status) if status.ready => match &status.kubeconfig {
+            Some(kubeconfig) => (Kubeconfig::from_yaml(kubeconfig)?, status.expires_at),
+            None => return Ok(()),
+        },
+        _ => return Ok(()),
+    };
+
+    let endpoint = ctx.endpoint();
+
+    if !kubeconfig.rebase(&endpoint.url, endpoint.ca.as_deref()) {
+        return Ok(());
+    }
+
+    let format = request
+        .spec
+        .output_format
+        .unwrap_or(ctx.settings().default_output_format());
+    let output = match format {
+        OutputFormat::Yaml => None,
+        format => Some(kubeconfig.render(format, expires_at)?),
+    };
+
+    request
+        .kubeconfig(&kubeconfig.to_yaml()?)
+        .output(output)
+        .cluster(Some(endpoint.url), Some(endpoint.provider))
+        .update_status(ctx)
+        .await?;
+
+    tracing::info!("Regenerated kubeconfig for {}", request.name_any());
+
+    Ok(())
+}
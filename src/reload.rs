@@ -0,0 +1,87 @@
+use crate::config::{KufefeConfig, Overrides};
+use crate::context::Context;
+use anyhow::{Context as _, Result};
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::runtime::watcher::{self, watcher, Event};
+use kube::runtime::WatchStreamExt;
+use kube::Api;
+use std::sync::Arc;
+
+/// Key of the ConfigMap holding the settings
+pub const CONFIG_KEY: &str = "config.yaml";
+
+/// Watches the settings ConfigMap and applies its changes to new Requests
+pub async fn run(ctx: Context) {
+    let Some(name) = ctx.settings().config_map() else {
+        return;
+    };
+
+    // Overrides always apply on top of the settings Kufefe was started with
+    let base = ctx.settings();
+    let mut current = Overrides::default();
+
+    tracing::info!("Watching ConfigMap {} for configuration changes", name);
+
+    let api: Api<ConfigMap> = Api::namespaced(ctx.client(), &ctx.namespace());
+    let config = watcher::Config::default().fields(&format!("metadata.name={}", name));
+    let mut events = watcher(api, config)
+        .backoff(watcher::default_backoff())
+        .boxed();
+
+    while let Some(event) = events.next().await {
+        let config_map = match event {
+            Ok(Event::Applied(config_map)) => Some(config_map),
+            Ok(Event::Deleted(_)) => None,
+            Ok(Event::Restarted(config_maps)) => config_maps.into_iter().next(),
+            Err(e) => {
+                tracing::error!("Error watching ConfigMap {}: {}", name, e);
+                continue;
+            }
+        };
+
+        match apply(&ctx, &base, &current, config_map.as_ref()) {
+            Ok(Some(overrides)) => current = overrides,
+            Ok(None) => {}
+            Err(e) => {
+                ctx.metrics().config_reload_error();
+                tracing::error!(
+                    "Rejected configuration change, keeping generation {}: {:#}",
+                    ctx.generation(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Applies the settings of the ConfigMap, or the base settings if it is gone,
+/// returning the overrides now in use if they changed
+pub fn apply(
+    ctx: &Context,
+    base: &Arc<KufefeConfig>,
+    current: &Overrides,
+    config_map: Option<&ConfigMap>,
+) -> Result<Option<Overrides>> {
+    let overrides: Overrides = match config_map
+        .and_then(|c| c.data.as_ref())
+        .and_then(|d| d.get(CONFIG_KEY))
+    {
+        Some(yaml) => serde_yaml::from_str(yaml).with_context(|| {
+            format!("Invalid {} in configuration ConfigMap", CONFIG_KEY)
+        })?,
+        None => Overrides::default(),
+    };
+
+    if overrides == *current {
+        return Ok(None);
+    }
+
+    let settings = base.with_overrides(&overrides)?;
+    let redacted = settings.redacted();
+    let generation = ctx.set_settings(settings);
+
+    tracing::info!(config = %redacted, "Applied configuration generation {}", generation);
+
+    Ok(Some(overrides))
+}
@@ -1,5 +1,3 @@
-use crate::{traits::api::ApiResource, CONFIG};
-use kube::api::Api;
 use kube_derive::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -41,14 +39,3 @@ pub struct ClusterStatusApiEndpoints {
     pub host: String,
     pub port: i32,
 }
-
-impl ApiResource for Cluster {
-    type ApiType = Cluster;
-
-    fn get_api(&self) -> Api<Self::ApiType> {
-        let client = CONFIG.get().unwrap().client();
-        let api: Api<Self::ApiType> = Api::all(client);
-
-        api
-    }
-}
@@ -1,38 +1,79 @@
-use crate::CONFIG;
-use anyhow::{anyhow, bail, Result};
+use crate::{context::Context, error::Error, error::Result};
 use k8s_openapi::api::rbac::v1::ClusterRole;
-use kube::Api;
+use kube::{api::ListParams, Api, ResourceExt};
+
+/// Annotation on roles whose Requests someone else must approve before they are
+/// provisioned
+pub const APPROVAL_ANNOTATION: &str = "kufefe.io/approval-required";
+
+/// Checks if Requests for a role must be approved
+pub fn requires_approval(role: &ClusterRole) -> bool {
+    role.annotations().get(APPROVAL_ANNOTATION) == Some(&"true".to_string())
+}
 
 pub struct Role {
     api: Api<ClusterRole>,
+    allowed: Option<Vec<String>>,
 }
 
 impl Role {
     /// Instantiate a Role struct
-    pub fn new() -> Self {
-        let client = CONFIG.get().unwrap().client();
-        let api: Api<ClusterRole> = Api::all(client);
+    pub fn new(ctx: &Context) -> Self {
+        let api: Api<ClusterRole> = Api::all(ctx.client());
+        let allowed = ctx.settings().allowed_roles().map(|r| r.to_vec());
 
-        Self { api }
+        Self { api, allowed }
     }
 
-    /// Find a role by name and verify it has the annotation kufefe.io/role
+    /// Find a role by name and verify it has the annotation kufefe.io/role,
+    /// and is one of the allowed roles if those are restricted
     pub async fn get(&self, name: &str) -> Result<ClusterRole> {
-        match self.api.get(name).await {
-            Ok(o) => {
-                let annotations =
-                    o.metadata.annotations.as_ref().ok_or_else(|| {
-                        anyhow!("Role lacks the annotation kufefe.io/role")
-                    })?;
-
-                if annotations.get("kufefe.io/role") != Some(&"true".to_string()) {
-                    bail!("Role lacks the annotation kufefe.io/role");
-                }
-
-                Ok(o)
+        if let Some(allowed) = &self.allowed {
+            if !allowed.iter().any(|r| r == name) {
+                return Err(Error::RoleNotListed {
+                    role: name.to_string(),
+                });
+            }
+        }
+
+        let role = match self.api.get(name).await {
+            Ok(role) => role,
+            Err(kube::Error::Api(e)) if e.code == 404 => {
+                return Err(Error::RoleNotFound {
+                    role: name.to_string(),
+                })
             }
+            Err(e) => return Err(Error::api("get", "ClusterRole", name)(e)),
+        };
 
-            Err(e) => bail!(e),
+        if role.annotations().get("kufefe.io/role") != Some(&"true".to_string()) {
+            return Err(Error::RoleNotAllowed {
+                role: name.to_string(),
+            });
         }
+
+        Ok(role)
+    }
+
+    /// Lists the names of the roles that may be requested
+    pub async fn list(&self) -> Result<Vec<String>> {
+        let roles = self
+            .api
+            .list(&ListParams::default())
+            .await
+            .map_err(Error::api("list", "ClusterRole", ""))?;
+
+        Ok(roles
+            .into_iter()
+            .filter(|r| {
+                r.annotations().get("kufefe.io/role") == Some(&"true".to_string())
+            })
+            .map(|r| r.name_any())
+            .filter(|name| {
+                self.allowed
+                    .as_ref()
+                    .is_none_or(|allowed| allowed.contains(name))
+            })
+            .collect())
     }
 }
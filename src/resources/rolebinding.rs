@@ -1,22 +1,30 @@
-use crate::traits::api::ApiResource;
-use crate::{resources::role::Role, traits::meta::Meta, Request, CONFIG};
-use anyhow::{bail, Result};
+use crate::error::{Error, Result};
+use crate::traits::meta::{Meta, HUB_LABEL};
+use crate::traits::{api::ApiResource, create::GetOrCreate};
+use crate::{context::Context, crd::Request, resources::role::Role};
 use k8s_openapi::api::core::v1::ServiceAccount;
 use k8s_openapi::api::rbac::v1::{ClusterRoleBinding, RoleRef, Subject};
 use kube::api::PostParams;
 use kube::Api;
 
 pub struct RoleBinding {
+    namespace: String,
+    hub: Option<String>,
     api: Api<ClusterRoleBinding>,
+    role: Role,
 }
 
 impl RoleBinding {
     /// Instantiate a RoleBinding struct
-    pub fn new() -> Self {
-        let client = CONFIG.get().unwrap().client();
-        let api: Api<ClusterRoleBinding> = Api::all(client);
+    pub fn new(ctx: &Context) -> Self {
+        let api: Api<ClusterRoleBinding> = Api::all(ctx.client());
 
-        Self { api }
+        Self {
+            namespace: ctx.namespace(),
+            hub: ctx.cluster().map(|_| ctx.hub_name()),
+            api,
+            role: Role::new(ctx),
+        }
     }
 
     /// Create the RoleBinding in Kubernetes
@@ -27,25 +35,30 @@ impl RoleBinding {
         sa: &ServiceAccount,
         owner: &Request,
     ) -> Result<ClusterRoleBinding> {
-        let namespace = CONFIG.get().unwrap().namespace();
-        let meta = self.generate_meta(Some(name.clone()), None, owner).await;
-        let role_api = Role::new();
+        let mut meta = self.generate_meta(name.clone(), None, owner);
+
+        // The Request lives in another cluster, the finalizer cleans up instead,
+        // and the label keeps the Kufefe of the target cluster from sweeping it
+        if let Some(hub) = &self.hub {
+            meta.owner_references = None;
+            meta.labels
+                .get_or_insert_with(Default::default)
+                .insert(HUB_LABEL.to_string(), hub.clone());
+        }
 
         // Get the owner name
-        let sa_name = if let Some(name) = sa.metadata.name.clone() {
-            name
-        } else {
-            bail!("ServiceAccount has no name");
-        };
+        let sa_name = sa.metadata.name.clone().ok_or(Error::MissingName {
+            kind: "ServiceAccount",
+        })?;
 
         // Check if the specified role has the annotation kufefe.io/role.
-        role_api.get(&role).await?;
+        self.role.get(&role).await?;
 
         // Construct a subject
         let subject = Subject {
             kind: "ServiceAccount".to_string(),
             name: sa_name,
-            namespace: Some(namespace),
+            namespace: Some(self.namespace.clone()),
             ..Subject::default()
         };
 
@@ -59,13 +72,13 @@ impl RoleBinding {
             },
         };
 
-        match self.api.create(&PostParams::default(), &binding).await {
-            Ok(o) => {
-                tracing::info!("Created RoleBinding {}", &name);
-                Ok(o)
-            }
-            Err(e) => bail!(e),
-        }
+        let binding = self
+            .api
+            .get_or_create(&PostParams::default(), &binding)
+            .await?;
+
+        tracing::info!("Ensured RoleBinding {}", &name);
+        Ok(binding)
     }
 }
 
@@ -1,23 +1,27 @@
-use crate::traits::api::ApiResource;
-use crate::{crd::Request, traits::meta::Meta, CONFIG};
+use crate::traits::meta::{Meta, HUB_LABEL};
+use crate::traits::{api::ApiResource, create::GetOrCreate};
+use crate::{context::Context, crd::Request, error::Result};
 use k8s_openapi::api::core::v1::ServiceAccount as KubeServiceAccount;
 use kube::api::PostParams;
 use kube::Api;
 
 pub struct ServiceAccount {
     namespace: String,
+    hub: Option<String>,
     api: Api<KubeServiceAccount>,
 }
 
 impl ServiceAccount {
     /// Instantiate a ServiceAccount struct
-    pub fn new() -> Self {
-        let client = CONFIG.get().unwrap().client();
-        let namespace = CONFIG.get().unwrap().namespace();
+    pub fn new(ctx: &Context) -> Self {
+        let namespace = ctx.namespace();
+        let api: Api<KubeServiceAccount> = Api::namespaced(ctx.client(), &namespace);
 
-        let api: Api<KubeServiceAccount> = Api::namespaced(client, &namespace);
-
-        Self { namespace, api }
+        Self {
+            namespace,
+            hub: ctx.cluster().map(|_| ctx.hub_name()),
+            api,
+        }
     }
 
     /// Create the Service Account in Kubernetes
@@ -25,10 +29,18 @@ impl ServiceAccount {
         &self,
         name: String,
         owner: &Request,
-    ) -> Result<KubeServiceAccount, kube::Error> {
-        let meta = self
-            .generate_meta(Some(name.clone()), Some(self.namespace.clone()), owner)
-            .await;
+    ) -> Result<KubeServiceAccount> {
+        let mut meta =
+            self.generate_meta(name.clone(), Some(self.namespace.clone()), owner);
+
+        // The Request lives in another cluster, the finalizer cleans up instead,
+        // and the label keeps the Kufefe of the target cluster from sweeping it
+        if let Some(hub) = &self.hub {
+            meta.owner_references = None;
+            meta.labels
+                .get_or_insert_with(Default::default)
+                .insert(HUB_LABEL.to_string(), hub.clone());
+        }
 
         // Construct the API Object
         let sa = KubeServiceAccount {
@@ -38,13 +50,10 @@ impl ServiceAccount {
         };
 
         // Create the ServiceAccount
-        match self.api.create(&PostParams::default(), &sa).await {
-            Ok(o) => {
-                tracing::info!("Created ServiceAccount {}", &name);
-                Ok(o)
-            }
-            Err(e) => Err(e),
-        }
+        let sa = self.api.get_or_create(&PostParams::default(), &sa).await?;
+
+        tracing::info!("Ensured ServiceAccount {}", &name);
+        Ok(sa)
     }
 }
 
@@ -1,10 +1,9 @@
-use crate::traits::{api::ApiResource, meta::Meta};
-use crate::CONFIG;
-use anyhow::{bail, Result};
+use crate::traits::{api::ApiResource, create::GetOrCreate, meta::Meta};
+use crate::{context::Context, error::Error, error::Result};
 use k8s_openapi::api::core::v1::{Secret, ServiceAccount};
 use k8s_openapi::ByteString;
 use kube::api::PostParams;
-use kube::Api;
+use kube::{Api, ResourceExt};
 
 pub struct Token {
     namespace: String,
@@ -13,10 +12,9 @@ pub struct Token {
 
 impl Token {
     /// Instantiate Token Struct
-    pub fn new() -> Self {
-        let client = CONFIG.get().unwrap().client();
-        let namespace = CONFIG.get().unwrap().namespace();
-        let api: Api<Secret> = Api::namespaced(client, &namespace);
+    pub fn new(ctx: &Context) -> Self {
+        let namespace = ctx.namespace();
+        let api: Api<Secret> = Api::namespaced(ctx.client(), &namespace);
 
         Self { namespace, api }
     }
@@ -24,14 +22,10 @@ impl Token {
     /// Create a new Service Account Token Secret
     pub async fn create(&self, name: String, owner: &ServiceAccount) -> Result<Secret> {
         // Convert the k8sapi ServiceAccount to a kube ServiceACcount
-        let mut metadata = self
-            .generate_meta(Some(name.clone()), Some(self.namespace.clone()), owner)
-            .await;
+        let mut metadata =
+            self.generate_meta(name.clone(), Some(self.namespace.clone()), owner);
 
-        let mut annotations = match metadata.annotations {
-            Some(annotations) => annotations,
-            None => std::collections::BTreeMap::new(),
-        };
+        let mut annotations = metadata.annotations.unwrap_or_default();
 
         annotations.insert(
             "kubernetes.io/service-account.name".to_string(),
@@ -51,30 +45,26 @@ impl Token {
             ..Secret::default()
         };
 
-        match self.api.create(&PostParams::default(), &secret).await {
-            Ok(o) => {
-                tracing::info!("Created Secret (SA Token) {}", name);
-                Ok(o)
-            }
-            Err(e) => bail!(e),
-        }
+        let secret = self
+            .api
+            .get_or_create(&PostParams::default(), &secret)
+            .await?;
+
+        tracing::info!("Ensured Secret (SA Token) {}", name);
+        Ok(secret)
     }
 
     /// Get data from a secret idiomatically
-    pub async fn data(&self, secret_name: String, key: &str) -> Result<ByteString> {
-        let secret = self.get_api().get(&secret_name).await?;
-
-        let data = if let Some(data) = secret.data {
-            data
-        } else {
-            bail!("Secret has no data");
-        };
-
-        if let Some(v) = data.get(key) {
-            Ok(v.clone())
-        } else {
-            bail!("Secret has no property {}", key);
-        }
+    pub fn data(secret: &Secret, key: &str) -> Result<ByteString> {
+        secret
+            .data
+            .as_ref()
+            .and_then(|data| data.get(key))
+            .cloned()
+            .ok_or_else(|| Error::InvalidSecret {
+                secret: secret.name_any(),
+                key: key.to_string(),
+            })
     }
 }
 
@@ -0,0 +1,94 @@
+use crate::{context::Context, crd::Request, shutdown};
+use futures::{StreamExt, TryStreamExt};
+use kube::runtime::reflector::ObjectRef;
+use kube::runtime::watcher::{self, watcher, Event};
+use kube::runtime::{reflector, WatchStreamExt};
+use kube::ResourceExt;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::select;
+use tokio::time::Instant;
+use tokio_util::time::{delay_queue::Key, DelayQueue};
+
+/// Deletes each Request at its exact expiry, with a periodic full scan as safety net
+pub async fn run(ctx: &Context) {
+    let api = Request::api(ctx);
+    let crd = Request::mock();
+
+    tracing::info!("Starting expiry scheduler");
+
+    let (store, writer) = reflector::store();
+    let stream = reflector(writer, watcher(api, watcher::Config::default()))
+        .backoff(watcher::default_backoff());
+    futures::pin_mut!(stream);
+
+    let mut queue: DelayQueue<String> = DelayQueue::new();
+    let mut keys: HashMap<String, Key> = HashMap::new();
+    let mut safety_net = tokio::time::interval(ctx.settings().scan_interval());
+
+    loop {
+        select! {
+            event = stream.try_next() => match event {
+                Ok(Some(Event::Applied(request))) => {
+                    schedule(&mut queue, &mut keys, &request);
+                }
+                Ok(Some(Event::Deleted(request))) => {
+                    if let Some(key) = keys.remove(&request.name_any()) {
+                        queue.remove(&key);
+                    }
+                }
+                Ok(Some(Event::Restarted(requests))) => {
+                    // Rebuild the schedule from scratch after a (re)list
+                    queue.clear();
+                    keys.clear();
+
+                    for request in &requests {
+                        schedule(&mut queue, &mut keys, request);
+                    }
+
+                    tracing::info!("Scheduled expiry of {} requests", keys.len());
+                }
+                Ok(None) => break,
+                Err(e) => tracing::error!("Error during expiry watch: {}", e),
+            },
+            Some(expired) = queue.next() => {
+                let name = expired.into_inner();
+                keys.remove(&name);
+
+                // The cache is authoritative, the expiry may have moved in the meantime
+                if let Some(request) = store.get(&ObjectRef::new(&name)) {
+                    if crd.is_expired(&request) {
+                        crd.delete_expired(ctx, &request).await;
+                    } else {
+                        schedule(&mut queue, &mut keys, &request);
+                    }
+                }
+            }
+            _ = safety_net.tick() => crd.scan(ctx).await,
+            _ = shutdown::triggered() => break,
+        }
+    }
+}
+
+/// Schedules (or reschedules) the deletion of a Request at its expiry
+fn schedule(
+    queue: &mut DelayQueue<String>,
+    keys: &mut HashMap<String, Key>,
+    request: &Request,
+) {
+    let expires_at = match request.status.as_ref().and_then(|s| s.expires_at) {
+        Some(expires_at) => expires_at,
+        None => return,
+    };
+
+    let remaining = (expires_at - chrono::Utc::now().timestamp()).max(0) as u64;
+    let deadline = Instant::now() + Duration::from_secs(remaining);
+
+    match keys.get(&request.name_any()) {
+        Some(key) => queue.reset_at(key, deadline),
+        None => {
+            let key = queue.insert_at(request.name_any(), deadline);
+            keys.insert(request.name_any(), key);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::context::Context;
+use crate::crd::{Request, RequestStatus};
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+/// How long interrupted reconciles get to roll back once the deadline has passed
+const ROLLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+static SHUTDOWN: LazyLock<Shutdown> = LazyLock::new(Shutdown::default);
+
+#[derive(Default)]
+struct Shutdown {
+    token: CancellationToken,
+    abort: CancellationToken,
+    tracker: TaskTracker,
+    in_flight: Mutex<HashSet<String>>,
+}
+
+/// Marks a Request as being reconciled for as long as it is held
+pub struct InFlight(String);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        SHUTDOWN.in_flight.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// Starts shutting down, no new events will be accepted
+pub fn trigger() {
+    SHUTDOWN.token.cancel();
+}
+
+/// Resolves once shutdown has been triggered
+pub async fn triggered() {
+    SHUTDOWN.token.cancelled().await
+}
+
+/// Checks if shutdown has been triggered
+pub fn is_triggered() -> bool {
+    SHUTDOWN.token.is_cancelled()
+}
+
+/// Resolves once the deadline has passed and in-flight reconciles should roll back
+pub async fn aborted() {
+    SHUTDOWN.abort.cancelled().await
+}
+
+/// Spawns a task that is waited for during shutdown
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    SHUTDOWN.tracker.spawn(future);
+}
+
+/// Registers a Request as being reconciled
+pub fn in_flight(name: &str) -> InFlight {
+    SHUTDOWN.in_flight.lock().unwrap().insert(name.to_string());
+
+    InFlight(name.to_string())
+}
+
+/// Waits for tracked tasks to finish. Requests still being provisioned when the
+/// deadline passes roll back what they created, and anything that doesn't finish
+/// in time either is left in a state it will be resumed from on the next start.
+pub async fn drain(ctx: &Context, deadline: Duration) {
+    SHUTDOWN.tracker.close();
+
+    tracing::info!(
+        "Waiting up to {}s for {} in-flight requests",
+        deadline.as_secs(),
+        SHUTDOWN.in_flight.lock().unwrap().len()
+    );
+
+    if tokio::time::timeout(deadline, SHUTDOWN.tracker.wait())
+        .await
+        .is_ok()
+    {
+        tracing::info!("All in-flight requests finished");
+        return;
+    }
+
+    tracing::warn!("Shutdown deadline passed, rolling back in-flight requests");
+    SHUTDOWN.abort.cancel();
+
+    if tokio::time::timeout(ROLLBACK_TIMEOUT, SHUTDOWN.tracker.wait())
+        .await
+        .is_ok()
+    {
+        tracing::info!("All in-flight requests were rolled back");
+        return;
+    }
+
+    let interrupted = SHUTDOWN.in_flight.lock().unwrap().clone();
+
+    for name in interrupted {
+        tracing::warn!("Reconciliation of {} interrupted by shutdown", name);
+
+        let mut request = Request::mock();
+        request.metadata.name = Some(name);
+        request.status = Some(RequestStatus::new(ctx, &request).await);
+
+        request
+            .ready(false)
+            .message("Interrupted by shutdown, will be resumed on restart".to_string())
+            .update_status(ctx)
+            .await
+            .ok();
+    }
+}
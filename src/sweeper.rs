@@ -0,0 +1,122 @@
+use crate::traits::delete::DeleteOpt;
+use crate::traits::meta::{MANAGED_BY_SELECTOR, REQUEST_UID_LABEL};
+use crate::{context::Context, crd::Request};
+use k8s_openapi::api::core::v1::{Secret, ServiceAccount};
+use k8s_openapi::api::rbac::v1::ClusterRoleBinding;
+use kube::api::{DeleteParams, ListParams};
+use kube::{Api, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::fmt::Debug;
+
+/// Resources younger than this are skipped, as their Request may still be settling
+const GRACE_PERIOD_SECONDS: i64 = 300;
+
+/// Periodically deletes resources managed by Kufefe whose Request no longer exists
+pub async fn run(ctx: Context) {
+    let settings = ctx.settings();
+
+    tracing::info!(
+        "Starting sweeper for orphaned resources (dry run: {})",
+        settings.sweep_dry_run()
+    );
+
+    loop {
+        tokio::time::sleep(settings.sweep_interval()).await;
+        sweep(&ctx).await;
+    }
+}
+
+/// Sweeps every kind of resource Kufefe creates
+pub async fn sweep(ctx: &Context) {
+    let client = ctx.client();
+    let namespace = ctx.namespace();
+    let dry_run = ctx.settings().sweep_dry_run();
+
+    // Never sweep without knowing which Requests are alive
+    let live = match Request::api(ctx).list(&ListParams::default()).await {
+        Ok(list) => list.items.iter().filter_map(|r| r.uid()).collect(),
+        Err(e) => {
+            tracing::error!("Failed to list requests, skipping sweep: {}", e);
+            return;
+        }
+    };
+
+    sweep_api(
+        Api::<ServiceAccount>::namespaced(client.clone(), &namespace),
+        &live,
+        dry_run,
+    )
+    .await;
+    sweep_api(
+        Api::<Secret>::namespaced(client.clone(), &namespace),
+        &live,
+        dry_run,
+    )
+    .await;
+    sweep_api(Api::<ClusterRoleBinding>::all(client), &live, dry_run).await;
+}
+
+/// Deletes (or reports) the orphans of a single kind of resource
+async fn sweep_api<K>(api: Api<K>, live: &HashSet<String>, dry_run: bool)
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let kind = K::kind(&());
+
+    let list = match api
+        .list(&ListParams::default().labels(MANAGED_BY_SELECTOR))
+        .await
+    {
+        Ok(list) => list,
+        Err(e) => {
+            tracing::error!("Failed to list {}: {}", kind, e);
+            return;
+        }
+    };
+
+    for object in list.items.iter().filter(|o| is_orphan(*o, live)) {
+        if dry_run {
+            tracing::info!("Found orphaned {} {} (dry run)", kind, object.name_any());
+            continue;
+        }
+
+        tracing::info!("Deleting orphaned {} {}", kind, object.name_any());
+
+        if let Err(e) = api
+            .delete_opt(&object.name_any(), &DeleteParams::default())
+            .await
+        {
+            tracing::error!("Failed to delete {} {}: {}", kind, object.name_any(), e);
+        }
+    }
+}
+
+/// Checks if a resource has no live owning Request
+fn is_orphan<K: Resource>(object: &K, live: &HashSet<String>) -> bool {
+    let meta = object.meta();
+
+    let age = meta
+        .creation_timestamp
+        .as_ref()
+        .map(|t| chrono::Utc::now().timestamp() - t.0.timestamp())
+        .unwrap_or_default();
+
+    if age < GRACE_PERIOD_SECONDS {
+        return false;
+    }
+
+    let request_uid = object.labels().get(REQUEST_UID_LABEL).cloned().or_else(|| {
+        object
+            .owner_references()
+            .iter()
+            .find(|o| o.kind == "Request")
+            .map(|o| o.uid.clone())
+    });
+
+    match request_uid {
+        Some(uid) => !live.contains(&uid),
+        // Anything owned by another resource is garbage collected by Kubernetes
+        None => object.owner_references().is_empty(),
+    }
+}
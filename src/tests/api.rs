@@ -0,0 +1,369 @@
+use super::expiry::expiring;
+use super::fake::{FakeApi, NAMESPACE};
+use super::{request_path, role, role_path};
+use crate::api::auth::{self, Caller};
+use crate::api::error::ApiError;
+use crate::api::oidc::{self, Jwk, Jwks};
+use crate::api::requests::{self, Issue};
+use crate::api::ui;
+use crate::config::{ConfigArgs, KufefeConfig};
+use crate::crd::{CredentialMode, OutputFormat, APPROVAL_REQUIRED};
+use crate::error::Error;
+use crate::resources::role::Role;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hyper::{Method, StatusCode};
+use openssl::{hash::MessageDigest, pkey::PKey, rsa::Rsa, sign::Signer};
+use serde_json::{json, Value};
+
+const ISSUER: &str = "https://issuer.test";
+const CLIENT_ID: &str = "kufefe";
+const NOW: i64 = 1_700_000_000;
+
+fn token_reviews() -> String {
+    "/apis/authentication.k8s.io/v1/tokenreviews".to_string()
+}
+
+fn caller(groups: &[&str]) -> Caller {
+    Caller {
+        username: "jane@example.com".to_string(),
+        groups: groups.iter().map(|g| g.to_string()).collect(),
+    }
+}
+
+/// Signs claims with a fresh key and returns the token and the matching key set
+fn sign(claims: Value) -> (String, Jwks) {
+    let rsa = Rsa::generate(2048).unwrap();
+    let encode = |bytes: &[u8]| URL_SAFE_NO_PAD.encode(bytes);
+    let jwks = Jwks {
+        keys: vec![Jwk {
+            kid: Some("key-1".to_string()),
+            kty: "RSA".to_string(),
+            n: Some(encode(&rsa.n().to_vec())),
+            e: Some(encode(&rsa.e().to_vec())),
+        }],
+    };
+
+    let header = encode(
+        json!({ "alg": "RS256", "kid": "key-1" })
+            .to_string()
+            .as_bytes(),
+    );
+    let payload = encode(claims.to_string().as_bytes());
+    let signed = format!("{}.{}", header, payload);
+
+    let key = PKey::from_rsa(rsa).unwrap();
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+    signer.update(signed.as_bytes()).unwrap();
+    let signature = encode(&signer.sign_to_vec().unwrap());
+
+    (format!("{}.{}", signed, signature), jwks)
+}
+
+fn claims() -> Value {
+    json!({
+        "iss": ISSUER,
+        "aud": [CLIENT_ID, "other"],
+        "exp": NOW + 300,
+        "sub": "1234",
+        "email": "jane@example.com",
+        "email_verified": true,
+        "groups": ["oncall"],
+    })
+}
+
+#[tokio::test]
+async fn authenticates_tokens_through_token_reviews() {
+    let (fake, ctx) = FakeApi::start();
+    fake.reply(
+        Method::POST,
+        &token_reviews(),
+        json!({
+            "apiVersion": "authentication.k8s.io/v1",
+            "kind": "TokenReview",
+            "spec": {},
+            "status": {
+                "authenticated": true,
+                "user": { "username": "jane@example.com", "groups": ["oncall"] },
+            },
+        }),
+    );
+
+    let reviewed = auth::token_review(&ctx, "token").await.unwrap();
+    assert_eq!(reviewed, caller(&["oncall"]));
+    assert_eq!(
+        fake.body(Method::POST, &token_reviews())["spec"]["token"],
+        "token"
+    );
+
+    // The fake echoes the review without a status, as for an unknown token
+    let rejected = auth::token_review(&ctx, "invalid").await;
+    assert!(matches!(rejected, Err(ApiError::Unauthorized { .. })));
+}
+
+#[test]
+fn restricts_callers_to_allowed_groups() {
+    let settings = KufefeConfig::load(ConfigArgs {
+        api_allowed_groups: Some(vec!["oncall".to_string()]),
+        ..ConfigArgs::default()
+    })
+    .unwrap();
+
+    assert!(auth::authorize(&settings, caller(&["dev", "oncall"])).is_ok());
+
+    let denied = auth::authorize(&settings, caller(&["dev"])).unwrap_err();
+    assert_eq!(denied.status(), StatusCode::FORBIDDEN);
+
+    // Without allowed groups nobody gets in, not even authenticated ServiceAccounts
+    let settings = KufefeConfig::load(ConfigArgs::default()).unwrap();
+    let denied = auth::authorize(&settings, caller(&["system:serviceaccounts"]));
+    assert_eq!(denied.unwrap_err().status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn verifies_oidc_id_tokens() {
+    let (token, jwks) = sign(claims());
+    let verified = oidc::verify(&token, &jwks, ISSUER, CLIENT_ID, NOW).unwrap();
+    assert_eq!(verified, caller(&["oncall"]));
+
+    // Expired, for another client, from another issuer or tampered with
+    assert!(oidc::verify(&token, &jwks, ISSUER, CLIENT_ID, NOW + 600).is_err());
+    assert!(oidc::verify(&token, &jwks, ISSUER, "another", NOW).is_err());
+    assert!(oidc::verify(&token, &jwks, "https://evil.test", CLIENT_ID, NOW).is_err());
+
+    let (forged, _) = sign(claims());
+    assert!(oidc::verify(&forged, &jwks, ISSUER, CLIENT_ID, NOW).is_err());
+
+    // An unverified email could belong to anyone, so the subject names the caller
+    let mut unverified = claims();
+    unverified["email_verified"] = json!(false);
+    let (token, jwks) = sign(unverified);
+    let verified = oidc::verify(&token, &jwks, ISSUER, CLIENT_ID, NOW).unwrap();
+    assert_eq!(verified.username, "oidc:https://issuer.test#1234");
+}
+
+#[tokio::test]
+async fn rejects_requests_outside_policy_before_creating_them() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&role_path("edit"), role("edit", false));
+
+    let issue = Issue {
+        role: "edit".to_string(),
+        ..Issue::default()
+    };
+    let result = requests::issue(&ctx, &caller(&[]), issue).await;
+
+    assert!(matches!(
+        result,
+        Err(ApiError::Kufefe(Error::RoleNotAllowed { .. }))
+    ));
+    assert_eq!(result.unwrap_err().status(), StatusCode::FORBIDDEN);
+
+    // Exec plugin kubeconfigs have no static token to render these formats with
+    let issue = Issue {
+        role: "edit".to_string(),
+        credential_mode: Some(CredentialMode::Exec),
+        output_format: Some(OutputFormat::Env),
+        ..Issue::default()
+    };
+    let result = requests::issue(&ctx, &caller(&[]), issue).await;
+
+    assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+    assert!(fake
+        .requests(Method::POST, "/apis/kufefe.io/v1/requests")
+        .is_empty());
+}
+
+#[tokio::test]
+async fn hides_requests_of_other_callers() {
+    let (fake, ctx) = FakeApi::start();
+    let mut request = super::request("view-1", "uid-1", "view");
+    request["metadata"]["annotations"] =
+        json!({ requests::REQUESTED_BY_ANNOTATION: "someone@example.com" });
+    fake.insert(&request_path("view-1"), request);
+
+    let request = crate::crd::Request::api(&ctx).get("view-1").await.unwrap();
+    assert!(!requests::owned_by(&request, &caller(&[])));
+}
+
+#[tokio::test]
+async fn revokes_only_requests_of_the_caller() {
+    let (fake, ctx) = FakeApi::start();
+    for (name, owner) in [
+        ("view-1", "jane@example.com"),
+        ("view-2", "someone@example.com"),
+    ] {
+        let mut request = super::request(name, name, "view");
+        request["metadata"]["annotations"] =
+            json!({ requests::REQUESTED_BY_ANNOTATION: owner });
+        fake.insert(&request_path(name), request);
+    }
+
+    let owned = requests::list(&ctx, &caller(&[])).await.unwrap();
+    assert_eq!(owned.len(), 1);
+
+    let result = requests::revoke(&ctx, &caller(&[]), "view-2").await;
+    assert!(matches!(result, Err(ApiError::NotFound)));
+    assert!(fake.get(&request_path("view-2")).is_some());
+
+    requests::revoke(&ctx, &caller(&[]), "view-1")
+        .await
+        .unwrap();
+    assert!(fake.get(&request_path("view-1")).is_none());
+}
+
+#[tokio::test]
+async fn mints_tokens_only_for_live_requests_of_the_caller() {
+    let (fake, ctx) = FakeApi::start();
+    for (name, owner, offset) in [
+        ("exec-1", "jane@example.com", 3600),
+        ("exec-2", "someone@example.com", 3600),
+        ("exec-3", "jane@example.com", -60),
+    ] {
+        let mut request = expiring(name, offset);
+        request["spec"]["credentialMode"] = json!("exec");
+        request["metadata"]["annotations"] =
+            json!({ requests::REQUESTED_BY_ANNOTATION: owner });
+        fake.insert(&request_path(name), request);
+    }
+
+    let token_path = |name: &str| {
+        format!(
+            "/api/v1/namespaces/{}/serviceaccounts/{}/token",
+            NAMESPACE, name
+        )
+    };
+    let expiration = chrono::Utc::now() + chrono::Duration::hours(2);
+    fake.reply(
+        Method::POST,
+        &token_path("exec-1"),
+        json!({
+            "apiVersion": "authentication.k8s.io/v1",
+            "kind": "TokenRequest",
+            "spec": { "audiences": [] },
+            "status": { "token": "minted", "expirationTimestamp": expiration },
+        }),
+    );
+
+    // The token is cut off at the expiry of the Request
+    let token = requests::mint(&ctx, &caller(&[]), "exec-1", 600)
+        .await
+        .unwrap();
+    let request = crate::crd::Request::api(&ctx).get("exec-1").await.unwrap();
+
+    assert_eq!(token.token, "minted");
+    assert_eq!(Some(token.expires_at), request.status.unwrap().expires_at);
+    assert_eq!(
+        fake.body(Method::POST, &token_path("exec-1"))["spec"]["expirationSeconds"],
+        600
+    );
+
+    // Requests of others are hidden, expired ones refused on the server
+    let other = requests::mint(&ctx, &caller(&[]), "exec-2", 600).await;
+    assert!(matches!(other, Err(ApiError::NotFound)));
+
+    let expired = requests::mint(&ctx, &caller(&[]), "exec-3", 600).await;
+    assert_eq!(expired.unwrap_err().status(), StatusCode::FORBIDDEN);
+    assert!(fake
+        .requests(Method::POST, &token_path("exec-3"))
+        .is_empty());
+}
+
+#[tokio::test]
+async fn approves_only_waiting_requests_of_others() {
+    let (fake, ctx) = FakeApi::start();
+    for (name, owner, waiting) in [
+        ("admin-1", "someone@example.com", true),
+        ("admin-2", "jane@example.com", true),
+        ("admin-3", "someone@example.com", false),
+    ] {
+        let mut request = super::request(name, name, "admin");
+        request["metadata"]["annotations"] =
+            json!({ requests::REQUESTED_BY_ANNOTATION: owner });
+        request["status"] = json!({
+            "ready": !waiting,
+            "failed": false,
+            "message": "",
+            "serviceAccountName": "",
+            "tokenName": "",
+            "rolebindingName": "",
+            "conditions": [{
+                "type": "Ready",
+                "status": if waiting { "False" } else { "True" },
+                "reason": if waiting { APPROVAL_REQUIRED } else { "Provisioned" },
+                "message": "",
+            }],
+        });
+        fake.insert(&request_path(name), request);
+    }
+
+    let settings = KufefeConfig::load(ConfigArgs {
+        namespace: Some(NAMESPACE.to_string()),
+        api_port: Some(8443),
+        api_tls_cert: Some("tls.crt".into()),
+        api_tls_key: Some("tls.key".into()),
+        api_allowed_groups: Some(vec!["oncall".to_string(), "security".to_string()]),
+        api_approver_groups: Some(vec!["security".to_string()]),
+        ..ConfigArgs::default()
+    })
+    .unwrap();
+    ctx.set_settings(settings);
+
+    let result = requests::grant(&ctx, &caller(&["oncall"]), "admin-1").await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::FORBIDDEN);
+
+    // Approvers can't approve their own Requests, nor those not waiting for it
+    let approver = caller(&["security"]);
+    let own = requests::grant(&ctx, &approver, "admin-2").await;
+    assert_eq!(own.unwrap_err().status(), StatusCode::FORBIDDEN);
+
+    let ready = requests::grant(&ctx, &approver, "admin-3").await;
+    assert_eq!(ready.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+    let approved = requests::grant(&ctx, &approver, "admin-1").await.unwrap();
+    let stored = crate::crd::Request::api(&ctx).get("admin-1").await.unwrap();
+
+    assert!(approved.approval_granted());
+    assert!(stored.approval_granted());
+    assert_eq!(
+        stored.status.unwrap().approved_by.as_deref(),
+        Some("jane@example.com")
+    );
+
+    // Callers are told their Request waits for approval
+    let pending = crate::crd::Request::api(&ctx).get("admin-2").await.unwrap();
+    let result = requests::issued(pending);
+    assert_eq!(result.unwrap_err().status(), StatusCode::ACCEPTED);
+}
+
+#[tokio::test]
+async fn offers_only_annotated_roles() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&role_path("view"), role("view", true));
+    fake.insert(&role_path("edit"), role("edit", false));
+
+    assert_eq!(Role::new(&ctx).list().await.unwrap(), vec!["view"]);
+}
+
+#[tokio::test]
+async fn refuses_large_bodies() {
+    let small = requests::read(hyper::Body::from("csrf=abc")).await.unwrap();
+    assert_eq!(&small[..], b"csrf=abc");
+
+    let large = requests::read(hyper::Body::from(vec![b'a'; 65 * 1024])).await;
+    assert_eq!(large.unwrap_err().status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn escapes_html() {
+    assert_eq!(
+        ui::escape("<a href=\"x\">'&'</a>"),
+        "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+    );
+}
+
+#[test]
+fn derives_csrf_tokens_from_sessions() {
+    assert_eq!(ui::csrf("token"), ui::csrf("token"));
+    assert_ne!(ui::csrf("token"), ui::csrf("other"));
+    assert!(!ui::csrf("token").contains("token"));
+}
@@ -0,0 +1,160 @@
+use super::fake::{FakeApi, NAMESPACE};
+use super::{parse, request, request_path, role, role_path};
+use crate::clusters::FINALIZER;
+use crate::context::Endpoint;
+use crate::{sweeper, transaction::Transaction, watcher};
+use hyper::Method;
+use serde_json::json;
+
+const NAME: &str = "edge-access";
+const UID: &str = "9a8b7c6d-1111-2222-3333-444455556666";
+const GENERATED: &str = "kufefe-edge-access-9a8b7c6d";
+
+#[tokio::test]
+async fn provisions_request_in_target_cluster() {
+    let (hub, ctx) = FakeApi::start();
+    let (edge, edge_ctx) = FakeApi::start();
+    let target = ctx.target(
+        "edge",
+        edge_ctx.client(),
+        NAMESPACE.to_string(),
+        Endpoint {
+            url: "https://edge.test:6443".to_string(),
+            provider: "cluster".to_string(),
+            ca: None,
+        },
+    );
+
+    let mut resource = request(NAME, UID, "view");
+    resource["spec"]["cluster"] = json!("edge");
+    hub.insert(&request_path(NAME), resource);
+    edge.insert(&role_path("view"), role("view", true));
+
+    let resource = parse(hub.get(&request_path(NAME)).unwrap());
+    watcher::added(&target, resource, &mut Transaction::default())
+        .await
+        .unwrap();
+
+    // Resources are created in the target, without an owner that doesn't exist there
+    let sa = edge.body(
+        Method::POST,
+        &format!("/api/v1/namespaces/{}/serviceaccounts", NAMESPACE),
+    );
+    let binding = edge.body(
+        Method::POST,
+        "/apis/rbac.authorization.k8s.io/v1/clusterrolebindings",
+    );
+
+    assert_eq!(sa["metadata"]["name"], GENERATED);
+    assert!(sa["metadata"].get("ownerReferences").is_none());
+    assert!(binding["metadata"].get("ownerReferences").is_none());
+    assert_eq!(sa["metadata"]["labels"]["kufefe.io/request-uid"], UID);
+
+    // The Request in the hub holds the finalizer and a kubeconfig for the target
+    let status = parse(hub.get(&request_path(NAME)).unwrap());
+    let kubeconfig: serde_yaml::Value =
+        serde_yaml::from_str(status.status.unwrap().kubeconfig.as_deref().unwrap())
+            .unwrap();
+
+    assert_eq!(
+        status.metadata.finalizers,
+        Some(vec![FINALIZER.to_string()])
+    );
+    assert_eq!(
+        kubeconfig["clusters"][0]["cluster"]["server"],
+        "https://edge.test:6443"
+    );
+    assert!(hub
+        .requests(
+            Method::POST,
+            &format!("/api/v1/namespaces/{}/serviceaccounts", NAMESPACE)
+        )
+        .is_empty());
+}
+
+#[tokio::test]
+async fn fails_request_for_unregistered_cluster() {
+    let (fake, ctx) = FakeApi::start();
+    let mut resource = request(NAME, UID, "view");
+    resource["spec"]["cluster"] = json!("unknown");
+    fake.insert(&request_path(NAME), resource);
+
+    watcher::process(&ctx, parse(fake.get(&request_path(NAME)).unwrap())).await;
+
+    let status = parse(fake.get(&request_path(NAME)).unwrap())
+        .status
+        .unwrap();
+
+    assert!(status.failed);
+    assert_eq!(status.conditions.unwrap()[0].reason, "ClusterNotFound");
+}
+
+#[tokio::test]
+async fn target_cluster_does_not_sweep_resources_of_hub() {
+    let (hub, ctx) = FakeApi::start();
+    let (edge, edge_ctx) = FakeApi::start();
+    let target = ctx.target(
+        "edge",
+        edge_ctx.client(),
+        NAMESPACE.to_string(),
+        Endpoint {
+            url: "https://edge.test:6443".to_string(),
+            provider: "cluster".to_string(),
+            ca: None,
+        },
+    );
+
+    let mut resource = request(NAME, UID, "view");
+    resource["spec"]["cluster"] = json!("edge");
+    hub.insert(&request_path(NAME), resource);
+    edge.insert(&role_path("view"), role("view", true));
+
+    let resource = parse(hub.get(&request_path(NAME)).unwrap());
+    watcher::added(&target, resource, &mut Transaction::default())
+        .await
+        .unwrap();
+
+    // A leftover of the Kufefe running in the target cluster itself
+    let orphan = format!("/api/v1/namespaces/{}/serviceaccounts/orphan", NAMESPACE);
+    edge.insert(
+        &orphan,
+        json!({
+            "apiVersion": "v1",
+            "kind": "ServiceAccount",
+            "metadata": {
+                "name": "orphan",
+                "namespace": NAMESPACE,
+                "labels": {
+                    "app.kubernetes.io/managed-by": "kufefe",
+                    "kufefe.io/request-uid": "gone",
+                },
+            },
+        }),
+    );
+
+    // Age everything past the grace period, the Request isn't known to the target
+    let paths = [
+        format!(
+            "/api/v1/namespaces/{}/serviceaccounts/{}",
+            NAMESPACE, GENERATED
+        ),
+        format!("/api/v1/namespaces/{}/secrets/{}", NAMESPACE, GENERATED),
+        format!(
+            "/apis/rbac.authorization.k8s.io/v1/clusterrolebindings/{}",
+            GENERATED
+        ),
+    ];
+    for path in paths.iter().chain([&orphan]) {
+        let mut object = edge.get(path).unwrap();
+        object["metadata"]["creationTimestamp"] = json!("2020-01-01T00:00:00Z");
+        edge.insert(path, object);
+    }
+
+    sweeper::sweep(&edge_ctx).await;
+
+    for path in &paths {
+        let object = edge.get(path).unwrap();
+        assert_eq!(object["metadata"]["labels"]["kufefe.io/hub"], "kufefe");
+    }
+    assert!(edge.get(&orphan).is_none());
+}
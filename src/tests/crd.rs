@@ -0,0 +1,10 @@
+use crate::crd::manifest;
+
+#[test]
+fn chart_crd_matches_types() {
+    assert!(
+        manifest() == include_str!("../../charts/kufefe/templates/crd.yaml"),
+        "charts/kufefe/templates/crd.yaml is out of date, regenerate it with \
+         `cargo run -- crd > charts/kufefe/templates/crd.yaml`"
+    );
+}
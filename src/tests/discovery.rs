@@ -0,0 +1,209 @@
+use super::fake::FakeApi;
+use crate::config::{ConfigArgs, KufefeConfig};
+use crate::discovery;
+use hyper::Method;
+use serde_json::json;
+
+const ORDER: &[&str] = &["anthos", "openshift", "cluster-info", "endpoints"];
+
+fn order() -> Vec<String> {
+    ORDER.iter().map(|p| p.to_string()).collect()
+}
+
+fn endpoints() -> serde_json::Value {
+    json!({
+        "apiVersion": "v1",
+        "kind": "Endpoints",
+        "metadata": { "name": "kubernetes", "namespace": "default" },
+        "subsets": [{
+            "addresses": [{ "ip": "10.0.0.1" }],
+            "ports": [{ "name": "https", "port": 6443, "protocol": "TCP" }],
+        }],
+    })
+}
+
+/// Serves an API group through discovery, as an operator installing its CRDs would
+fn serve(fake: &FakeApi, group: &str, version: &str, kind: &str, plural: &str) {
+    let group_version = format!("{}/{}", group, version);
+
+    fake.reply(
+        Method::GET,
+        "/apis",
+        json!({
+            "kind": "APIGroupList",
+            "apiVersion": "v1",
+            "groups": [{
+                "name": group,
+                "versions": [{ "groupVersion": group_version, "version": version }],
+                "preferredVersion": { "groupVersion": group_version, "version": version },
+            }],
+        }),
+    );
+    fake.reply(
+        Method::GET,
+        &format!("/apis/{}", group_version),
+        json!({
+            "kind": "APIResourceList",
+            "apiVersion": "v1",
+            "groupVersion": group_version,
+            "resources": [{
+                "name": plural,
+                "singularName": "",
+                "namespaced": true,
+                "kind": kind,
+                "verbs": ["get", "list", "watch"],
+            }],
+        }),
+    );
+}
+
+#[tokio::test]
+async fn discovers_url_of_eks_clusters() {
+    let (fake, ctx) = FakeApi::start();
+    serve(
+        &fake,
+        "eks.services.k8s.aws",
+        "v1alpha1",
+        "Cluster",
+        "clusters",
+    );
+    for (name, id) in [("dev", "D3V"), ("prod", "PR0D")] {
+        fake.insert(
+            &format!("/apis/eks.services.k8s.aws/v1alpha1/clusters/{}", name),
+            json!({
+                "apiVersion": "eks.services.k8s.aws/v1alpha1",
+                "kind": "Cluster",
+                "metadata": { "name": name, "namespace": "infra" },
+                "status": {
+                    "endpoint": format!("https://{}.gr7.eu-west-1.eks.amazonaws.com", id),
+                },
+            }),
+        );
+    }
+    let settings = KufefeConfig::load(ConfigArgs {
+        cluster_name: Some("prod".to_string()),
+        ..ConfigArgs::default()
+    })
+    .unwrap();
+
+    let chain = discovery::chain(&["eks".to_string()], &settings).unwrap();
+    let discovered = discovery::discover(&ctx.client(), &chain).await.unwrap();
+
+    assert_eq!(
+        discovered.url,
+        "https://PR0D.gr7.eu-west-1.eks.amazonaws.com"
+    );
+    assert_eq!(discovered.provider, "eks");
+}
+
+#[tokio::test]
+async fn discovers_eks_endpoint_from_kube_proxy() {
+    let (fake, ctx) = FakeApi::start();
+    fake.reply(
+        Method::GET,
+        "/apis",
+        json!({ "kind": "APIGroupList", "apiVersion": "v1", "groups": [] }),
+    );
+    fake.insert(
+        "/api/v1/namespaces/kube-system/configmaps/kube-proxy",
+        json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "kube-proxy", "namespace": "kube-system" },
+            "data": {
+                "kubeconfig": "apiVersion: v1\nkind: Config\nclusters:\n- name: default\n  cluster:\n    server: https://D3V.gr7.eu-west-1.eks.amazonaws.com\n",
+            },
+        }),
+    );
+
+    let chain = discovery::chain(&["eks".to_string()], &ctx.settings()).unwrap();
+    let discovered = discovery::discover(&ctx.client(), &chain).await.unwrap();
+
+    assert_eq!(
+        discovered.url,
+        "https://D3V.gr7.eu-west-1.eks.amazonaws.com"
+    );
+    assert_eq!(discovered.provider, "eks");
+}
+
+#[tokio::test]
+async fn discovers_private_fqdn_of_aks_clusters() {
+    let (fake, ctx) = FakeApi::start();
+    serve(
+        &fake,
+        "containerservice.azure.com",
+        "v1api20230201",
+        "ManagedCluster",
+        "managedclusters",
+    );
+    fake.insert(
+        "/apis/containerservice.azure.com/v1api20230201/managedclusters/dev",
+        json!({
+            "apiVersion": "containerservice.azure.com/v1api20230201",
+            "kind": "ManagedCluster",
+            "metadata": { "name": "dev", "namespace": "infra" },
+            "status": {
+                "apiServerAccessProfile": { "enablePrivateCluster": true },
+                "fqdn": "dev-1a2b3c4d.hcp.westeurope.azmk8s.io",
+                "privateFQDN": "dev-1a2b3c4d.privatelink.westeurope.azmk8s.io",
+            },
+        }),
+    );
+
+    let chain = discovery::chain(&["aks".to_string()], &ctx.settings()).unwrap();
+    let discovered = discovery::discover(&ctx.client(), &chain).await.unwrap();
+
+    assert_eq!(
+        discovered.url,
+        "https://dev-1a2b3c4d.privatelink.westeurope.azmk8s.io:443"
+    );
+    assert_eq!(discovered.provider, "aks");
+}
+
+#[tokio::test]
+async fn discovers_url_from_cluster_info() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(
+        "/api/v1/namespaces/default/endpoints/kubernetes",
+        endpoints(),
+    );
+    fake.insert(
+        "/api/v1/namespaces/kube-public/configmaps/cluster-info",
+        json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": "cluster-info", "namespace": "kube-public" },
+            "data": {
+                "kubeconfig": "apiVersion: v1\nkind: Config\nclusters:\n- name: ''\n  cluster:\n    server: https://api.example.com:6443\n",
+            },
+        }),
+    );
+
+    let chain = discovery::chain(&order(), &ctx.settings()).unwrap();
+    let discovered = discovery::discover(&ctx.client(), &chain).await.unwrap();
+
+    assert_eq!(discovered.url, "https://api.example.com:6443");
+    assert_eq!(discovered.provider, "cluster-info");
+}
+
+#[tokio::test]
+async fn falls_back_to_endpoints() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(
+        "/api/v1/namespaces/default/endpoints/kubernetes",
+        endpoints(),
+    );
+
+    let chain = discovery::chain(&order(), &ctx.settings()).unwrap();
+    let discovered = discovery::discover(&ctx.client(), &chain).await.unwrap();
+
+    assert_eq!(discovered.url, "https://10.0.0.1:6443");
+    assert_eq!(discovered.provider, "endpoints");
+}
+
+#[test]
+fn rejects_unknown_provider() {
+    let settings = KufefeConfig::for_tests("kufefe");
+
+    assert!(discovery::chain(&["rancher".to_string()], &settings).is_err());
+}
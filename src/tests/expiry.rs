@@ -0,0 +1,41 @@
+use super::fake::FakeApi;
+use super::{request, request_path};
+use crate::crd::Request;
+use hyper::Method;
+use serde_json::{json, Value};
+
+/// A ready Request expiring at the given offset from now
+pub fn expiring(name: &str, offset: i64) -> Value {
+    let mut request = request(name, &format!("uid-{}", name), "view");
+    request["status"] = json!({
+        "ready": true,
+        "failed": false,
+        "message": "Completed",
+        "serviceAccountName": name,
+        "tokenName": name,
+        "rolebindingName": name,
+        "expiresAt": chrono::Utc::now().timestamp() + offset,
+    });
+
+    request
+}
+
+#[tokio::test]
+async fn scan_deletes_expired_requests() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&request_path("expired"), expiring("expired", -60));
+    fake.insert(&request_path("valid"), expiring("valid", 3600));
+
+    Request::mock().scan(&ctx).await;
+
+    assert_eq!(
+        fake.requests(Method::DELETE, &request_path("expired"))
+            .len(),
+        1
+    );
+    assert!(fake
+        .requests(Method::DELETE, &request_path("valid"))
+        .is_empty());
+    assert!(fake.get(&request_path("expired")).is_none());
+    assert!(fake.get(&request_path("valid")).is_some());
+}
@@ -0,0 +1,326 @@
+use crate::{config::KufefeConfig, context::Context};
+use base64::{engine::general_purpose, Engine as _};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use kube::Client;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Namespace the fake controller provisions resources in
+pub const NAMESPACE: &str = "kufefe";
+
+/// A request the fake API server received
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: Method,
+    pub path: String,
+    pub body: Option<Value>,
+}
+
+#[derive(Default)]
+struct State {
+    objects: BTreeMap<String, Value>,
+    recorded: Vec<Recorded>,
+    failures: Vec<(Method, String, u16)>,
+    replies: Vec<(Method, String, Value)>,
+    next_uid: u32,
+    token_controller_stopped: bool,
+}
+
+/// An in-memory Kubernetes API server, storing objects by their URL path
+#[derive(Clone, Default)]
+pub struct FakeApi {
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeApi {
+    /// Starts a fake API server and returns a Context talking to it
+    pub fn start() -> (Self, Context) {
+        let fake = Self::default();
+        let (service, mut handle) =
+            tower_test::mock::pair::<Request<Body>, Response<Body>>();
+
+        let server = fake.clone();
+        tokio::spawn(async move {
+            while let Some((request, send)) = handle.next_request().await {
+                send.send_response(server.handle(request).await);
+            }
+        });
+
+        let client = Client::new(service, NAMESPACE);
+        let ctx = Context::new(client, KufefeConfig::for_tests(NAMESPACE));
+
+        (fake, ctx)
+    }
+
+    /// Stores an object at the given path
+    pub fn insert(&self, path: &str, object: Value) {
+        let mut state = self.state.lock().unwrap();
+        state.objects.insert(path.to_string(), object);
+    }
+
+    /// Gets the object stored at the given path
+    pub fn get(&self, path: &str) -> Option<Value> {
+        self.state.lock().unwrap().objects.get(path).cloned()
+    }
+
+    /// Makes the next request with the given method and path fail with a status code
+    pub fn fail(&self, method: Method, path: &str, code: u16) {
+        let mut state = self.state.lock().unwrap();
+        state.failures.push((method, path.to_string(), code));
+    }
+
+    /// Makes the next request with the given method and path return an object as is
+    pub fn reply(&self, method: Method, path: &str, object: Value) {
+        let mut state = self.state.lock().unwrap();
+        state.replies.push((method, path.to_string(), object));
+    }
+
+    /// Leaves token Secrets created from now on without a token
+    pub fn stop_token_controller(&self) {
+        self.state.lock().unwrap().token_controller_stopped = true;
+    }
+
+    /// Gets every recorded request with the given method and path
+    pub fn requests(&self, method: Method, path: &str) -> Vec<Recorded> {
+        self.state
+            .lock()
+            .unwrap()
+            .recorded
+            .iter()
+            .filter(|r| r.method == method && r.path == path)
+            .cloned()
+            .collect()
+    }
+
+    /// Gets the body of the only request with the given method and path
+    pub fn body(&self, method: Method, path: &str) -> Value {
+        let requests = self.requests(method.clone(), path);
+        assert_eq!(requests.len(), 1, "expected a single {} {}", method, path);
+
+        requests[0].body.clone().unwrap()
+    }
+
+    /// Serves a single request
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        let query: BTreeMap<String, String> =
+            form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes())
+                .into_owned()
+                .collect();
+
+        let bytes = hyper::body::to_bytes(request.into_body()).await.unwrap();
+        let body: Option<Value> = serde_json::from_slice(&bytes).ok();
+
+        let mut state = self.state.lock().unwrap();
+        state.recorded.push(Recorded {
+            method: method.clone(),
+            path: path.clone(),
+            body: body.clone(),
+        });
+
+        if let Some(i) = state
+            .failures
+            .iter()
+            .position(|(m, p, _)| *m == method && *p == path)
+        {
+            let (_, _, code) = state.failures.remove(i);
+            return status(code, "Injected failure");
+        }
+
+        if let Some(i) = state
+            .replies
+            .iter()
+            .position(|(m, p, _)| *m == method && *p == path)
+        {
+            let (_, _, object) = state.replies.remove(i);
+            return ok(object);
+        }
+
+        // Status updates apply to the object itself
+        let (path, collection) = match classify(&path) {
+            Some(Kind::Collection) => (path, true),
+            Some(Kind::Object) => (path, false),
+            Some(Kind::Subresource(object)) => (object, false),
+            None => return status(404, "Unknown path"),
+        };
+
+        match (method, collection) {
+            (Method::GET, true) if query.contains_key("watch") => {
+                Response::new(Body::empty())
+            }
+            (Method::GET, true) => {
+                let name = query
+                    .get("fieldSelector")
+                    .and_then(|s| s.strip_prefix("metadata.name="));
+                let labels = query.get("labelSelector");
+                let items: Vec<Value> = state
+                    .objects
+                    .iter()
+                    .filter(|(p, _)| parent(p) == path)
+                    .map(|(_, o)| o.clone())
+                    .filter(|o| name.is_none() || o["metadata"]["name"] == name.unwrap())
+                    .filter(|o| labels.is_none_or(|l| selects(l, o)))
+                    .collect();
+
+                ok(json!({
+                    "apiVersion": "v1",
+                    "kind": "List",
+                    "metadata": { "resourceVersion": "1" },
+                    "items": items,
+                }))
+            }
+            (Method::POST, true) => {
+                let mut object = body.unwrap_or_default();
+                let generate = object["metadata"]["generateName"].as_str();
+                if let Some(prefix) =
+                    generate.filter(|_| object["metadata"]["name"].is_null())
+                {
+                    object["metadata"]["name"] =
+                        json!(format!("{}{}", prefix, state.next_uid + 1));
+                }
+
+                let name = object["metadata"]["name"].as_str().unwrap_or_default();
+                let object_path = format!("{}/{}", path, name);
+
+                if state.objects.contains_key(&object_path) {
+                    return status(409, "AlreadyExists");
+                }
+
+                state.next_uid += 1;
+                object["metadata"]["uid"] = json!(format!("uid-{}", state.next_uid));
+                object["metadata"]["resourceVersion"] = json!("1");
+                object["metadata"]["creationTimestamp"] =
+                    json!(chrono::Utc::now().to_rfc3339());
+
+                // Act as the token controller, which fills in token Secrets
+                if object["type"] == "kubernetes.io/service-account-token"
+                    && !state.token_controller_stopped
+                {
+                    object["data"] = json!({
+                        "ca.crt": general_purpose::STANDARD.encode("FAKE CA"),
+                        "token": general_purpose::STANDARD.encode("fake-token"),
+                    });
+                }
+
+                state.objects.insert(object_path, object.clone());
+                ok(object)
+            }
+            (Method::GET, false) => match state.objects.get(&path) {
+                Some(object) => ok(object.clone()),
+                None => status(404, "NotFound"),
+            },
+            (Method::PUT, false) => {
+                if !state.objects.contains_key(&path) {
+                    return status(404, "NotFound");
+                }
+
+                let object = body.unwrap_or_default();
+                state.objects.insert(path, object.clone());
+                ok(object)
+            }
+            (Method::PATCH, false) => match state.objects.get_mut(&path) {
+                Some(object) => {
+                    merge(object, body.unwrap_or_default());
+                    ok(object.clone())
+                }
+                None => status(404, "NotFound"),
+            },
+            (Method::DELETE, false) => match state.objects.remove(&path) {
+                Some(object) => ok(object),
+                None => status(404, "NotFound"),
+            },
+            _ => status(405, "MethodNotAllowed"),
+        }
+    }
+}
+
+/// Checks an object against a label selector such as "a=b,!c"
+fn selects(selector: &str, object: &Value) -> bool {
+    let labels = &object["metadata"]["labels"];
+
+    selector.split(',').all(|term| match term.split_once('=') {
+        Some((key, value)) => labels[key] == value,
+        None => match term.strip_prefix('!') {
+            Some(key) => labels.get(key).is_none(),
+            None => labels.get(term).is_some(),
+        },
+    })
+}
+
+enum Kind {
+    Collection,
+    Object,
+    Subresource(String),
+}
+
+/// Works out what a path points at, e.g. /api/v1/namespaces/{ns}/secrets/{name}
+fn classify(path: &str) -> Option<Kind> {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    let rest = match segments.as_slice() {
+        ["api", _, rest @ ..] => rest,
+        ["apis", _, _, rest @ ..] => rest,
+        _ => return None,
+    };
+
+    let rest = match rest {
+        ["namespaces", _, rest @ ..] if !rest.is_empty() => rest,
+        rest => rest,
+    };
+
+    match rest.len() {
+        1 => Some(Kind::Collection),
+        2 => Some(Kind::Object),
+        3 => Some(Kind::Subresource(parent(path).to_string())),
+        _ => None,
+    }
+}
+
+/// Applies a JSON merge patch
+fn merge(target: &mut Value, patch: Value) {
+    match patch {
+        Value::Object(patch) => {
+            if !target.is_object() {
+                *target = json!({});
+            }
+
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.as_object_mut().unwrap().remove(&key);
+                } else {
+                    merge(&mut target[&key], value);
+                }
+            }
+        }
+        patch => *target = patch,
+    }
+}
+
+/// Strips the last segment of a path
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or("")
+}
+
+fn ok(body: Value) -> Response<Body> {
+    Response::new(Body::from(body.to_string()))
+}
+
+fn status(code: u16, reason: &str) -> Response<Body> {
+    let body = json!({
+        "apiVersion": "v1",
+        "kind": "Status",
+        "status": "Failure",
+        "message": reason,
+        "reason": reason,
+        "code": code,
+    });
+
+    Response::builder()
+        .status(StatusCode::from_u16(code).unwrap())
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
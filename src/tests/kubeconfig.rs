@@ -0,0 +1,212 @@
+use crate::kubeconfig::file::{self, Issued, KubeconfigFile};
+use std::path::PathBuf;
+
+const ISSUED: &str = r#"
+apiVersion: v1
+kind: Config
+clusters:
+- name: kubernetes
+  cluster: { server: "https://kubernetes.test:6443" }
+contexts:
+- name: kubernetes
+  context: { cluster: kubernetes, user: kufefe-dev }
+current-context: kubernetes
+users:
+- name: kufefe-dev
+  user: { token: secret }
+"#;
+
+const EXISTING: &str = r#"
+apiVersion: v1
+kind: Config
+clusters:
+- name: mine
+  cluster: { server: "https://mine.test:6443" }
+contexts:
+- name: mine
+  context: { cluster: mine, user: mine }
+- name: kufefe-dev
+  context: { cluster: mine, user: mine }
+current-context: mine
+users:
+- name: mine
+  user: { token: mine }
+"#;
+
+/// A kubeconfig file in a temporary location, holding the given content
+fn file(name: &str, content: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("kufefe-{}-{}", name, std::process::id()));
+    std::fs::write(&path, content).unwrap();
+
+    path
+}
+
+fn origin(request: &str) -> Issued {
+    Issued {
+        request: request.to_string(),
+        server: "https://hub.test:6443".to_string(),
+    }
+}
+
+#[test]
+fn merges_under_collision_free_names() {
+    let path = file("merge", EXISTING);
+    let mut kubeconfig = KubeconfigFile::load(&path).unwrap();
+
+    // Merging the same Request again replaces its entries
+    kubeconfig
+        .merge(ISSUED, origin("dev"), None, false)
+        .unwrap();
+    let context = kubeconfig
+        .merge(ISSUED, origin("dev"), Some("team-a"), false)
+        .unwrap();
+    kubeconfig.save().unwrap();
+
+    let merged = kube::config::Kubeconfig::read_from(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(context, "kufefe-dev-2");
+    assert_eq!(merged.current_context.as_deref(), Some("mine"));
+    assert_eq!(merged.contexts.len(), 3);
+    assert_eq!(merged.clusters.len(), 2);
+    assert_eq!(merged.auth_infos.len(), 2);
+
+    let context = merged.contexts[2].context.as_ref().unwrap();
+    assert_eq!(context.cluster, "kufefe-dev-2");
+    assert_eq!(context.user, "kufefe-dev-2");
+    assert_eq!(context.namespace.as_deref(), Some("team-a"));
+}
+
+#[test]
+fn switches_the_current_context_on_request() {
+    let path = file("switch", EXISTING);
+    let mut kubeconfig = KubeconfigFile::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let context = kubeconfig.merge(ISSUED, origin("ops"), None, true).unwrap();
+
+    assert_eq!(context, "kufefe-ops");
+    assert_eq!(kubeconfig.current_context(), Some("kufefe-ops"));
+}
+
+#[test]
+fn prunes_only_expired_entries() {
+    let path = file("prune", EXISTING);
+    let mut kubeconfig = KubeconfigFile::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    kubeconfig.merge(ISSUED, origin("old"), None, true).unwrap();
+    kubeconfig
+        .merge(ISSUED, origin("new"), None, false)
+        .unwrap();
+
+    let pruned = kubeconfig.prune(|issued| issued.request == "old");
+    let remaining: Vec<String> = kubeconfig
+        .issued()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+
+    assert_eq!(pruned, vec!["kufefe-old".to_string()]);
+    assert_eq!(remaining, vec!["kufefe-new".to_string()]);
+    assert_eq!(kubeconfig.current_context(), None);
+}
+
+#[test]
+fn keeps_what_kufefe_does_not_know_about() {
+    let existing = format!(
+        "{}preferences: {{ colors: true }}\nx-team: platform\n",
+        EXISTING.replace(
+            r#"cluster: { server: "https://mine.test:6443" }"#,
+            r#"cluster: { server: "https://mine.test:6443", proxy-url: "socks5://proxy.test:1080", x-note: keep }"#,
+        )
+    );
+    let path = file("lossless", &existing);
+    let mut kubeconfig = KubeconfigFile::load(&path).unwrap();
+
+    kubeconfig
+        .merge(ISSUED, origin("dev"), None, false)
+        .unwrap();
+    kubeconfig.save().unwrap();
+
+    let saved: serde_yaml::Value =
+        serde_yaml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(saved["preferences"]["colors"], true);
+    assert_eq!(saved["x-team"], "platform");
+    assert_eq!(
+        saved["clusters"][0]["cluster"]["proxy-url"],
+        "socks5://proxy.test:1080"
+    );
+    assert_eq!(saved["clusters"][0]["cluster"]["x-note"], "keep");
+    assert_eq!(saved["contexts"][2]["name"], "kufefe-dev-2");
+}
+
+#[test]
+fn changes_entries_in_the_file_defining_them() {
+    let first = file("first", EXISTING);
+    let second = file("second", "");
+    let missing =
+        std::env::temp_dir().join(format!("kufefe-missing-{}", std::process::id()));
+
+    // An earlier merge went into the second file
+    let mut kubeconfig = KubeconfigFile::load(&second).unwrap();
+    kubeconfig
+        .merge(ISSUED, origin("old"), None, false)
+        .unwrap();
+    kubeconfig.save().unwrap();
+
+    let mut kubeconfig =
+        KubeconfigFile::load_all(vec![missing.clone(), first.clone(), second.clone()])
+            .unwrap();
+    assert_eq!(kubeconfig.path(), first.as_path());
+    assert_eq!(kubeconfig.current_context(), Some("mine"));
+
+    kubeconfig.prune(|issued| issued.request == "old");
+    kubeconfig.merge(ISSUED, origin("new"), None, true).unwrap();
+    kubeconfig.save().unwrap();
+
+    let read = |path: &PathBuf| -> serde_yaml::Value {
+        serde_yaml::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    };
+    let (first_yaml, second_yaml) = (read(&first), read(&second));
+    std::fs::remove_file(&first).unwrap();
+    std::fs::remove_file(&second).unwrap();
+
+    assert!(!missing.exists());
+    assert_eq!(first_yaml["current-context"], "kufefe-new");
+    assert_eq!(first_yaml["contexts"][2]["name"], "kufefe-new");
+    assert_eq!(second_yaml["contexts"].as_sequence().unwrap().len(), 0);
+    assert_eq!(second_yaml["clusters"].as_sequence().unwrap().len(), 0);
+    assert_eq!(second_yaml["users"].as_sequence().unwrap().len(), 0);
+}
+
+#[test]
+fn pins_the_context_of_the_exec_plugin() {
+    let exec = r#"
+apiVersion: v1
+kind: Config
+users:
+- name: kufefe-dev
+  user:
+    exec:
+      apiVersion: client.authentication.k8s.io/v1
+      command: kufefe
+      args: [credential, --request, dev, --api-url, "https://kufefe.test"]
+"#;
+
+    let pinned = file::with_credential_context(exec, "mine").unwrap();
+    let pinned: serde_yaml::Value = serde_yaml::from_str(&pinned).unwrap();
+    let args = &pinned["users"][0]["user"]["exec"]["args"];
+
+    assert_eq!(args[5], "--context");
+    assert_eq!(args[6], "mine");
+
+    // A context chosen already is kept
+    let repinned =
+        file::with_credential_context(&serde_yaml::to_string(&pinned).unwrap(), "other");
+    let repinned: serde_yaml::Value = serde_yaml::from_str(&repinned.unwrap()).unwrap();
+    assert_eq!(repinned["users"][0]["user"]["exec"]["args"], *args);
+}
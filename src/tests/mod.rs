@@ -0,0 +1,58 @@
+mod api;
+mod clusters;
+mod config;
+mod crd;
+mod discovery;
+mod expiry;
+mod fake;
+mod kubeconfig;
+mod notify;
+mod plugin;
+mod provisioning;
+mod refresh;
+mod reload;
+mod validation;
+
+use crate::crd::Request;
+use serde_json::{json, Value};
+
+/// Path of a Request in the fake API
+fn request_path(name: &str) -> String {
+    format!("/apis/kufefe.io/v1/requests/{}", name)
+}
+
+/// Path of a ClusterRole in the fake API
+fn role_path(name: &str) -> String {
+    format!("/apis/rbac.authorization.k8s.io/v1/clusterroles/{}", name)
+}
+
+/// A Request as the API server would return it
+fn request(name: &str, uid: &str, role: &str) -> Value {
+    json!({
+        "apiVersion": "kufefe.io/v1",
+        "kind": "Request",
+        "metadata": { "name": name, "uid": uid, "resourceVersion": "1" },
+        "spec": { "role": role },
+    })
+}
+
+/// A ClusterRole, annotated for use with Kufefe or not
+fn role(name: &str, annotated: bool) -> Value {
+    let annotations = if annotated {
+        json!({ "kufefe.io/role": "true" })
+    } else {
+        json!({})
+    };
+
+    json!({
+        "apiVersion": "rbac.authorization.k8s.io/v1",
+        "kind": "ClusterRole",
+        "metadata": { "name": name, "annotations": annotations },
+        "rules": [{ "apiGroups": [""], "resources": ["pods"], "verbs": ["get", "list"] }],
+    })
+}
+
+/// Parses a Request from the fake API
+fn parse(value: Value) -> Request {
+    serde_json::from_value(value).unwrap()
+}
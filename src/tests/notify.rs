@@ -0,0 +1,184 @@
+use super::expiry::expiring;
+use super::fake::FakeApi;
+use super::request_path;
+use crate::config::Overrides;
+use crate::crd::Request;
+use crate::notify::{self, Format, Notification, Payload, Webhook};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Response, Server, StatusCode};
+use hyper_openssl::HttpsConnector;
+use serde_json::Value;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// A call a receiver got, with its signature header and body
+type Received = (Option<String>, Value);
+
+/// Starts a webhook receiver answering with the given statuses in turn, then 200
+fn receiver(statuses: Vec<u16>) -> (String, mpsc::UnboundedReceiver<Received>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+
+    let service = make_service_fn(move |_| {
+        let (tx, statuses) = (tx.clone(), statuses.clone());
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+                let (tx, statuses) = (tx.clone(), statuses.clone());
+
+                async move {
+                    let signature = request
+                        .headers()
+                        .get(notify::SIGNATURE_HEADER)
+                        .map(|s| s.to_str().unwrap().to_string());
+                    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                    tx.send((signature, serde_json::from_slice(&body).unwrap()))
+                        .ok();
+
+                    let status = statuses.lock().unwrap().next().unwrap_or(200);
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .status(StatusCode::from_u16(status).unwrap())
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                }
+            }))
+        }
+    });
+
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(service);
+    let url = format!("http://{}/hook", server.local_addr());
+    tokio::spawn(server);
+
+    (url, rx)
+}
+
+fn webhook(url: &str) -> Webhook {
+    Webhook {
+        url: url.to_string(),
+        format: Format::Json,
+        events: None,
+        roles: None,
+        secret_env: None,
+    }
+}
+
+fn payload(event: Notification) -> Payload {
+    let mut request: Request = super::parse(expiring("view-1", 3600));
+    request.status.as_mut().unwrap().expires_at = Some(1_700_000_000);
+    request.spec.cluster = Some("staging".to_string());
+
+    Payload::new(event, &request)
+}
+
+#[test]
+fn filters_by_event_and_role() {
+    let hook = Webhook {
+        events: Some(vec![Notification::Issued]),
+        roles: Some(vec!["cluster-admin".to_string()]),
+        ..webhook("https://hooks.test")
+    };
+
+    assert!(hook.wants(Notification::Issued, "cluster-admin"));
+    assert!(!hook.wants(Notification::Expired, "cluster-admin"));
+    assert!(!hook.wants(Notification::Issued, "view"));
+    assert!(webhook("https://hooks.test").wants(Notification::Revoked, "view"));
+}
+
+#[test]
+fn renders_payload_templates() {
+    let payload = payload(Notification::Issued);
+    let text = "Request view-1 for role view on cluster staging was issued, \
+        valid until 2023-11-14 22:13 UTC";
+
+    assert_eq!(payload.text(), text);
+    assert_eq!(payload.render(Format::Slack)["text"], text);
+    assert_eq!(payload.render(Format::Teams)["@type"], "MessageCard");
+    assert_eq!(payload.render(Format::Teams)["text"], text);
+
+    let json = payload.render(Format::Json);
+    assert_eq!(json["event"], "issued");
+    assert_eq!(json["request"], "view-1");
+    assert_eq!(json["cluster"], "staging");
+    assert_eq!(json["expiresAt"], 1_700_000_000);
+}
+
+#[test]
+fn signs_with_hmac_sha256() {
+    assert_eq!(
+        notify::sign("key", "The quick brown fox jumps over the lazy dog").unwrap(),
+        "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+    );
+}
+
+#[tokio::test]
+async fn retries_failed_deliveries() {
+    let (url, mut rx) = receiver(vec![503, 500]);
+    std::env::set_var("KUFEFE_TEST_WEBHOOK_SECRET", "s3cret");
+    let hook = Webhook {
+        secret_env: Some("KUFEFE_TEST_WEBHOOK_SECRET".to_string()),
+        ..webhook(&url)
+    };
+    let client = Client::builder().build(HttpsConnector::new().unwrap());
+
+    notify::deliver(
+        &client,
+        &hook,
+        &payload(Notification::Revoked),
+        Duration::from_millis(1),
+    )
+    .await
+    .unwrap();
+
+    for _ in 0..3 {
+        let (signature, body) = rx.recv().await.unwrap();
+        assert_eq!(body["event"], "revoked");
+        assert_eq!(
+            signature.unwrap(),
+            notify::sign("s3cret", &body.to_string()).unwrap()
+        );
+    }
+}
+
+#[tokio::test]
+async fn gives_up_on_client_errors() {
+    let (url, mut rx) = receiver(vec![400]);
+    let client = Client::builder().build(HttpsConnector::new().unwrap());
+
+    let result = notify::deliver(
+        &client,
+        &webhook(&url),
+        &payload(Notification::Issued),
+        Duration::from_millis(1),
+    )
+    .await;
+
+    assert!(result.is_err());
+    assert!(rx.recv().await.unwrap().0.is_none());
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn reports_expired_requests() {
+    let (fake, ctx) = FakeApi::start();
+    let (url, mut rx) = receiver(vec![]);
+    let overrides = Overrides {
+        webhooks: Some(vec![webhook(&url)]),
+        ..Overrides::default()
+    };
+    ctx.set_settings(ctx.settings().with_overrides(&overrides).unwrap());
+    fake.insert(&request_path("expired"), expiring("expired", -60));
+
+    Request::mock().scan(&ctx).await;
+
+    let (_, body) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(body["event"], "expired");
+    assert_eq!(body["request"], "expired");
+}
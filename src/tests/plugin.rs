@@ -0,0 +1,89 @@
+use super::fake::FakeApi;
+use super::{parse, request, request_path};
+use crate::config::Overrides;
+use crate::crd::Request;
+use crate::plugin::duration;
+use crate::watcher::{self, EXPIRES_AT_ANNOTATION};
+use serde_json::{json, Value};
+
+/// A ready Request created now that expires in an hour
+fn ready(name: &str, extend_to: i64) -> Value {
+    let now = chrono::Utc::now();
+    let mut request = request(name, &format!("uid-{}", name), "view");
+    request["metadata"]["creationTimestamp"] = json!(now.to_rfc3339());
+    request["metadata"]["annotations"] =
+        json!({ EXPIRES_AT_ANNOTATION: extend_to.to_string() });
+    request["status"] = json!({
+        "ready": true,
+        "failed": false,
+        "message": "Completed",
+        "serviceAccountName": name,
+        "tokenName": name,
+        "rolebindingName": name,
+        "expiresAt": now.timestamp() + 3600,
+    });
+
+    request
+}
+
+#[test]
+fn parses_durations() {
+    assert_eq!(duration::parse("90"), Ok(90));
+    assert_eq!(duration::parse("30m"), Ok(30));
+    assert_eq!(duration::parse("2h"), Ok(120));
+    assert_eq!(duration::parse("1h30m"), Ok(90));
+
+    for invalid in ["", "0", "0m", "h", "1d", "1h30", "-5m"] {
+        assert!(duration::parse(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn generates_names_from_roles() {
+    assert_eq!(Request::name_prefix("view"), "view-");
+    assert_eq!(
+        Request::name_prefix("system:aggregate-to-Edit"),
+        "system-aggregate-to-edit-"
+    );
+    assert_eq!(Request::name_prefix(":::"), "request-");
+}
+
+#[tokio::test]
+async fn extends_requests_within_policy() {
+    let (fake, ctx) = FakeApi::start();
+    let extend_to = chrono::Utc::now().timestamp() + 7200;
+    fake.insert(&request_path("dev"), ready("dev", extend_to));
+
+    watcher::extend(&ctx, parse(ready("dev", extend_to)), extend_to).await;
+
+    let stored = parse(fake.get(&request_path("dev")).unwrap());
+    assert_eq!(stored.status.unwrap().expires_at, Some(extend_to));
+    assert!(!stored
+        .metadata
+        .annotations
+        .unwrap()
+        .contains_key(EXPIRES_AT_ANNOTATION));
+}
+
+#[tokio::test]
+async fn rejects_extensions_beyond_the_maximum() {
+    let (fake, ctx) = FakeApi::start();
+    let overrides = Overrides {
+        max_expire_minutes: Some(90),
+        ..Overrides::default()
+    };
+    ctx.set_settings(ctx.settings().with_overrides(&overrides).unwrap());
+
+    let extend_to = chrono::Utc::now().timestamp() + 7200;
+    fake.insert(&request_path("dev"), ready("dev", extend_to));
+
+    watcher::extend(&ctx, parse(ready("dev", extend_to)), extend_to).await;
+
+    let stored = parse(fake.get(&request_path("dev")).unwrap());
+    assert!(stored.status.unwrap().expires_at < Some(extend_to));
+    assert!(!stored
+        .metadata
+        .annotations
+        .unwrap()
+        .contains_key(EXPIRES_AT_ANNOTATION));
+}
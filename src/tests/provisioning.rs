@@ -0,0 +1,530 @@
+use super::fake::{FakeApi, NAMESPACE};
+use super::{parse, request, request_path, role, role_path};
+use crate::config::{ConfigArgs, KufefeConfig, Overrides};
+use crate::crd::{OutputFormat, RequestStatus, APPROVAL_REQUIRED, MAX_DURATION_MINUTES};
+use crate::resources::role::APPROVAL_ANNOTATION;
+use crate::{error::Error, transaction::Transaction, watcher};
+use hyper::Method;
+use serde_json::json;
+
+const NAME: &str = "dev-access";
+const UID: &str = "0f8e7d6c-1111-2222-3333-444455556666";
+const GENERATED: &str = "kufefe-dev-access-0f8e7d6c";
+
+fn sa_path() -> String {
+    format!("/api/v1/namespaces/{}/serviceaccounts", NAMESPACE)
+}
+
+fn secret_path() -> String {
+    format!("/api/v1/namespaces/{}/secrets", NAMESPACE)
+}
+
+fn binding_path() -> String {
+    "/apis/rbac.authorization.k8s.io/v1/clusterrolebindings".to_string()
+}
+
+#[tokio::test]
+async fn provisions_request() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&role_path("view"), role("view", true));
+    fake.insert(&request_path(NAME), request(NAME, UID, "view"));
+
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    watcher::added(&ctx, resource, &mut Transaction::default())
+        .await
+        .unwrap();
+
+    let labels = json!({
+        "app.kubernetes.io/managed-by": "kufefe",
+        "kufefe.io/owned-by": "debug",
+        "kufefe.io/request-uid": UID,
+    });
+    let owner = json!([{
+        "apiVersion": "kufefe.io/v1",
+        "kind": "Request",
+        "name": NAME,
+        "uid": UID,
+    }]);
+
+    assert_eq!(
+        fake.body(Method::POST, &sa_path()),
+        json!({
+            "apiVersion": "v1",
+            "kind": "ServiceAccount",
+            "automountServiceAccountToken": true,
+            "metadata": {
+                "name": GENERATED,
+                "namespace": NAMESPACE,
+                "labels": labels,
+                "ownerReferences": owner,
+            },
+        })
+    );
+
+    assert_eq!(
+        fake.body(Method::POST, &secret_path()),
+        json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "type": "kubernetes.io/service-account-token",
+            "metadata": {
+                "name": GENERATED,
+                "namespace": NAMESPACE,
+                "labels": labels,
+                "annotations": { "kubernetes.io/service-account.name": GENERATED },
+                "ownerReferences": [{
+                    "apiVersion": "v1",
+                    "kind": "ServiceAccount",
+                    "name": GENERATED,
+                    "uid": "uid-1",
+                }],
+            },
+        })
+    );
+
+    assert_eq!(
+        fake.body(Method::POST, &binding_path()),
+        json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRoleBinding",
+            "metadata": {
+                "name": GENERATED,
+                "labels": labels,
+                "ownerReferences": owner,
+            },
+            "roleRef": {
+                "apiGroup": "rbac.authorization.k8s.io",
+                "kind": "ClusterRole",
+                "name": "view",
+            },
+            "subjects": [{
+                "kind": "ServiceAccount",
+                "name": GENERATED,
+                "namespace": NAMESPACE,
+            }],
+        })
+    );
+
+    // The last status update hands out the kubeconfig
+    let updates = fake.requests(Method::PUT, &format!("{}/status", request_path(NAME)));
+    let status = &updates.last().unwrap().body.as_ref().unwrap()["status"];
+
+    assert_eq!(status["ready"], true);
+    assert_eq!(status["failed"], false);
+    assert_eq!(status["attempts"], 1);
+    assert_eq!(status["serviceAccountName"], GENERATED);
+    assert_eq!(status["tokenName"], GENERATED);
+    assert_eq!(status["rolebindingName"], GENERATED);
+    assert_eq!(status["conditions"][0]["type"], "Ready");
+    assert_eq!(status["conditions"][0]["status"], "True");
+    assert_eq!(status["conditions"][0]["reason"], "Provisioned");
+    assert_eq!(status["clusterUrl"], "https://kubernetes.test:6443");
+    assert_eq!(status["clusterUrlProvider"], "config");
+
+    let kubeconfig: serde_yaml::Value =
+        serde_yaml::from_str(status["kubeconfig"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        kubeconfig["clusters"][0]["cluster"]["server"],
+        "https://kubernetes.test:6443"
+    );
+    assert_eq!(kubeconfig["users"][0]["name"], GENERATED);
+    assert_eq!(kubeconfig["users"][0]["user"]["token"], "fake-token");
+}
+
+#[tokio::test]
+async fn rejects_role_without_annotation() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&role_path("admin"), role("admin", false));
+    fake.insert(&request_path(NAME), request(NAME, UID, "admin"));
+
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    let result = watcher::added(&ctx, resource, &mut Transaction::default()).await;
+
+    assert!(matches!(result, Err(Error::RoleNotAllowed { role }) if role == "admin"));
+    assert!(fake.requests(Method::POST, &sa_path()).is_empty());
+    assert!(fake.requests(Method::POST, &secret_path()).is_empty());
+    assert!(fake.requests(Method::POST, &binding_path()).is_empty());
+}
+
+#[tokio::test]
+async fn fails_request_with_missing_role() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&request_path(NAME), request(NAME, UID, "missing"));
+
+    watcher::process(&ctx, parse(fake.get(&request_path(NAME)).unwrap())).await;
+
+    let status = parse(fake.get(&request_path(NAME)).unwrap())
+        .status
+        .unwrap();
+    let condition = &status.conditions.unwrap()[0];
+
+    assert!(status.failed);
+    assert!(!status.ready);
+    assert_eq!(status.last_error.as_deref(), Some("Role missing not found"));
+    assert_eq!(condition.status, "False");
+    assert_eq!(condition.reason, "RoleNotFound");
+}
+
+#[tokio::test]
+async fn rolls_back_on_failure() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&role_path("view"), role("view", true));
+    fake.insert(&request_path(NAME), request(NAME, UID, "view"));
+    fake.fail(Method::POST, &binding_path(), 403);
+
+    watcher::process(&ctx, parse(fake.get(&request_path(NAME)).unwrap())).await;
+
+    // Everything created before the failure is deleted again
+    let sa = format!("{}/{}", sa_path(), GENERATED);
+    let secret = format!("{}/{}", secret_path(), GENERATED);
+
+    assert_eq!(fake.requests(Method::DELETE, &secret).len(), 1);
+    assert_eq!(fake.requests(Method::DELETE, &sa).len(), 1);
+    assert!(fake.get(&sa).is_none());
+    assert!(fake.get(&secret).is_none());
+
+    let status = parse(fake.get(&request_path(NAME)).unwrap())
+        .status
+        .unwrap();
+
+    assert!(status.failed);
+    assert_eq!(status.conditions.unwrap()[0].reason, "ApiError");
+}
+
+#[tokio::test]
+async fn skips_rolling_back_unnamed_resources() {
+    let (fake, ctx) = FakeApi::start();
+
+    Transaction::from_status(&RequestStatus::default())
+        .rollback(&ctx)
+        .await;
+
+    for path in [sa_path(), secret_path(), binding_path()] {
+        assert!(fake.requests(Method::DELETE, &path).is_empty());
+        assert!(fake
+            .requests(Method::DELETE, &format!("{}/", path))
+            .is_empty());
+    }
+}
+
+#[tokio::test]
+async fn fails_when_the_token_stays_missing() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&role_path("view"), role("view", true));
+    fake.insert(&request_path(NAME), request(NAME, UID, "view"));
+    fake.stop_token_controller();
+
+    let settings = KufefeConfig::load(ConfigArgs {
+        namespace: Some(NAMESPACE.to_string()),
+        cluster_url: Some("https://kubernetes.test:6443".to_string()),
+        validate_kubeconfig: Some(false),
+        token_timeout_seconds: Some(1),
+        retry_max_attempts: Some(2),
+        ..ConfigArgs::default()
+    })
+    .unwrap();
+    ctx.set_settings(settings);
+
+    // Waiting for the token counts as an attempt
+    watcher::process(&ctx, parse(fake.get(&request_path(NAME)).unwrap())).await;
+
+    let status = parse(fake.get(&request_path(NAME)).unwrap())
+        .status
+        .unwrap();
+    assert!(!status.failed);
+    assert_eq!(status.attempts, Some(2));
+    assert!(fake.get(&format!("{}/{}", sa_path(), GENERATED)).is_some());
+
+    // Once attempts run out, the Request fails instead of being requeued forever
+    watcher::process_requeue(&ctx, parse(fake.get(&request_path(NAME)).unwrap())).await;
+
+    let status = parse(fake.get(&request_path(NAME)).unwrap())
+        .status
+        .unwrap();
+    assert!(status.failed);
+    assert_eq!(status.conditions.unwrap()[0].reason, "TokenNotReady");
+    assert!(fake.get(&format!("{}/{}", sa_path(), GENERATED)).is_none());
+}
+
+#[tokio::test]
+async fn holds_requests_until_approved() {
+    let (fake, ctx) = FakeApi::start();
+    let mut admin = role("admin", true);
+    admin["metadata"]["annotations"][APPROVAL_ANNOTATION] = json!("true");
+    fake.insert(&role_path("admin"), admin);
+    fake.insert(&request_path(NAME), request(NAME, UID, "admin"));
+
+    // Nothing is provisioned until someone approves the Request
+    watcher::process(&ctx, parse(fake.get(&request_path(NAME)).unwrap())).await;
+
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    let status = resource.status.clone().unwrap();
+
+    assert!(resource.awaiting_approval());
+    assert!(!status.ready && !status.failed);
+    assert_eq!(status.conditions.unwrap()[0].reason, APPROVAL_REQUIRED);
+    assert!(fake.requests(Method::POST, &sa_path()).is_empty());
+
+    // Looking at it again, for example after a restart, changes nothing
+    watcher::process(&ctx, resource).await;
+    assert!(parse(fake.get(&request_path(NAME)).unwrap()).awaiting_approval());
+
+    let mut approved = fake.get(&request_path(NAME)).unwrap();
+    approved["status"]["approvedBy"] = json!("security@example.com");
+    fake.insert(&request_path(NAME), approved);
+
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    assert!(resource.approval_granted());
+
+    watcher::process(&ctx, resource).await;
+
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    let status = resource.status.clone().unwrap();
+
+    assert!(status.ready);
+    assert!(!resource.approval_granted());
+    assert_eq!(status.approved_by.as_deref(), Some("security@example.com"));
+    assert_eq!(status.conditions.unwrap()[0].reason, "Provisioned");
+    assert!(fake.get(&format!("{}/{}", sa_path(), GENERATED)).is_some());
+}
+
+#[tokio::test]
+async fn refuses_to_adopt_resources_of_others() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&role_path("view"), role("view", true));
+    fake.insert(&request_path(NAME), request(NAME, UID, "view"));
+
+    // A ServiceAccount of the same name, created for another Request
+    let sa = format!("{}/{}", sa_path(), GENERATED);
+    let foreign = json!({
+        "apiVersion": "v1",
+        "kind": "ServiceAccount",
+        "metadata": {
+            "name": GENERATED,
+            "namespace": NAMESPACE,
+            "labels": { "kufefe.io/request-uid": "other" },
+        },
+    });
+    fake.insert(&sa, foreign.clone());
+
+    watcher::process(&ctx, parse(fake.get(&request_path(NAME)).unwrap())).await;
+
+    let status = parse(fake.get(&request_path(NAME)).unwrap())
+        .status
+        .unwrap();
+    assert!(status.failed);
+    assert_eq!(status.conditions.unwrap()[0].reason, "ResourceConflict");
+    assert_eq!(fake.get(&sa), Some(foreign));
+    assert!(fake.requests(Method::DELETE, &sa).is_empty());
+
+    // A binding of the same Request, but to another role
+    fake.insert(&request_path(NAME), request(NAME, UID, "view"));
+    fake.insert(
+        &sa,
+        json!({
+            "apiVersion": "v1",
+            "kind": "ServiceAccount",
+            "metadata": {
+                "name": GENERATED,
+                "namespace": NAMESPACE,
+                "labels": { "kufefe.io/request-uid": UID },
+            },
+        }),
+    );
+    let binding = format!("{}/{}", binding_path(), GENERATED);
+    fake.insert(
+        &binding,
+        json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRoleBinding",
+            "metadata": { "name": GENERATED, "labels": { "kufefe.io/request-uid": UID } },
+            "roleRef": {
+                "apiGroup": "rbac.authorization.k8s.io",
+                "kind": "ClusterRole",
+                "name": "cluster-admin",
+            },
+            "subjects": [{
+                "kind": "ServiceAccount",
+                "name": GENERATED,
+                "namespace": NAMESPACE,
+            }],
+        }),
+    );
+
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    let result = watcher::added(&ctx, resource, &mut Transaction::default()).await;
+
+    assert!(matches!(
+        result,
+        Err(Error::Occupied { kind: "ClusterRoleBinding", ref reason, .. })
+            if reason == "binds role cluster-admin"
+    ));
+}
+
+#[tokio::test]
+async fn retry_keeps_assigned_names() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&role_path("view"), role("view", true));
+    fake.insert(&request_path(NAME), request(NAME, UID, "view"));
+    fake.fail(Method::POST, &binding_path(), 403);
+
+    watcher::process(&ctx, parse(fake.get(&request_path(NAME)).unwrap())).await;
+
+    // The status doesn't allow changing names, so a new prefix only applies to new Requests
+    let overrides = Overrides {
+        name_prefix: Some("other".to_string()),
+        ..Overrides::default()
+    };
+    ctx.set_settings(ctx.settings().with_overrides(&overrides).unwrap());
+
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    watcher::added(&ctx, resource, &mut Transaction::default())
+        .await
+        .unwrap();
+
+    let status = parse(fake.get(&request_path(NAME)).unwrap())
+        .status
+        .unwrap();
+
+    assert!(fake
+        .get(&format!("{}/{}", binding_path(), GENERATED))
+        .is_some());
+    assert_eq!(status.service_account_name, GENERATED);
+    assert_eq!(status.token_name, GENERATED);
+    assert_eq!(status.rolebinding_name, GENERATED);
+}
+
+#[tokio::test]
+async fn rejects_exec_requests_needing_a_static_token() {
+    let (fake, ctx) = FakeApi::start();
+    let settings = KufefeConfig::load(ConfigArgs {
+        namespace: Some(NAMESPACE.to_string()),
+        api_port: Some(8443),
+        api_tls_cert: Some("tls.crt".into()),
+        api_tls_key: Some("tls.key".into()),
+        api_allowed_groups: Some(vec!["oncall".to_string()]),
+        api_url: Some("https://kufefe.test".to_string()),
+        default_output_format: Some(OutputFormat::ExecCredential),
+        ..ConfigArgs::default()
+    })
+    .unwrap();
+    ctx.set_settings(settings);
+
+    let mut resource = request(NAME, UID, "view");
+    resource["spec"]["credentialMode"] = json!("exec");
+    fake.insert(&role_path("view"), role("view", true));
+    fake.insert(&request_path(NAME), resource);
+
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    let result = watcher::added(&ctx, resource, &mut Transaction::default()).await;
+
+    assert!(matches!(result, Err(Error::Unsupported { .. })));
+    assert!(fake.requests(Method::POST, &sa_path()).is_empty());
+}
+
+#[tokio::test]
+async fn exec_requests_fetch_tokens_from_the_api() {
+    let (fake, ctx) = FakeApi::start();
+    let mut resource = request(NAME, UID, "view");
+    resource["spec"]["credentialMode"] = json!("exec");
+    fake.insert(&role_path("view"), role("view", true));
+    fake.insert(&request_path(NAME), resource.clone());
+
+    // Without the URL of the self-service API there is nothing to fetch tokens from
+    watcher::process(&ctx, parse(fake.get(&request_path(NAME)).unwrap())).await;
+
+    let status = parse(fake.get(&request_path(NAME)).unwrap())
+        .status
+        .unwrap();
+    assert!(status.failed);
+    assert_eq!(status.conditions.unwrap()[0].reason, "Unsupported");
+
+    let settings = KufefeConfig::load(ConfigArgs {
+        namespace: Some(NAMESPACE.to_string()),
+        validate_kubeconfig: Some(false),
+        api_port: Some(8443),
+        api_tls_cert: Some("tls.crt".into()),
+        api_tls_key: Some("tls.key".into()),
+        api_allowed_groups: Some(vec!["oncall".to_string()]),
+        api_url: Some("https://kufefe.test".to_string()),
+        ..ConfigArgs::default()
+    })
+    .unwrap();
+    ctx.set_settings(settings);
+    fake.insert(&request_path(NAME), resource);
+
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    watcher::added(&ctx, resource, &mut Transaction::default())
+        .await
+        .unwrap();
+
+    let status = parse(fake.get(&request_path(NAME)).unwrap())
+        .status
+        .unwrap();
+    let kubeconfig: serde_json::Value =
+        serde_yaml::from_str(status.kubeconfig.as_deref().unwrap()).unwrap();
+    let exec = &kubeconfig["users"][0]["user"]["exec"];
+
+    assert!(kubeconfig["users"][0]["user"].get("token").is_none());
+    assert_eq!(
+        exec["args"],
+        json!([
+            "credential",
+            "--request",
+            NAME,
+            "--api-url",
+            "https://kufefe.test"
+        ])
+    );
+}
+
+#[tokio::test]
+async fn rejects_requests_outside_policy() {
+    let (fake, ctx) = FakeApi::start();
+    let overrides = Overrides {
+        max_expire_minutes: Some(60),
+        allowed_roles: Some(vec!["view".to_string()]),
+        ..Overrides::default()
+    };
+    ctx.set_settings(ctx.settings().with_overrides(&overrides).unwrap());
+
+    fake.insert(&role_path("view"), role("view", true));
+    fake.insert(&role_path("edit"), role("edit", true));
+
+    let mut long = request(NAME, UID, "view");
+    long["spec"]["durationMinutes"] = json!(120);
+    let result = watcher::added(&ctx, parse(long), &mut Transaction::default()).await;
+
+    assert!(matches!(
+        result,
+        Err(Error::DurationNotAllowed {
+            minutes: 120,
+            max: 60
+        })
+    ));
+
+    fake.insert(&request_path(NAME), request(NAME, UID, "edit"));
+    let resource = parse(fake.get(&request_path(NAME)).unwrap());
+    let result = watcher::added(&ctx, resource, &mut Transaction::default()).await;
+
+    assert!(matches!(result, Err(Error::RoleNotListed { role }) if role == "edit"));
+}
+
+#[tokio::test]
+async fn rejects_durations_that_would_overflow() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&role_path("view"), role("view", true));
+
+    let mut long = request(NAME, UID, "view");
+    long["spec"]["durationMinutes"] = json!(153722867280912930u64);
+    let result = watcher::added(&ctx, parse(long), &mut Transaction::default()).await;
+
+    assert!(matches!(
+        result,
+        Err(Error::DurationNotAllowed {
+            minutes: 153722867280912930,
+            max: MAX_DURATION_MINUTES
+        })
+    ));
+    assert!(fake.requests(Method::POST, &sa_path()).is_empty());
+}
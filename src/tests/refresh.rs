@@ -0,0 +1,91 @@
+use super::fake::FakeApi;
+use super::{parse, request, request_path};
+use crate::context::Endpoint;
+use crate::kubeconfig::Kubeconfig;
+use crate::refresh;
+use hyper::Method;
+use serde_json::{json, Value};
+
+const KUBECONFIG: &str = "apiVersion: v1
+clusters:
+- cluster:
+    certificate-authority-data: T0xEIENB
+    server: https://old.test:6443
+  name: kubernetes
+contexts:
+- context:
+    cluster: kubernetes
+    user: kufefe-dev
+  name: kubernetes
+current-context: kubernetes
+kind: Config
+preferences: {}
+users:
+- name: kufefe-dev
+  user:
+    token: fake-token
+";
+
+/// A ready Request holding a kubeconfig for the old endpoint
+fn issued(name: &str, ready: bool) -> Value {
+    let mut request = request(name, &format!("uid-{}", name), "view");
+    request["status"] = json!({
+        "ready": ready,
+        "failed": false,
+        "message": "Completed",
+        "serviceAccountName": name,
+        "tokenName": name,
+        "rolebindingName": name,
+        "kubeconfig": KUBECONFIG,
+    });
+
+    request
+}
+
+#[tokio::test]
+async fn regenerates_kubeconfigs_on_endpoint_change() {
+    let (fake, ctx) = FakeApi::start();
+    fake.insert(&request_path("ready"), issued("ready", true));
+    fake.insert(&request_path("pending"), issued("pending", false));
+
+    assert!(ctx.set_endpoint(Endpoint {
+        url: "https://new.test:6443".to_string(),
+        provider: "cluster-info".to_string(),
+        ca: Some("NEW CA".to_string()),
+    }));
+
+    refresh::regenerate(&ctx).await;
+
+    let status = parse(fake.get(&request_path("ready")).unwrap())
+        .status
+        .unwrap();
+    let kubeconfig =
+        Kubeconfig::from_yaml(status.kubeconfig.as_deref().unwrap()).unwrap();
+    let parsed: serde_yaml::Value =
+        serde_yaml::from_str(&kubeconfig.to_yaml().unwrap()).unwrap();
+
+    assert_eq!(
+        parsed["clusters"][0]["cluster"]["server"],
+        "https://new.test:6443"
+    );
+    assert_eq!(
+        parsed["clusters"][0]["cluster"]["certificate-authority-data"],
+        "TkVXIENB"
+    );
+    assert_eq!(parsed["users"][0]["user"]["token"], "fake-token");
+    assert_eq!(status.cluster_url.as_deref(), Some("https://new.test:6443"));
+    assert_eq!(status.cluster_url_provider.as_deref(), Some("cluster-info"));
+
+    // Requests that aren't ready have nothing to regenerate
+    assert!(fake
+        .requests(Method::PUT, &format!("{}/status", request_path("pending")))
+        .is_empty());
+
+    // Nothing is written when the kubeconfig is already up to date
+    refresh::regenerate(&ctx).await;
+    assert_eq!(
+        fake.requests(Method::PUT, &format!("{}/status", request_path("ready")))
+            .len(),
+        1
+    );
+}
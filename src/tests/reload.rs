@@ -0,0 +1,55 @@
+use super::fake::FakeApi;
+use crate::config::Overrides;
+use crate::reload::{self, CONFIG_KEY};
+use k8s_openapi::api::core::v1::ConfigMap;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// A settings ConfigMap holding the given YAML
+fn config_map(yaml: &str) -> ConfigMap {
+    ConfigMap {
+        data: Some(BTreeMap::from([(CONFIG_KEY.to_string(), yaml.to_string())])),
+        ..ConfigMap::default()
+    }
+}
+
+#[tokio::test]
+async fn applies_config_map_changes() {
+    let (_fake, ctx) = FakeApi::start();
+    let base = ctx.settings();
+
+    let changed = config_map("expireMinutes: 5\nallowedRoles: [view]\n");
+    let current = reload::apply(&ctx, &base, &Overrides::default(), Some(&changed))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(ctx.generation(), 2);
+    assert_eq!(ctx.settings().expire_after(), Duration::from_secs(300));
+    assert_eq!(
+        ctx.settings().allowed_roles(),
+        Some(&["view".to_string()][..])
+    );
+
+    // Unchanged settings don't start a new generation
+    assert!(reload::apply(&ctx, &base, &current, Some(&changed))
+        .unwrap()
+        .is_none());
+    assert_eq!(ctx.generation(), 2);
+
+    // Invalid settings are rejected, keeping the ones in use
+    let invalid = config_map("namePrefix: Bad!\n");
+    assert!(reload::apply(&ctx, &base, &current, Some(&invalid)).is_err());
+    assert!(
+        reload::apply(&ctx, &base, &current, Some(&config_map("namespace: x\n")))
+            .is_err()
+    );
+    assert_eq!(ctx.generation(), 2);
+    assert_eq!(ctx.settings().expire_after(), Duration::from_secs(300));
+
+    // Without the ConfigMap, the settings Kufefe was started with apply again
+    reload::apply(&ctx, &base, &current, None).unwrap();
+
+    assert_eq!(ctx.generation(), 3);
+    assert_eq!(ctx.settings().expire_after(), Duration::from_secs(3600));
+    assert_eq!(ctx.settings().allowed_roles(), None);
+}
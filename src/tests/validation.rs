@@ -0,0 +1,54 @@
+use super::{fake::FakeApi, fake::NAMESPACE, role};
+use crate::{error::Error, validation};
+use hyper::Method;
+use serde_json::{json, Value};
+
+const REVIEWS: &str = "/apis/authorization.k8s.io/v1/selfsubjectrulesreviews";
+
+/// A SelfSubjectRulesReview granting the given verbs on pods
+fn rules(verbs: &[&str]) -> Value {
+    json!({
+        "apiVersion": "authorization.k8s.io/v1",
+        "kind": "SelfSubjectRulesReview",
+        "spec": {},
+        "status": {
+            "incomplete": false,
+            "nonResourceRules": [],
+            "resourceRules": [{ "apiGroups": [""], "resources": ["pods"], "verbs": verbs }],
+        },
+    })
+}
+
+#[tokio::test]
+async fn reviews_again_while_the_role_binding_propagates() {
+    let (fake, ctx) = FakeApi::start();
+    let role = serde_json::from_value(role("view", true)).unwrap();
+
+    // The authorizer doesn't know about the RoleBinding at first
+    fake.reply(Method::POST, REVIEWS, rules(&[]));
+    fake.reply(Method::POST, REVIEWS, rules(&["get", "list"]));
+
+    let summary = validation::review(&ctx.client(), NAMESPACE, &role)
+        .await
+        .unwrap();
+
+    assert_eq!(summary, vec!["get,list pods (apiGroups: core)"]);
+    assert_eq!(fake.requests(Method::POST, REVIEWS).len(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn gives_up_on_permissions_that_stay_missing() {
+    let (fake, ctx) = FakeApi::start();
+    let role = serde_json::from_value(role("view", true)).unwrap();
+
+    for _ in 0..5 {
+        fake.reply(Method::POST, REVIEWS, rules(&["get"]));
+    }
+
+    let result = validation::review(&ctx.client(), NAMESPACE, &role).await;
+
+    assert!(
+        matches!(result, Err(Error::PermissionMissing { verb, .. }) if verb == "list")
+    );
+    assert_eq!(fake.requests(Method::POST, REVIEWS).len(), 5);
+}
@@ -0,0 +1,101 @@
+use crate::error::{Error, Result};
+use crate::traits::meta::REQUEST_UID_LABEL;
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::{Secret, ServiceAccount};
+use k8s_openapi::api::rbac::v1::ClusterRoleBinding;
+use kube::{api::PostParams, error::ErrorResponse, Api, Resource, ResourceExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Debug;
+
+/// Objects that may be taken over when they already exist
+pub trait Adopt: Resource + Sized {
+    /// Explains why an existing object can't stand in for the desired one
+    fn conflict(&self, desired: &Self) -> Option<String> {
+        other_request(self, desired)
+    }
+}
+
+/// Checks if an existing object was created for another Request
+fn other_request<T: Resource>(existing: &T, desired: &T) -> Option<String> {
+    let uid = |o: &T| o.labels().get(REQUEST_UID_LABEL).cloned();
+
+    (uid(existing) != uid(desired)).then(|| "belongs to another Request".to_string())
+}
+
+impl Adopt for ServiceAccount {}
+
+impl Adopt for Secret {
+    fn conflict(&self, desired: &Self) -> Option<String> {
+        let account = |s: &Self| {
+            s.annotations()
+                .get("kubernetes.io/service-account.name")
+                .cloned()
+        };
+
+        if let Some(reason) = other_request(self, desired) {
+            return Some(reason);
+        }
+
+        if self.type_ != desired.type_ || account(self) != account(desired) {
+            return Some("is not a token of the ServiceAccount".to_string());
+        }
+
+        None
+    }
+}
+
+impl Adopt for ClusterRoleBinding {
+    fn conflict(&self, desired: &Self) -> Option<String> {
+        if let Some(reason) = other_request(self, desired) {
+            return Some(reason);
+        }
+
+        if self.role_ref != desired.role_ref {
+            return Some(format!("binds role {}", self.role_ref.name));
+        }
+
+        if self.subjects != desired.subjects {
+            return Some("binds other subjects".to_string());
+        }
+
+        None
+    }
+}
+
+#[async_trait]
+pub trait GetOrCreate<T> {
+    async fn get_or_create(&self, pp: &PostParams, data: &T) -> Result<T>;
+}
+
+#[async_trait]
+impl<T> GetOrCreate<T> for Api<T>
+where
+    T: Resource + k8s_openapi::Resource + Adopt,
+    T: Clone + DeserializeOwned + Serialize + Debug + Send + Sync,
+{
+    /// Creates an object, adopting an existing one of the same name only if it
+    /// belongs to the same Request and matches what would be created
+    async fn get_or_create(&self, pp: &PostParams, data: &T) -> Result<T> {
+        let name = data.name_any();
+
+        match self.create(pp, data).await {
+            Ok(obj) => Ok(obj),
+            Err(kube::Error::Api(ErrorResponse { code: 409, .. })) => {
+                let existing =
+                    self.get(&name)
+                        .await
+                        .map_err(Error::api("get", T::KIND, &name))?;
+
+                match existing.conflict(data) {
+                    Some(reason) => Err(Error::Occupied {
+                        kind: T::KIND,
+                        name,
+                        reason,
+                    }),
+                    None => Ok(existing),
+                }
+            }
+            Err(e) => Err(Error::api("create", T::KIND, &name)(e)),
+        }
+    }
+}
@@ -1,17 +1,11 @@
 use chrono::Utc;
-use std::env;
+use std::time::Duration;
 
 pub trait Expire {
-    /// Generates expiry timestamp
-    fn generate_expiry(&self) -> i64 {
-        let minutes: i64 = env::var("EXPIRE_MINUTES")
-            .unwrap_or_else(|_| "60".to_string())
-            .parse::<i64>()
-            .unwrap_or(60);
+    /// Generates expiry timestamp, or None if it would overflow
+    fn generate_expiry(&self, after: Duration) -> Option<i64> {
+        let after = i64::try_from(after.as_secs()).ok()?;
 
-        Utc::now()
-            .checked_add_signed(chrono::Duration::minutes(minutes))
-            .unwrap()
-            .timestamp()
+        Utc::now().timestamp().checked_add(after)
     }
 }
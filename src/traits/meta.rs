@@ -1,33 +1,45 @@
-use crate::traits::api::ApiResource;
+use crate::{context::Context, crd::Request, traits::api::ApiResource};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
 use kube::{core::ObjectMeta, Resource, ResourceExt};
-use rand::distributions::{Alphanumeric, DistString};
 use serde::de::DeserializeOwned;
 use std::fmt::Debug;
 
-#[async_trait::async_trait]
+/// Label holding the UID of the Request a resource belongs to
+pub const REQUEST_UID_LABEL: &str = "kufefe.io/request-uid";
+
+/// Label naming the hub that provisioned a resource in a target cluster
+pub const HUB_LABEL: &str = "kufefe.io/hub";
+
+/// Label selector matching every resource created by Kufefe in its own cluster,
+/// leaving out those a hub provisioned here for a Request it tracks
+pub const MANAGED_BY_SELECTOR: &str =
+    "app.kubernetes.io/managed-by=kufefe,!kufefe.io/hub";
+
 pub trait Meta {
-    /// Generates a unique resource name
-    async fn generate_name(&self) -> String
-    where
-        Self: ApiResource,
-    {
-        // Get API
-        let api = self.get_api();
-
-        let name = format!(
-            "kufefe-generated-{}",
-            Alphanumeric.sample_string(&mut rand::thread_rng(), 6)
-        )
-        .to_lowercase();
-
-        // Verify that the name does not already exist for the resource
-        if api.get(&name).await.is_ok() {
-            tracing::info!("Name {} already exists, generating a new one", name);
-            return self.generate_name().await;
-        }
+    /// Derives the resource name from the name and UID of the Request
+    fn generate_name(ctx: &Context, request: &Request) -> String {
+        let settings = ctx.settings();
+        let prefix = settings.name_prefix();
+
+        // A short UID suffix keeps recreated Requests from colliding
+        let suffix = match request.uid() {
+            Some(uid) => format!("-{}", uid.chars().take(8).collect::<String>()),
+            None => String::new(),
+        };
+
+        // Shorten the Request name so the result fits within the length limit
+        let available = settings
+            .name_max_length()
+            .saturating_sub(prefix.len() + 1 + suffix.len());
+
+        let name = request
+            .name_any()
+            .chars()
+            .take(available)
+            .collect::<String>();
 
-        name
+        format!("{}-{}{}", prefix, name.trim_end_matches(['-', '.']), suffix)
+            .to_lowercase()
     }
 
     /// Gets ownership labels
@@ -45,9 +57,9 @@ pub trait Meta {
     }
 
     /// Creates metadata for Kubernetes resources
-    async fn generate_meta<T>(
+    fn generate_meta<T>(
         &self,
-        mut name: Option<String>,
+        name: String,
         namespace: Option<String>,
         owner: &T,
     ) -> ObjectMeta
@@ -55,20 +67,32 @@ pub trait Meta {
         Self: ApiResource,
         T: Resource<DynamicType = ()> + DeserializeOwned + Debug + Clone + Send + Sync,
     {
-        if name.is_none() {
-            name = Some(self.generate_name().await);
+        let api_version = <T as Resource>::api_version(&()).to_string();
+        let kind = <T as Resource>::kind(&()).to_string();
+
+        // Track the owning Request, inheriting it when owned by another child
+        let mut labels = Self::labels();
+        let request_uid = if kind == Request::kind(&()) {
+            owner.uid()
+        } else {
+            owner.labels().get(REQUEST_UID_LABEL).cloned()
+        };
+
+        if let Some(uid) = request_uid {
+            labels.insert(REQUEST_UID_LABEL.to_string(), uid);
+        }
+
+        if let Some(hub) = owner.labels().get(HUB_LABEL) {
+            labels.insert(HUB_LABEL.to_string(), hub.clone());
         }
 
         let mut meta = ObjectMeta {
-            name,
+            name: Some(name),
             namespace,
-            labels: Some(Self::labels()),
+            labels: Some(labels),
             ..ObjectMeta::default()
         };
 
-        let api_version = <T as Resource>::api_version(&()).to_string();
-        let kind = <T as Resource>::kind(&()).to_string();
-
         if owner.uid().is_some() {
             meta.owner_references = Some(vec![OwnerReference {
                 api_version,
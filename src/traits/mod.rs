@@ -1,4 +1,5 @@
 pub mod api;
+pub mod create;
 pub mod delete;
 pub mod expire;
 pub mod meta;
@@ -0,0 +1,113 @@
+use crate::error::Result;
+use crate::resources::{
+    rolebinding::RoleBinding, serviceaccount::ServiceAccount, token::Token,
+};
+use crate::traits::{api::ApiResource, delete::DeleteOpt};
+use crate::{context::Context, crd::RequestStatus};
+use kube::api::DeleteParams;
+use std::future::Future;
+use std::time::Duration;
+
+/// How many times a step is attempted when it fails with a retryable error
+const MAX_ATTEMPTS: u32 = 4;
+
+/// A resource created while provisioning a Request
+#[derive(Debug)]
+pub enum Step {
+    ServiceAccount(String),
+    Token(String),
+    RoleBinding(String),
+}
+
+impl Step {
+    /// Name of the created resource
+    fn name(&self) -> &str {
+        match self {
+            Self::ServiceAccount(name) | Self::Token(name) | Self::RoleBinding(name) => {
+                name
+            }
+        }
+    }
+}
+
+/// Tracks the resources created for a Request so they can be undone on failure
+#[derive(Default)]
+pub struct Transaction {
+    steps: Vec<Step>,
+}
+
+impl Transaction {
+    /// Creates a transaction covering every resource named in the status
+    pub fn from_status(status: &RequestStatus) -> Self {
+        Self {
+            steps: vec![
+                Step::ServiceAccount(status.service_account_name.clone()),
+                Step::Token(status.token_name.clone()),
+                Step::RoleBinding(status.rolebinding_name.clone()),
+            ],
+        }
+    }
+
+    /// Records a step that succeeded
+    pub fn record(&mut self, step: Step) {
+        self.steps.push(step);
+    }
+
+    /// Deletes the created resources in reverse order
+    pub async fn rollback(self, ctx: &Context) {
+        for step in self.steps.into_iter().rev() {
+            // Deleting an empty name would address the whole collection
+            if step.name().is_empty() {
+                tracing::debug!("Skipping rollback of {:?} without a name", step);
+                continue;
+            }
+
+            tracing::info!("Rolling back {:?}", step);
+
+            let result = match &step {
+                Step::ServiceAccount(name) => ServiceAccount::new(ctx)
+                    .get_api()
+                    .delete_opt(name, &DeleteParams::default())
+                    .await
+                    .map(|_| ()),
+                Step::Token(name) => Token::new(ctx)
+                    .get_api()
+                    .delete_opt(name, &DeleteParams::default())
+                    .await
+                    .map(|_| ()),
+                Step::RoleBinding(name) => RoleBinding::new(ctx)
+                    .get_api()
+                    .delete_opt(name, &DeleteParams::default())
+                    .await
+                    .map(|_| ()),
+            };
+
+            if let Err(e) = result {
+                tracing::error!("Failed to roll back {:?}: {}", step, e);
+            }
+        }
+    }
+}
+
+/// Runs a step, retrying it with backoff while it fails with a retryable error
+pub async fn retry<T, F, Fut>(mut step: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut delay = Duration::from_millis(500);
+    let mut attempt = 1;
+
+    loop {
+        match step().await {
+            Err(e) if attempt < MAX_ATTEMPTS && e.is_retryable() => {
+                tracing::warn!("Attempt {} failed, retrying: {}", attempt, e);
+
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
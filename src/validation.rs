@@ -0,0 +1,197 @@
+use crate::error::{Error, Result};
+use crate::{context::Context, kubeconfig::Kubeconfig};
+use k8s_openapi::api::authorization::v1::{
+    ResourceAttributes, ResourceRule, SelfSubjectAccessReview,
+    SelfSubjectAccessReviewSpec, SelfSubjectRulesReview, SelfSubjectRulesReviewSpec,
+    SubjectRulesReviewStatus,
+};
+use k8s_openapi::api::rbac::v1::ClusterRole;
+use kube::{api::PostParams, Api, Client};
+use std::time::Duration;
+
+/// Delays between reviews while the RoleBinding of the credential propagates
+/// to the authorizer cache of the API server
+const PROPAGATION_DELAYS: [Duration; 4] = [
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+];
+
+/// Verifies that the kubeconfig authenticates and grants the permissions of the role.
+/// Returns a summary of the effective rules.
+pub async fn validate(
+    ctx: &Context,
+    kubeconfig: &Kubeconfig,
+    role: &ClusterRole,
+) -> Result<Vec<String>> {
+    let client = kubeconfig.client().await?;
+
+    review(&client, &ctx.namespace(), role).await
+}
+
+/// Reviews the effective rules of the client against the role. The RoleBinding was
+/// just created, so missing permissions are looked up again a few times before
+/// giving up.
+pub async fn review(
+    client: &Client,
+    namespace: &str,
+    role: &ClusterRole,
+) -> Result<Vec<String>> {
+    let mut delays = PROPAGATION_DELAYS.iter();
+
+    loop {
+        let rules = effective_rules(client, namespace).await?;
+
+        match missing(client, namespace, role, &rules).await? {
+            None => return Ok(rules.resource_rules.iter().map(summarize).collect()),
+            Some(e) => match delays.next() {
+                Some(delay) => {
+                    tracing::debug!("{}, reviewing again in {:?}", e, delay);
+                    tokio::time::sleep(*delay).await;
+                }
+                None => return Err(e),
+            },
+        }
+    }
+}
+
+/// Fetches the effective rules for the issued credential
+async fn effective_rules(
+    client: &Client,
+    namespace: &str,
+) -> Result<SubjectRulesReviewStatus> {
+    let api: Api<SelfSubjectRulesReview> = Api::all(client.clone());
+    let review = SelfSubjectRulesReview {
+        spec: SelfSubjectRulesReviewSpec {
+            namespace: Some(namespace.to_string()),
+        },
+        ..SelfSubjectRulesReview::default()
+    };
+
+    match api.create(&PostParams::default(), &review).await {
+        Ok(review) => review.status.ok_or_else(|| Error::Validation {
+            reason: "SelfSubjectRulesReview returned no status".to_string(),
+        }),
+        Err(e) => Err(Error::Unauthenticated {
+            source: Box::new(e),
+        }),
+    }
+}
+
+/// Finds the first rule of the role not covered by the effective rules
+async fn missing(
+    client: &Client,
+    namespace: &str,
+    role: &ClusterRole,
+    status: &SubjectRulesReviewStatus,
+) -> Result<Option<Error>> {
+    for (group, resource, verb) in expand(role) {
+        if status
+            .resource_rules
+            .iter()
+            .any(|r| covers(r, &group, &resource, &verb))
+        {
+            continue;
+        }
+
+        // Some authorizers can't enumerate rules, ask about the rule explicitly
+        if status.incomplete
+            && access_allowed(client, namespace, &group, &resource, &verb).await?
+        {
+            continue;
+        }
+
+        return Ok(Some(Error::PermissionMissing {
+            verb,
+            resource,
+            group,
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Expands the resource rules of a role into (group, resource, verb) triples
+fn expand(role: &ClusterRole) -> Vec<(String, String, String)> {
+    let mut triples = vec![];
+
+    for rule in role.rules.iter().flatten() {
+        for group in rule.api_groups.iter().flatten() {
+            for resource in rule.resources.iter().flatten() {
+                for verb in &rule.verbs {
+                    triples.push((group.clone(), resource.clone(), verb.clone()));
+                }
+            }
+        }
+    }
+
+    triples
+}
+
+/// Checks if an effective rule covers the given group, resource and verb
+fn covers(rule: &ResourceRule, group: &str, resource: &str, verb: &str) -> bool {
+    let matches = |values: Option<&Vec<String>>, value: &str| {
+        values.is_some_and(|v| v.iter().any(|v| v == value || v == "*"))
+    };
+
+    matches(rule.api_groups.as_ref(), group)
+        && matches(rule.resources.as_ref(), resource)
+        && matches(Some(&rule.verbs), verb)
+}
+
+/// Runs a SelfSubjectAccessReview for a single group, resource and verb
+async fn access_allowed(
+    client: &Client,
+    namespace: &str,
+    group: &str,
+    resource: &str,
+    verb: &str,
+) -> Result<bool> {
+    let api: Api<SelfSubjectAccessReview> = Api::all(client.clone());
+    let review = SelfSubjectAccessReview {
+        spec: SelfSubjectAccessReviewSpec {
+            resource_attributes: Some(ResourceAttributes {
+                group: Some(group.to_string()),
+                resource: Some(resource.to_string()),
+                verb: Some(verb.to_string()),
+                namespace: Some(namespace.to_string()),
+                ..ResourceAttributes::default()
+            }),
+            ..SelfSubjectAccessReviewSpec::default()
+        },
+        ..SelfSubjectAccessReview::default()
+    };
+
+    let review = api
+        .create(&PostParams::default(), &review)
+        .await
+        .map_err(Error::api("create", "SelfSubjectAccessReview", resource))?;
+
+    Ok(review.status.is_some_and(|s| s.allowed))
+}
+
+/// Summarizes an effective rule as a single line
+fn summarize(rule: &ResourceRule) -> String {
+    let join = |values: Option<&Vec<String>>| match values {
+        Some(values) if !values.is_empty() => values
+            .iter()
+            .map(|v| if v.is_empty() { "core" } else { v.as_str() })
+            .collect::<Vec<_>>()
+            .join(","),
+        _ => "*".to_string(),
+    };
+
+    let mut summary = format!(
+        "{} {} (apiGroups: {})",
+        rule.verbs.join(","),
+        join(rule.resources.as_ref()),
+        join(rule.api_groups.as_ref())
+    );
+
+    if let Some(names) = rule.resource_names.as_ref().filter(|n| !n.is_empty()) {
+        summary.push_str(&format!(" [{}]", names.join(",")));
+    }
+
+    summary
+}
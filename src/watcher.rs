@@ -1,81 +1,131 @@
-use crate::crd::RequestStatus;
+use crate::crd::{CredentialMode, OutputFormat, RequestStatus, APPROVAL_REQUIRED};
+use crate::notify::{notify, Notification};
+use crate::resources::role::{self, Role};
 use crate::resources::{rolebinding, serviceaccount, token};
+use crate::traits::api::ApiResource;
 use crate::traits::{expire::Expire, meta::Meta};
-use crate::{crd::Request, kubeconfig::Kubeconfig, CONFIG};
-use anyhow::Result;
-use futures::TryStreamExt;
-use kube::api::ListParams;
+use crate::transaction::{retry, Step, Transaction};
+use crate::{clusters, context::Context, crd::Request, kubeconfig::Kubeconfig};
+use crate::{error::Error, error::Result, shutdown, validation};
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::{Secret, ServiceAccount};
+use kube::api::{ListParams, Patch, PatchParams};
+use kube::runtime::events::{Event, EventType};
 use kube::runtime::watcher::Event::*;
-use kube::{api::Api, runtime::watcher, ResourceExt};
+use kube::{api::Api, runtime::watcher, Resource, ResourceExt};
+use serde_json::json;
+use std::time::Duration;
+use tokio::select;
+
+/// Delay before a Request waiting for its token is looked at again
+const REQUEUE_DELAY: Duration = Duration::from_secs(30);
+
+/// Annotation users set (to any new value) to retry a failed Request
+const RETRY_ANNOTATION: &str = "kufefe.io/retry";
+
+/// Annotation users set to a unix timestamp to postpone the expiry of a ready Request
+pub const EXPIRES_AT_ANNOTATION: &str = "kufefe.io/expires-at";
+
+/// What to do with a Request once it is requeued
+enum Requeue {
+    /// Provision the Request again from scratch
+    Retry,
+    /// Continue with the resources that already exist
+    Resume,
+}
 
 /// Starts the controller which watches for CRD Creation/Modification
-pub async fn watch() {
+pub async fn watch(ctx: Context) {
     tracing::info!(
         "Starting watcher for resource creation/deletion in Kubernetes namespace {}",
-        CONFIG.get().unwrap().namespace()
+        ctx.namespace()
     );
 
-    let client = CONFIG.get().unwrap().client();
-    let api: Api<Request> = Api::all(client);
+    let api = Request::api(&ctx);
 
     // Do an inital scan for previously created & unready CRD's
     if let Ok(list) = api.list(&ListParams::default()).await {
         for item in list {
+            if item.metadata.deletion_timestamp.is_some() {
+                finalize(&ctx, item).await;
+                continue;
+            }
+
+            if let Some(expires_at) = extension_requested(&item) {
+                extend(&ctx, item, expires_at).await;
+                continue;
+            }
+
             if let Some(status) = &item.status {
-                if status.ready {
+                if status.ready || (status.failed && !retry_requested(&item)) {
                     continue;
                 }
-            }
-
-            tracing::info!("Processing Addition: {}", item.name_any());
 
-            if let Err(e) = added(item).await {
-                tracing::error!("{}", e);
+                // Retries that were scheduled before the restart keep their backoff
+                if let Some(retry_at) = status.retry_at.filter(|_| !status.failed) {
+                    let delay = (retry_at - chrono::Utc::now().timestamp()).max(0);
+                    requeue(
+                        ctx.clone(),
+                        item,
+                        Duration::from_secs(delay as u64),
+                        Requeue::Retry,
+                    );
+                    continue;
+                }
             }
+
+            process(&ctx, item).await;
         }
     }
 
-    // Start the watcher
-    tokio::spawn(async move {
-        loop {
+    // Start the watcher, which stops accepting events on shutdown
+    shutdown::spawn(async move {
+        while !shutdown::is_triggered() {
             let api: Api<Request> = api.clone();
-            _watch(api).await;
+            _watch(&ctx, api).await;
         }
     });
 }
 
 /// Start the watcher for CRD Creation/Deletion
-async fn _watch(api: Api<Request>) {
+async fn _watch(ctx: &Context, api: Api<Request>) {
     tracing::info!("Watching for CRD Creation/Deletion");
 
+    // Events being handled are finished, but no new ones are taken on shutdown
     if let Err(e) = watcher(api.clone(), watcher::Config::default())
+        .take_until(shutdown::triggered())
         .try_for_each(|r| {
             tracing::debug!("Event: {:?}", r);
 
             async move {
                 match r {
-                    Applied(mut a) => {
-                        if a.status.is_none() {
-                            tracing::info!("Processing Addition: {}", a.name_any());
-
-                            if let Err(e) = added(a.clone()).await {
-                                let status = RequestStatus::new(&a).await;
-                                a.status = Some(status);
-
-                                a.message(e.to_string())
-                                    .failed(true)
-                                    .update_status()
-                                    .await
-                                    .ok();
-
-                                tracing::error!("{}", e);
-                            }
+                    Applied(a) if a.metadata.deletion_timestamp.is_some() => {
+                        finalize(ctx, a).await;
+                        Ok(())
+                    }
+                    Applied(a) => {
+                        let failed = a.status.as_ref().is_some_and(|s| s.failed);
+
+                        if a.status.is_none()
+                            || (failed && retry_requested(&a))
+                            || a.approval_granted()
+                        {
+                            process(ctx, a).await;
+                        } else if let Some(expires_at) = extension_requested(&a) {
+                            extend(ctx, a, expires_at).await;
                         }
 
                         Ok(())
                     }
                     Deleted(d) => {
                         tracing::debug!("Resource deleted: {}", d.name_any());
+
+                        // Expired Requests are reported by whoever deleted them
+                        let ready = d.status.as_ref().is_some_and(|s| s.ready);
+                        if ready && !Request::mock().is_expired(&d) {
+                            notify(ctx, Notification::Revoked, &d);
+                        }
+
                         Ok(())
                     }
                     _ => Ok(()),
@@ -88,54 +138,630 @@ async fn _watch(api: Api<Request>) {
     };
 }
 
-/// Handle new resource creation
-async fn added(mut resource: Request) -> Result<()> {
-    let sa = serviceaccount::ServiceAccount::new();
-    let rb = rolebinding::RoleBinding::new();
-    let tk = token::Token::new();
+/// Provisions a Request, handling any failure
+pub async fn process(ctx: &Context, resource: Request) {
+    tracing::info!(
+        "Processing Addition: {} (config generation {})",
+        resource.name_any(),
+        ctx.generation()
+    );
+    let _in_flight = shutdown::in_flight(&resource.name_any());
+    let mut tx = Transaction::default();
 
-    // Generate the object name, expiry time and update CRD status
-    let expire_at = resource.generate_expiry();
-    let sa_name = sa.generate_name().await;
-    let rb_name = rb.generate_name().await;
-    let tk_name = tk.generate_name().await;
+    // Requests for a target cluster are provisioned there, but tracked here
+    let ctx = match clusters::context(ctx, &resource).await {
+        Ok(target) => target,
+        Err(e) => return handle_error(ctx, resource, e, tx).await,
+    };
+
+    // Provisioning is abandoned once the shutdown deadline passes, and rolled back
+    let result = select! {
+        result = added(&ctx, resource.clone(), &mut tx) => result,
+        _ = shutdown::aborted() => Err(Error::Interrupted),
+    };
+
+    if let Err(e) = result {
+        handle_error(&ctx, resource, e, tx).await;
+    }
+}
+
+/// Cleans up after a deleted Request for a target cluster
+async fn finalize(ctx: &Context, resource: Request) {
+    let _in_flight = shutdown::in_flight(&resource.name_any());
+
+    if let Err(e) = clusters::finalize(ctx, resource.clone()).await {
+        tracing::error!("Failed to clean up {}: {}", resource.name_any(), e);
+    }
+}
+
+/// Checks if the user asked for a retry through the retry annotation
+fn retry_requested(resource: &Request) -> bool {
+    let requested = resource.annotations().get(RETRY_ANNOTATION);
+    let handled = resource
+        .status
+        .as_ref()
+        .and_then(|s| s.retry_annotation.as_ref());
+
+    requested.is_some() && requested != handled
+}
+
+/// Gets the later expiry the user asked for on a ready Request, if any
+fn extension_requested(resource: &Request) -> Option<i64> {
+    let status = resource.status.as_ref().filter(|s| s.ready)?;
+    let requested = resource
+        .annotations()
+        .get(EXPIRES_AT_ANNOTATION)?
+        .parse::<i64>()
+        .ok()?;
+
+    (Some(requested) > status.expires_at).then_some(requested)
+}
+
+/// Postpones the expiry of a ready Request, within the maximum duration allowed
+pub async fn extend(ctx: &Context, mut resource: Request, expires_at: i64) {
+    let _in_flight = shutdown::in_flight(&resource.name_any());
+    let created = resource
+        .creation_timestamp()
+        .map_or_else(|| chrono::Utc::now().timestamp(), |t| t.0.timestamp());
+    let minutes = expires_at.saturating_sub(created).max(0) as u64 / 60;
+    let settings = ctx.settings();
+
+    match settings.duration_limit().as_secs() / 60 {
+        max if minutes > max => {
+            let e = Error::DurationNotAllowed { minutes, max };
+            tracing::warn!("Not extending {}: {}", resource.name_any(), e);
+            publish(
+                ctx,
+                &resource,
+                EventType::Warning,
+                "ExtensionRejected",
+                Some(e.to_string()),
+                "Extending",
+            )
+            .await;
+        }
+        _ => {
+            tracing::info!("Extending {} until {}", resource.name_any(), expires_at);
+            let updated = resource
+                .expires_at(expires_at)
+                .message("Expiry was postponed".to_string())
+                .update_status(ctx)
+                .await;
+
+            match updated {
+                Ok(_) => {
+                    notify(ctx, Notification::Extended, &resource);
+                    let note = format!("Expiry postponed to {}", expires_at);
+                    publish(
+                        ctx,
+                        &resource,
+                        EventType::Normal,
+                        "Extended",
+                        Some(note),
+                        "Extending",
+                    )
+                    .await
+                }
+                Err(e) => {
+                    return tracing::error!(
+                        "Failed to extend {}: {}",
+                        resource.name_any(),
+                        e
+                    )
+                }
+            }
+        }
+    }
+
+    // The annotation is consumed, so a rejected extension isn't reported again
+    let patch = json!({ "metadata": { "annotations": { EXPIRES_AT_ANNOTATION: null } } });
+    if let Err(e) = Request::api(ctx)
+        .patch(
+            &resource.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+    {
+        tracing::warn!(
+            "Failed to clear {} on {}: {}",
+            EXPIRES_AT_ANNOTATION,
+            resource.name_any(),
+            e
+        );
+    }
+}
+
+/// Handle new resource creation, recording each created resource in the transaction
+pub async fn added(
+    ctx: &Context,
+    mut resource: Request,
+    tx: &mut Transaction,
+) -> Result<()> {
+    let sa = serviceaccount::ServiceAccount::new(ctx);
+    let tk = token::Token::new(ctx);
+    let rb = rolebinding::RoleBinding::new(ctx);
+
+    check_credential_mode(ctx, &resource)?;
+
+    // Derive the object names and expiry time, keeping any expiry already set
+    let expire_at = expiry(ctx, &resource)?;
+    let (sa_name, token_name, rb_name) = names(ctx, &resource);
+    let notified = resource.awaiting_approval();
+
+    // Acting on an approval replaces the condition, so it is only acted upon once
+    if let Some(approver) = resource
+        .status
+        .as_ref()
+        .and_then(|s| s.approved_by.clone())
+        .filter(|_| resource.approval_granted())
+    {
+        resource.condition(
+            "Ready",
+            false,
+            "Approved",
+            format!("Approved by {}", approver),
+        );
+    }
+
+    // A retry requested by the user starts counting attempts from scratch
+    let attempts = match &resource.status {
+        Some(status) if !retry_requested(&resource) => {
+            status.attempts.unwrap_or_default()
+        }
+        _ => 0,
+    };
+    let retry_annotation = resource.annotations().get(RETRY_ANNOTATION).cloned();
 
     // Set status
     resource
-        .account_names(sa_name.clone(), tk_name.clone(), rb_name.clone())
+        .account_names(sa_name.clone(), token_name.clone(), rb_name.clone())
+        .attempts(Some(attempts + 1), None)
+        .retry_annotation(retry_annotation)
         .expires_at(expire_at)
         .ready(false)
         .failed(false)
         .message("Generated names for resources".to_string())
-        .update_status()
+        .update_status(ctx)
         .await?;
 
+    // Check that the role may be used before creating anything
+    let role = resource.spec.role.clone();
+    let role_api = Role::new(ctx);
+    let cluster_role = retry(|| role_api.get(&role)).await?;
+
+    if role::requires_approval(&cluster_role) && !resource.approved() {
+        return hold(ctx, resource, notified).await;
+    }
+
+    // Resources in a target cluster are cleaned up through a finalizer
+    retry(|| clusters::add_finalizer(ctx, &resource)).await?;
+
     // Create the Service Account
-    let service_account = sa.create(sa_name.clone(), &resource).await?;
+    let service_account = retry(|| sa.create(sa_name.clone(), &resource)).await?;
+    tx.record(Step::ServiceAccount(sa_name));
 
     // Create the SA Token
-    let token = token::Token::new()
-        .create(tk_name.clone(), &service_account)
-        .await?;
+    let token = retry(|| tk.create(token_name.clone(), &service_account)).await?;
+    tx.record(Step::Token(token_name));
 
     // Create the RoleBinding
-    rb.create(
-        rb_name.clone(),
-        resource.spec.role.clone(),
-        &service_account,
-        &resource,
+    retry(|| rb.create(rb_name.clone(), role.clone(), &service_account, &resource))
+        .await?;
+    tx.record(Step::RoleBinding(rb_name));
+
+    complete(ctx, resource, service_account, token).await
+}
+
+/// Holds a Request back until someone approves it, telling the webhooks about it once
+async fn hold(ctx: &Context, mut resource: Request, notified: bool) -> Result<()> {
+    let message = format!(
+        "Role {} requires approval, waiting for an approver",
+        resource.spec.role
+    );
+
+    resource
+        .condition("Ready", false, APPROVAL_REQUIRED, message.clone())
+        .message(message.clone())
+        .update_status(ctx)
+        .await?;
+
+    ctx.metrics().reconciled("approval");
+    if !notified {
+        notify(ctx, Notification::ApprovalNeeded, &resource);
+        publish(
+            ctx,
+            &resource,
+            EventType::Normal,
+            APPROVAL_REQUIRED,
+            Some(message),
+            "Provisioning",
+        )
+        .await;
+    }
+
+    Ok(())
+}
+
+/// Checks the exec credential mode can be used for the Request, before anything
+/// is created for it
+pub fn check_credential_mode(ctx: &Context, resource: &Request) -> Result<()> {
+    if resource.spec.credential_mode.unwrap_or_default() != CredentialMode::Exec {
+        return Ok(());
+    }
+
+    // Tokens of the exec plugin are minted by the self-service API, in this cluster
+    if resource.spec.cluster.is_some() {
+        return Err(Error::Unsupported {
+            reason: "the exec credential mode can't be used with a target cluster"
+                .to_string(),
+        });
+    }
+
+    // Without a static token, there is nothing to render these formats with
+    let format = resource
+        .spec
+        .output_format
+        .unwrap_or(ctx.settings().default_output_format());
+    if matches!(format, OutputFormat::Env | OutputFormat::ExecCredential) {
+        return Err(Error::Unsupported {
+            reason: "the exec credential mode can't be used with the env or \
+                     execCredential output format"
+                .to_string(),
+        });
+    }
+
+    api_url(ctx).map(|_| ())
+}
+
+/// Gets the URL of the self-service API, which kubeconfigs of the exec credential
+/// mode fetch their tokens from
+fn api_url(ctx: &Context) -> Result<String> {
+    ctx.settings().api_url().ok_or_else(|| Error::Unsupported {
+        reason: "the exec credential mode requires apiUrl to be set".to_string(),
+    })
+}
+
+/// Gets the names of the ServiceAccount, token and ClusterRoleBinding, keeping
+/// those an earlier attempt assigned as the status doesn't allow changing them
+fn names(ctx: &Context, resource: &Request) -> (String, String, String) {
+    let generated = serviceaccount::ServiceAccount::generate_name(ctx, resource);
+    let assigned = |name: Option<&String>| {
+        name.filter(|n| !n.is_empty())
+            .cloned()
+            .unwrap_or_else(|| generated.clone())
+    };
+    let status = resource.status.as_ref();
+
+    (
+        assigned(status.map(|s| &s.service_account_name)),
+        assigned(status.map(|s| &s.token_name)),
+        assigned(status.map(|s| &s.rolebinding_name)),
     )
+}
+
+/// Handle a Request whose resources have already been created
+async fn resume(ctx: &Context, resource: Request) -> Result<()> {
+    let status = match &resource.status {
+        Some(status) => status.clone(),
+        None => {
+            return Err(Error::MissingStatus {
+                request: resource.name_any(),
+            })
+        }
+    };
+
+    let service_account = retry(|| async {
+        serviceaccount::ServiceAccount::new(ctx)
+            .get_api()
+            .get(&status.service_account_name)
+            .await
+            .map_err(Error::api(
+                "get",
+                "ServiceAccount",
+                &status.service_account_name,
+            ))
+    })
+    .await?;
+
+    let token = retry(|| async {
+        token::Token::new(ctx)
+            .get_api()
+            .get(&status.token_name)
+            .await
+            .map_err(Error::api("get", "Secret", &status.token_name))
+    })
     .await?;
 
+    complete(ctx, resource, service_account, token).await
+}
+
+/// Gets the expiry of a Request, from its status or the duration it asks for.
+/// Approved Requests get their full duration from the time of approval.
+fn expiry(ctx: &Context, resource: &Request) -> Result<i64> {
+    if let Some(expires_at) = resource
+        .status
+        .as_ref()
+        .and_then(|s| s.expires_at)
+        .filter(|_| !resource.approval_granted())
+    {
+        return Ok(expires_at);
+    }
+
+    // The duration comes straight from the user, so it is bounded before any arithmetic
+    let settings = ctx.settings();
+    let limit = settings.duration_limit();
+    let minutes = resource
+        .spec
+        .duration_minutes
+        .unwrap_or(settings.expire_after().as_secs() / 60);
+    let not_allowed = || Error::DurationNotAllowed {
+        minutes,
+        max: limit.as_secs() / 60,
+    };
+
+    let after = minutes
+        .checked_mul(60)
+        .map(Duration::from_secs)
+        .filter(|after| *after <= limit)
+        .ok_or_else(not_allowed)?;
+
+    resource.generate_expiry(after).ok_or_else(not_allowed)
+}
+
+/// Generate the kubeconfig and mark the Request as ready
+async fn complete(
+    ctx: &Context,
+    mut resource: Request,
+    service_account: ServiceAccount,
+    token: Secret,
+) -> Result<()> {
+    let expire_at = expiry(ctx, &resource)?;
+
     // Create the Kubeconfig and update the CRD Status
-    let kubeconfig = Kubeconfig::new(service_account, token).await?.to_yaml()?;
+    let mut kubeconfig = Kubeconfig::new(ctx, service_account, token).await?;
+
+    if resource.spec.credential_mode.unwrap_or_default() == CredentialMode::Exec {
+        kubeconfig.exec(&resource.name_any(), &api_url(ctx)?);
+    }
+
+    // Verify that the kubeconfig works before handing it out
+    if ctx.settings().validate() {
+        let role = Role::new(ctx).get(&resource.spec.role).await?;
+        let rules = validation::validate(ctx, &kubeconfig, &role).await?;
+
+        resource.effective_rules(rules);
+    }
+
+    // Render the requested output format, the YAML kubeconfig is always kept
+    let format = resource
+        .spec
+        .output_format
+        .unwrap_or(ctx.settings().default_output_format());
+    let output = match format {
+        OutputFormat::Yaml => None,
+        format => Some(kubeconfig.render(format, Some(expire_at))?),
+    };
 
     resource
         .ready(true)
-        .kubeconfig(&kubeconfig)
+        .kubeconfig(&kubeconfig.to_yaml()?)
+        .output(output)
+        .cluster(Some(ctx.url()), Some(ctx.url_provider()))
         .message("Completed".to_string())
-        .update_status()
+        .condition("Ready", true, "Provisioned", "Completed".to_string())
+        .update_status(ctx)
         .await?;
 
+    ctx.metrics().reconciled("ready");
+    notify(ctx, Notification::Issued, &resource);
+    publish(
+        ctx,
+        &resource,
+        EventType::Normal,
+        "Provisioned",
+        None,
+        "Provisioning",
+    )
+    .await;
+
     Ok(())
 }
+
+/// Requeues the Request if the token isn't ready yet, otherwise rolls back
+/// the resources created so far and marks the Request as failed
+async fn handle_error(ctx: &Context, mut resource: Request, e: Error, tx: Transaction) {
+    let status = RequestStatus::new(ctx, &resource).await;
+    resource.status = Some(status);
+    resource.condition("Ready", false, e.reason(), e.to_string());
+    ctx.metrics().error(&e);
+
+    let config = ctx.settings();
+    let status = resource.status.clone().unwrap_or_default();
+    let attempts = status.attempts.unwrap_or(1);
+
+    // Waiting for the token counts as an attempt, so a stuck token controller
+    // eventually fails the Request
+    if let Error::TokenNotReady { .. } = e {
+        if attempts < config.retry_max_attempts() {
+            tracing::warn!("{}, requeueing {}", e, resource.name_any());
+            ctx.metrics().reconciled("requeue");
+
+            resource
+                .attempts(Some(attempts + 1), None)
+                .message(format!(
+                    "{}, requeued (attempt {}/{})",
+                    e,
+                    attempts,
+                    config.retry_max_attempts()
+                ))
+                .update_status(ctx)
+                .await
+                .ok();
+
+            requeue(ctx.clone(), resource, REQUEUE_DELAY, Requeue::Resume);
+            return;
+        }
+    }
+
+    // A create cut short may have gone through unrecorded, so everything named in the
+    // status is rolled back. The restart scan provisions the Request again, which
+    // isn't counted as an attempt.
+    if let Error::Interrupted = e {
+        if status.service_account_name.is_empty() {
+            tx.rollback(ctx).await;
+        } else {
+            Transaction::from_status(&status).rollback(ctx).await;
+        }
+
+        tracing::warn!(
+            "Rolled back {} interrupted by shutdown",
+            resource.name_any()
+        );
+        ctx.metrics().reconciled("interrupted");
+
+        resource
+            .attempts(Some(attempts.saturating_sub(1)), None)
+            .message(
+                "Interrupted by shutdown and rolled back, will be retried on restart"
+                    .to_string(),
+            )
+            .update_status(ctx)
+            .await
+            .ok();
+
+        return;
+    }
+
+    tx.rollback(ctx).await;
+
+    // Transient errors are retried with backoff until attempts run out
+    if e.is_retryable() && attempts < config.retry_max_attempts() {
+        let delay = config.retry_backoff() * 2u32.pow(attempts.saturating_sub(1).min(6));
+        let retry_at = chrono::Utc::now().timestamp() + delay.as_secs() as i64;
+
+        tracing::warn!(
+            "{}, retrying {} in {}s",
+            e,
+            resource.name_any(),
+            delay.as_secs()
+        );
+
+        resource
+            .attempts(Some(attempts), Some(retry_at))
+            .last_error(Some(e.to_string()))
+            .message(format!(
+                "Attempt {}/{} failed, retrying in {}s",
+                attempts,
+                config.retry_max_attempts(),
+                delay.as_secs()
+            ))
+            .update_status(ctx)
+            .await
+            .ok();
+
+        ctx.metrics().reconciled("retry");
+        requeue(ctx.clone(), resource, delay, Requeue::Retry);
+        return;
+    }
+
+    ctx.metrics().reconciled("failed");
+    publish(
+        ctx,
+        &resource,
+        EventType::Warning,
+        e.reason(),
+        Some(e.to_string()),
+        "Provisioning",
+    )
+    .await;
+
+    resource
+        .last_error(Some(e.to_string()))
+        .message(format!(
+            "{}. Created resources were rolled back. Set the annotation {} to retry",
+            e, RETRY_ANNOTATION
+        ))
+        .failed(true)
+        .update_status(ctx)
+        .await
+        .ok();
+
+    tracing::error!("{}", e);
+}
+
+/// Looks at the Request again after a delay without blocking the event stream
+fn requeue(ctx: Context, resource: Request, delay: Duration, action: Requeue) {
+    shutdown::spawn(async move {
+        // Requeued Requests are picked up again by the initial scan on restart
+        select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown::triggered() => return,
+        }
+
+        // The Request may have been deleted or changed in the meantime
+        let resource = match Request::api(&ctx).get(&resource.name_any()).await {
+            Ok(resource) => resource,
+            Err(e) => {
+                tracing::info!("Dropping requeue of {}: {}", resource.name_any(), e);
+                return;
+            }
+        };
+
+        if resource
+            .status
+            .as_ref()
+            .is_some_and(|s| s.ready || s.failed)
+        {
+            return;
+        }
+
+        match action {
+            Requeue::Retry => process(&ctx, resource).await,
+            Requeue::Resume => process_requeue(&ctx, resource).await,
+        }
+    });
+}
+
+/// Resumes a Request that was requeued while waiting for its token
+pub async fn process_requeue(ctx: &Context, resource: Request) {
+    tracing::info!("Processing Requeue: {}", resource.name_any());
+    let _in_flight = shutdown::in_flight(&resource.name_any());
+
+    let result = select! {
+        result = resume(ctx, resource.clone()) => result,
+        _ = shutdown::aborted() => Err(Error::Interrupted),
+    };
+
+    if let Err(e) = result {
+        let tx = resource
+            .status
+            .as_ref()
+            .map(Transaction::from_status)
+            .unwrap_or_default();
+
+        handle_error(ctx, resource, e, tx).await;
+    }
+}
+
+/// Publishes a Kubernetes event on the Request
+async fn publish(
+    ctx: &Context,
+    resource: &Request,
+    type_: EventType,
+    reason: &str,
+    note: Option<String>,
+    action: &str,
+) {
+    let event = Event {
+        type_,
+        reason: reason.to_string(),
+        note,
+        action: action.to_string(),
+        secondary: None,
+    };
+
+    if let Err(e) = ctx.recorder(resource.object_ref(&())).publish(event).await {
+        tracing::warn!("Failed to publish event for {}: {}", resource.name_any(), e);
+    }
+}